@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::os::fd::OwnedFd;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use ipc_protocol::ipc_payload::BrokerOp;
+use nix::fcntl::{AtFlags, OFlag, open, openat};
+use nix::sys::stat::{Mode, SFlag, fstatat};
+use nix::unistd::{UnlinkatFlags, unlinkat};
+use plugin_manager::{Broker, BrokerCaller};
+use serde::Serialize;
+
+use crate::config::{BrokerConfig, BrokerPolicy};
+use crate::quarantine::Vault;
+
+/// Result of a `delete_files` operation, sent back to the plugin as JSON.
+#[derive(Debug, Default, Serialize)]
+struct DeleteReport {
+    deleted: u64,
+    bytes_freed: u64,
+    failed: Vec<DeleteFailure>,
+}
+
+#[derive(Debug, Serialize)]
+struct DeleteFailure {
+    path: String,
+    error: String,
+}
+
+/// Performs file operations for plugins, restricted to the roots each one is granted in the config.
+pub struct FileBroker {
+    /// Canonical roots files may be deleted under, keyed by plugin library file name.
+    delete_roots: HashMap<String, Vec<PathBuf>>,
+    /// Canonical roots files may be quarantined from, keyed by plugin library file name.
+    quarantine_roots: HashMap<String, Vec<PathBuf>>,
+    /// `None` when the vault could not be opened at startup.
+    vault: Option<Arc<Mutex<Vault>>>,
+}
+
+impl FileBroker {
+    pub fn new(config: &BrokerConfig, vault: Option<Arc<Mutex<Vault>>>) -> Self {
+        Self {
+            delete_roots: canonical_roots(config, |policy| &policy.delete_roots),
+            quarantine_roots: canonical_roots(config, |policy| &policy.quarantine_roots),
+            vault,
+        }
+    }
+
+    fn delete_files(&self, caller: &BrokerCaller, paths: &[String]) -> Result<String, String> {
+        let roots = self
+            .delete_roots
+            .get(&caller.library)
+            .filter(|r| !r.is_empty())
+            .ok_or_else(|| format!("{} may not delete files", caller.library))?;
+
+        let mut report = DeleteReport::default();
+        for path in paths {
+            match delete_under(Path::new(path), roots) {
+                Ok(size) => {
+                    report.deleted += 1;
+                    report.bytes_freed += size;
+                }
+                Err(e) => report.failed.push(DeleteFailure {
+                    path: path.clone(),
+                    error: e.to_string(),
+                }),
+            }
+        }
+
+        println!(
+            "[BROKER](INFO) {} ({}) deleted {} files, {} refused or failed",
+            caller.name,
+            caller.pid,
+            report.deleted,
+            report.failed.len()
+        );
+        serde_json::to_string(&report).map_err(|e| e.to_string())
+    }
+
+    fn quarantine_file(
+        &self,
+        caller: &BrokerCaller,
+        path: &str,
+        detection: &str,
+    ) -> Result<String, String> {
+        let roots = self
+            .quarantine_roots
+            .get(&caller.library)
+            .filter(|r| !r.is_empty())
+            .ok_or_else(|| format!("{} may not quarantine files", caller.library))?;
+        let vault = self.vault.as_ref().ok_or("quarantine vault unavailable")?;
+
+        let entry = open_parent_under(Path::new(path), roots)
+            .and_then(|(dir, file_name)| {
+                vault.lock().unwrap().quarantine_at(
+                    &dir,
+                    file_name,
+                    Path::new(path),
+                    detection,
+                    &caller.name,
+                )
+            })
+            .map_err(|e| format!("cannot quarantine {path}: {e}"))?;
+
+        println!(
+            "[BROKER](INFO) {} ({}) quarantined {} as {} ({detection})",
+            caller.name, caller.pid, path, entry.id
+        );
+        serde_json::to_string(&entry).map_err(|e| e.to_string())
+    }
+}
+
+impl Broker for FileBroker {
+    fn handle(&self, caller: &BrokerCaller, op: BrokerOp) -> Result<String, String> {
+        match op {
+            BrokerOp::DeleteFiles { paths } => self.delete_files(caller, &paths),
+            BrokerOp::QuarantineFile { path, detection } => {
+                self.quarantine_file(caller, &path, &detection)
+            }
+        }
+    }
+}
+
+/// Canonicalizes the roots `roots_of` picks from each plugin policy.
+/// Roots that do not exist are left out with a warning.
+fn canonical_roots(
+    config: &BrokerConfig,
+    roots_of: impl Fn(&BrokerPolicy) -> &Vec<PathBuf>,
+) -> HashMap<String, Vec<PathBuf>> {
+    config
+        .plugins
+        .iter()
+        .map(|(library, policy)| {
+            let roots = roots_of(policy)
+                .iter()
+                .filter_map(|root| match fs::canonicalize(root) {
+                    Ok(root) => Some(root),
+                    Err(e) => {
+                        println!(
+                            "[BROKER](WARN) Ignoring root {} for {library}: {e}",
+                            root.display()
+                        );
+                        None
+                    }
+                })
+                .collect();
+            (library.clone(), roots)
+        })
+        .collect()
+}
+
+/// Opens the directory holding `path`, which must lie strictly inside one of `roots`,
+/// and returns it with the file name to act on relative to it.
+///
+/// The path is walked down from the root one directory fd at a time without
+/// following symlinks, so a directory swapped for a symlink midway cannot lead
+/// out of the allowed root.
+fn open_parent_under<'p>(path: &'p Path, roots: &[PathBuf]) -> io::Result<(OwnedFd, &'p OsStr)> {
+    let denied = |msg: &str| io::Error::new(io::ErrorKind::PermissionDenied, msg.to_string());
+
+    if !path.is_absolute() {
+        return Err(denied("path must be absolute"));
+    }
+    if path
+        .components()
+        .any(|c| !matches!(c, Component::RootDir | Component::Normal(_)))
+    {
+        return Err(denied("path must not contain . or .."));
+    }
+    let (root, relative) = roots
+        .iter()
+        .find_map(|root| Some((root, path.strip_prefix(root).ok()?)))
+        .filter(|(_, relative)| !relative.as_os_str().is_empty())
+        .ok_or_else(|| denied("outside the allowed roots"))?;
+    let (Some(parent), Some(file_name)) = (relative.parent(), path.file_name()) else {
+        return Err(denied("invalid path"));
+    };
+
+    let walk = OFlag::O_DIRECTORY | OFlag::O_NOFOLLOW | OFlag::O_CLOEXEC;
+    let mut dir = open(root.as_path(), walk, Mode::empty())?;
+    for component in parent.components() {
+        dir = openat(&dir, component.as_os_str(), walk, Mode::empty())?;
+    }
+    Ok((dir, file_name))
+}
+
+/// Removes `path` if it is a file or symlink strictly inside one of `roots`.
+/// A symlink is removed as a link, never followed.
+fn delete_under(path: &Path, roots: &[PathBuf]) -> io::Result<u64> {
+    let (dir, file_name) = open_parent_under(path, roots)?;
+
+    let stat = fstatat(&dir, file_name, AtFlags::AT_SYMLINK_NOFOLLOW)?;
+    if SFlag::from_bits_truncate(stat.st_mode) & SFlag::S_IFMT == SFlag::S_IFDIR {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "directories are not deleted",
+        ));
+    }
+
+    // Refuses a directory put in place since the check.
+    unlinkat(&dir, file_name, UnlinkatFlags::NoRemoveDir)?;
+    Ok(stat.st_size as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn delete_stays_under_the_root() {
+        let base = std::env::temp_dir().join(format!("griffon-broker-{}", std::process::id()));
+        let root = base.join("root");
+        let outside = base.join("outside");
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(root.join("dir/file"), b"1234").unwrap();
+        fs::write(outside.join("file"), b"kept").unwrap();
+        symlink(&outside, root.join("link")).unwrap();
+        let roots = [fs::canonicalize(&root).unwrap()];
+        let root = &roots[0];
+
+        assert_eq!(delete_under(&root.join("dir/file"), &roots).unwrap(), 4);
+        assert!(delete_under(&root.join("link/file"), &roots).is_err());
+        assert!(delete_under(&root.join("dir/../link/file"), &roots).is_err());
+        assert!(delete_under(&root.join("dir"), &roots).is_err());
+        assert!(delete_under(root, &roots).is_err());
+        assert!(delete_under(&outside.join("file"), &roots).is_err());
+        // The link itself is removed, not what it points to.
+        delete_under(&root.join("link"), &roots).unwrap();
+        assert!(outside.join("file").exists());
+
+        fs::remove_dir_all(base).unwrap();
+    }
+}
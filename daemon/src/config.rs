@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use plugin_manager::{LimitPolicy, ResourceLimits};
+use serde::Deserialize;
+
+/// Looked up in the working directory when `GRIFFON_CONFIG` is not set.
+static DEFAULT_CONFIG_PATH: &str = "./griffon.toml";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DaemonConfig {
+    pub plugins_dir: PathBuf,
+    pub data_dir: PathBuf,
+    /// User runners are started as unless their manifest sets `run_as`.
+    /// Only honoured when the daemon runs as root.
+    pub plugin_user: Option<String>,
+    /// Seconds a plugin gets to stop after a shutdown request before it is signalled.
+    pub shutdown_grace_secs: u64,
+    pub history: HistoryConfig,
+    pub limits: LimitsConfig,
+    pub broker: BrokerConfig,
+    pub updates: UpdatesConfig,
+    #[serde(rename = "job")]
+    pub jobs: Vec<JobConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// Keep at most this many entries, oldest are dropped first. 0 means unlimited.
+    pub max_entries: usize,
+    /// Drop entries that started more than this many days ago. 0 means unlimited.
+    pub max_age_days: u32,
+    /// Result payloads larger than this are stored truncated.
+    pub max_result_bytes: usize,
+}
+
+/// Resource limits for plugin runners. Top-level keys apply to every plugin,
+/// `[limits.plugins."<library file name>"]` tables override them.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    #[serde(flatten)]
+    pub default: PluginLimits,
+    pub plugins: HashMap<String, PluginLimits>,
+    /// Delegated cgroup v2 directory, defaults to `/sys/fs/cgroup/griffon`.
+    pub cgroup_root: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct PluginLimits {
+    /// Percent of one core, 200 allows two full cores.
+    pub cpu_percent: Option<u32>,
+    pub memory_mb: Option<u64>,
+    pub max_open_files: Option<u64>,
+}
+
+impl PluginLimits {
+    fn to_limits(self) -> ResourceLimits {
+        ResourceLimits {
+            cpu_percent: self.cpu_percent,
+            memory_bytes: self.memory_mb.map(|mb| mb * 1024 * 1024),
+            max_open_files: self.max_open_files,
+        }
+    }
+}
+
+impl LimitsConfig {
+    /// A zero CPU cap would stop the runner rather than limit it.
+    fn validate(&self) -> Result<(), String> {
+        let zero_cpu = |limits: &PluginLimits| limits.cpu_percent == Some(0);
+        if zero_cpu(&self.default) {
+            return Err("limits.cpu_percent must be at least 1".to_string());
+        }
+        if let Some(name) = self
+            .plugins
+            .iter()
+            .find_map(|(n, l)| zero_cpu(l).then_some(n))
+        {
+            return Err(format!(
+                "limits.plugins.\"{name}\".cpu_percent must be at least 1"
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn to_policy(&self) -> LimitPolicy {
+        let mut policy = LimitPolicy {
+            default: self.default.to_limits(),
+            per_plugin: self
+                .plugins
+                .iter()
+                .map(|(name, limits)| (name.clone(), limits.to_limits()))
+                .collect(),
+            ..LimitPolicy::default()
+        };
+        if let Some(root) = &self.cgroup_root {
+            policy.cgroup_root = root.clone();
+        }
+        policy
+    }
+}
+
+/// Privileged operations granted to plugins, keyed by library file name
+/// in `[broker.plugins."<library file name>"]` tables.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BrokerConfig {
+    pub plugins: HashMap<String, BrokerPolicy>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BrokerPolicy {
+    /// Directories under which the plugin may ask the daemon to delete files.
+    pub delete_roots: Vec<PathBuf>,
+    /// Directories from which the plugin may move flagged files into the quarantine vault.
+    pub quarantine_roots: Vec<PathBuf>,
+}
+
+/// `[updates]` table: where signed rule bundles are fetched from.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct UpdatesConfig {
+    /// `http(s)://` URL or local directory of a mirror, or a local bundle file.
+    /// `file://` URLs are read as local paths.
+    pub source: Option<String>,
+    /// Base64 Ed25519 public keys. A bundle is installed only when signed by one of them.
+    pub public_keys: Vec<String>,
+    /// Previous versions kept for `rules rollback`, besides the active one.
+    pub keep_versions: usize,
+    /// Larger bundles are refused before being verified.
+    pub max_bundle_mb: u64,
+    /// Bundles whose contents add up to more are refused while unpacking.
+    pub max_unpacked_mb: u64,
+    /// Applies to each download from an HTTP mirror.
+    pub timeout_secs: u64,
+    /// Cron expression of automatic updates, same syntax as `[[job]]`. Manual only when unset.
+    pub schedule: Option<String>,
+}
+
+/// A recurring plugin call, declared as a `[[job]]` table.
+#[derive(Debug, Clone, Deserialize)]
+pub struct JobConfig {
+    pub name: String,
+    /// Cron expression, either 5 fields (`min hour dom month dow`) or with a leading seconds field.
+    pub schedule: String,
+    /// Plugin name as announced in its handshake.
+    pub plugin: String,
+    pub function: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default = "default_true")]
+    pub skip_on_battery: bool,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            plugins_dir: PathBuf::from("./plugins"),
+            data_dir: PathBuf::from("./data"),
+            plugin_user: None,
+            shutdown_grace_secs: 10,
+            history: HistoryConfig::default(),
+            limits: LimitsConfig::default(),
+            broker: BrokerConfig::default(),
+            updates: UpdatesConfig::default(),
+            jobs: Vec::new(),
+        }
+    }
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 10_000,
+            max_age_days: 90,
+            max_result_bytes: 64 * 1024,
+        }
+    }
+}
+
+impl Default for UpdatesConfig {
+    fn default() -> Self {
+        Self {
+            source: None,
+            public_keys: Vec::new(),
+            keep_versions: 3,
+            max_bundle_mb: 256,
+            max_unpacked_mb: 1024,
+            timeout_secs: 60,
+            schedule: None,
+        }
+    }
+}
+
+impl DaemonConfig {
+    /// Loads the config from `GRIFFON_CONFIG` or `./griffon.toml`.
+    /// A missing file is not an error: the defaults are used instead.
+    pub fn load() -> io::Result<Self> {
+        let path = std::env::var_os("GRIFFON_CONFIG")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH));
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::from_file(&path)
+    }
+
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let raw = fs::read_to_string(path)?;
+        let invalid = |e: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {e}", path.display()),
+            )
+        };
+        let config: Self = toml::from_str(&raw).map_err(|e| invalid(e.to_string()))?;
+        config.limits.validate().map_err(invalid)?;
+        Ok(config)
+    }
+
+    pub fn history_path(&self) -> PathBuf {
+        self.data_dir.join("history.jsonl")
+    }
+
+    pub fn quarantine_dir(&self) -> PathBuf {
+        self.data_dir.join("quarantine")
+    }
+
+    /// Installed rule bundles, the active one under `current`.
+    pub fn rules_dir(&self) -> PathBuf {
+        self.data_dir.join("rules")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(raw: &str) -> LimitsConfig {
+        toml::from_str(raw).unwrap()
+    }
+
+    #[test]
+    fn plugin_limits_inherit_unset_defaults() {
+        let config = limits(
+            r#"
+            cpu_percent = 50
+            memory_mb = 512
+            [plugins."libslow.so"]
+            cpu_percent = 20
+            "#,
+        );
+        let policy = config.to_policy();
+        let merged = policy.limits_for(Path::new("/plugins/libslow.so"));
+        assert_eq!(merged.cpu_percent, Some(20));
+        assert_eq!(merged.memory_bytes, Some(512 * 1024 * 1024));
+        assert_eq!(policy.limits_for(Path::new("libother.so")), policy.default);
+    }
+
+    #[test]
+    fn zero_cpu_cap_is_rejected() {
+        assert!(limits("cpu_percent = 0").validate().is_err());
+        assert!(
+            limits("[plugins.\"a.so\"]\ncpu_percent = 0")
+                .validate()
+                .is_err()
+        );
+        assert!(limits("cpu_percent = 1").validate().is_ok());
+    }
+}
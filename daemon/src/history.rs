@@ -0,0 +1,371 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::config::HistoryConfig;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Outcome {
+    Running,
+    Success,
+    /// The plugin answered with `ok = false`.
+    Failure,
+    /// The runner reported an error instead of a result.
+    Error {
+        code: u32,
+    },
+    /// The plugin exited or was killed before answering.
+    Lost,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: u64,
+    pub plugin: String,
+    pub pid: u32,
+    pub request_id: u32,
+    pub function: String,
+    pub args: Vec<String>,
+    /// Name of the scheduled job that issued the call, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub outcome: Outcome,
+    /// Result output or error message, cut to `max_result_bytes`.
+    pub result: Option<String>,
+    /// Size of the full payload, set only when `result` was truncated.
+    pub result_len: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    pub plugin: Option<String>,
+    pub function: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+impl HistoryFilter {
+    fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.plugin.as_ref().is_none_or(|p| *p == entry.plugin)
+            && self.function.as_ref().is_none_or(|f| *f == entry.function)
+            && self.since.is_none_or(|t| entry.started_at >= t)
+            && self.until.is_none_or(|t| entry.started_at < t)
+    }
+}
+
+/// Append-only record of every plugin call.
+///
+/// Each state change of an entry is appended as one JSON line; when the file is
+/// read back the last line for a given id wins. The file is only rewritten when
+/// retention drops entries.
+pub struct HistoryStore {
+    path: PathBuf,
+    config: HistoryConfig,
+    entries: Vec<HistoryEntry>,
+    next_id: u64,
+    writer: BufWriter<File>,
+    last_retention: DateTime<Utc>,
+}
+
+/// Retention also runs this often while the daemon is up, for `max_age_days`.
+const RETENTION_INTERVAL_HOURS: i64 = 1;
+/// `max_entries` may be exceeded by this fraction before the file is compacted,
+/// so a full history is not rewritten on every call.
+const RETENTION_SLACK_DIVISOR: usize = 10;
+
+impl HistoryStore {
+    pub fn open<P: AsRef<Path>>(path: P, config: HistoryConfig) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let entries = if path.exists() {
+            Self::read_entries(&path)?
+        } else {
+            Vec::new()
+        };
+        let next_id = entries.last().map_or(1, |e| e.id + 1);
+
+        let mut store = Self {
+            writer: Self::open_writer(&path)?,
+            path,
+            config,
+            entries,
+            next_id,
+            last_retention: Utc::now(),
+        };
+        store.apply_retention()?;
+        Ok(store)
+    }
+
+    fn open_writer(path: &Path) -> io::Result<BufWriter<File>> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(BufWriter::new(file))
+    }
+
+    fn read_entries(path: &Path) -> io::Result<Vec<HistoryEntry>> {
+        let mut entries: Vec<HistoryEntry> = Vec::new();
+
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            // A torn last line after a crash must not make the whole history unreadable.
+            let Ok(entry) = serde_json::from_str::<HistoryEntry>(&line) else {
+                continue;
+            };
+            match entries.binary_search_by_key(&entry.id, |e| e.id) {
+                Ok(pos) => entries[pos] = entry,
+                Err(pos) => entries.insert(pos, entry),
+            }
+        }
+
+        // Calls still marked running belong to a previous daemon run and will never complete.
+        for entry in entries.iter_mut().filter(|e| e.outcome == Outcome::Running) {
+            entry.outcome = Outcome::Lost;
+        }
+        Ok(entries)
+    }
+
+    fn append(&mut self, index: usize) -> io::Result<()> {
+        let line = serde_json::to_string(&self.entries[index]).map_err(io::Error::other)?;
+        writeln!(self.writer, "{line}")?;
+        self.writer.flush()
+    }
+
+    /// Records a call that was just sent and returns its history id.
+    pub fn start(
+        &mut self,
+        plugin: &str,
+        pid: u32,
+        request_id: u32,
+        function: &str,
+        args: &[String],
+        job: Option<&str>,
+    ) -> io::Result<u64> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.entries.push(HistoryEntry {
+            id,
+            plugin: plugin.to_string(),
+            pid,
+            request_id,
+            function: function.to_string(),
+            args: args.to_vec(),
+            job: job.map(str::to_string),
+            started_at: Utc::now(),
+            ended_at: None,
+            outcome: Outcome::Running,
+            result: None,
+            result_len: None,
+        });
+        self.append(self.entries.len() - 1)?;
+        Ok(id)
+    }
+
+    /// Closes the running call matching `pid`/`request_id`.
+    /// Returns `Ok(false)` when no such call is pending.
+    pub fn complete(
+        &mut self,
+        pid: u32,
+        request_id: u32,
+        outcome: Outcome,
+        payload: &str,
+    ) -> io::Result<bool> {
+        let Some(index) = self.entries.iter().rposition(|e| {
+            e.pid == pid && e.request_id == request_id && e.outcome == Outcome::Running
+        }) else {
+            return Ok(false);
+        };
+
+        let (result, result_len) = summarize(payload, self.config.max_result_bytes);
+        let entry = &mut self.entries[index];
+        entry.ended_at = Some(Utc::now());
+        entry.outcome = outcome;
+        entry.result = Some(result);
+        entry.result_len = result_len;
+
+        self.append(index)?;
+        if self.retention_due() {
+            self.apply_retention()?;
+        }
+        Ok(true)
+    }
+
+    fn retention_due(&self) -> bool {
+        let max = self.config.max_entries;
+        let over_count = max > 0 && self.entries.len() > max + max / RETENTION_SLACK_DIVISOR;
+        let over_age = self.config.max_age_days > 0
+            && Utc::now() - self.last_retention >= Duration::hours(RETENTION_INTERVAL_HOURS);
+        over_count || over_age
+    }
+
+    /// Marks every call still running on `pid` as lost.
+    pub fn abandon(&mut self, pid: u32) -> io::Result<usize> {
+        let now = Utc::now();
+        let pending: Vec<usize> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.pid == pid && e.outcome == Outcome::Running)
+            .map(|(i, _)| i)
+            .collect();
+
+        for &index in &pending {
+            self.entries[index].outcome = Outcome::Lost;
+            self.entries[index].ended_at = Some(now);
+            self.append(index)?;
+        }
+        Ok(pending.len())
+    }
+
+    pub fn get(&self, id: u64) -> Option<&HistoryEntry> {
+        self.entries
+            .binary_search_by_key(&id, |e| e.id)
+            .ok()
+            .map(|pos| &self.entries[pos])
+    }
+
+    /// Returns matching entries, most recent first.
+    pub fn query(&self, filter: &HistoryFilter) -> Vec<&HistoryEntry> {
+        self.entries
+            .iter()
+            .rev()
+            .filter(|e| filter.matches(e))
+            .take(filter.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    /// Drops entries outside the configured limits and compacts the file.
+    /// Running calls are never dropped.
+    pub fn apply_retention(&mut self) -> io::Result<usize> {
+        let before = self.entries.len();
+        self.last_retention = Utc::now();
+
+        if self.config.max_age_days > 0 {
+            let cutoff = Utc::now() - Duration::days(i64::from(self.config.max_age_days));
+            self.entries
+                .retain(|e| e.outcome == Outcome::Running || e.started_at >= cutoff);
+        }
+
+        if self.config.max_entries > 0 && self.entries.len() > self.config.max_entries {
+            let mut excess = self.entries.len() - self.config.max_entries;
+            self.entries.retain(|e| {
+                if excess > 0 && e.outcome != Outcome::Running {
+                    excess -= 1;
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+
+        let removed = before - self.entries.len();
+        if removed > 0 {
+            self.rewrite()?;
+        }
+        Ok(removed)
+    }
+
+    fn rewrite(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+
+        let tmp = self.path.with_extension("jsonl.tmp");
+        {
+            let mut out = BufWriter::new(File::create(&tmp)?);
+            for entry in &self.entries {
+                let line = serde_json::to_string(entry).map_err(io::Error::other)?;
+                writeln!(out, "{line}")?;
+            }
+            out.flush()?;
+            out.get_ref().sync_all()?;
+        }
+        fs::rename(&tmp, &self.path)?;
+
+        self.writer = Self::open_writer(&self.path)?;
+        Ok(())
+    }
+}
+
+fn summarize(payload: &str, max_bytes: usize) -> (String, Option<usize>) {
+    if payload.len() <= max_bytes {
+        return (payload.to_string(), None);
+    }
+    let mut cut = max_bytes;
+    while !payload.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    (payload[..cut].to_string(), Some(payload.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(name: &str, config: HistoryConfig) -> (HistoryStore, PathBuf) {
+        let path = std::env::temp_dir().join(format!(
+            "griffon-history-{name}-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        (HistoryStore::open(&path, config).unwrap(), path)
+    }
+
+    #[test]
+    fn retention_runs_while_the_daemon_is_up() {
+        let config = HistoryConfig {
+            max_entries: 10,
+            max_age_days: 0,
+            ..HistoryConfig::default()
+        };
+        let (mut history, path) = store("retention", config);
+        for request_id in 0..50 {
+            history.start("p", 1, request_id, "f", &[], None).unwrap();
+            history
+                .complete(1, request_id, Outcome::Success, "ok")
+                .unwrap();
+        }
+        assert!(history.entries.len() <= 11);
+        assert_eq!(history.entries.last().unwrap().request_id, 49);
+
+        // The compacted file reads back to the same entries.
+        let reread = HistoryStore::read_entries(&path).unwrap();
+        assert_eq!(reread.len(), history.entries.len());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn running_calls_survive_retention() {
+        let config = HistoryConfig {
+            max_entries: 1,
+            max_age_days: 0,
+            ..HistoryConfig::default()
+        };
+        let (mut history, path) = store("running", config);
+        history.start("p", 1, 1, "f", &[], None).unwrap();
+        for request_id in 2..6 {
+            history.start("p", 1, request_id, "f", &[], None).unwrap();
+            history
+                .complete(1, request_id, Outcome::Success, "ok")
+                .unwrap();
+        }
+        assert!(history.entries.iter().any(|e| e.request_id == 1));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn summarize_cuts_on_char_boundaries() {
+        assert_eq!(summarize("short", 10), ("short".to_string(), None));
+        assert_eq!(summarize("héllo", 2), ("h".to_string(), Some(6)));
+    }
+}
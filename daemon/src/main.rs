@@ -1,16 +1,263 @@
+mod broker;
+mod config;
+mod history;
+mod quarantine;
+mod scheduler;
+mod updater;
+
 use std::io;
 use std::io::Write;
-use plugin_manager::{PluginManager, LogLevel};
-use ipc_protocol;
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{NaiveDate, TimeZone, Utc};
+use plugin_manager::{CallOutcome, LogLevel, PluginEvent, PluginManager};
+
+use broker::FileBroker;
+use config::DaemonConfig;
+use history::{HistoryFilter, HistoryStore, Outcome};
+use quarantine::Vault;
+use scheduler::Scheduler;
+use updater::{RuleStore, UpdateOutcome};
+
+fn record_events(events: Receiver<PluginEvent>, history: Arc<Mutex<HistoryStore>>) {
+    for event in events {
+        let mut history = history.lock().unwrap();
+        let res = match event {
+            PluginEvent::CallCompleted {
+                pid,
+                request_id,
+                outcome,
+                ..
+            } => {
+                let (outcome, payload) = match outcome {
+                    CallOutcome::Result { ok: true, output } => (Outcome::Success, output),
+                    CallOutcome::Result { ok: false, output } => (Outcome::Failure, output),
+                    CallOutcome::Error { code, message } => (Outcome::Error { code }, message),
+                };
+                history.complete(pid, request_id, outcome, &payload).map(|_| ())
+            }
+            PluginEvent::Exited { pid, .. } => history.abandon(pid).map(|_| ()),
+        };
+        if let Err(e) = res {
+            println!("[CORE](ERROR) Failed to write history: {e}");
+        }
+    }
+}
 
-static PLUGIN_DIR_PATH: &str = "./plugins";
+fn parse_day(s: &str) -> Option<chrono::DateTime<Utc>> {
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?;
+    Some(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0)?))
+}
+
+/// `history [from YYYY-MM-DD [to YYYY-MM-DD]] [plugin name]`
+fn parse_history_filter(args: &str) -> HistoryFilter {
+    let mut filter = HistoryFilter::default();
+    let mut words = args.split_whitespace().peekable();
+
+    if let Some(since) = words.peek().and_then(|w| parse_day(w)) {
+        filter.since = Some(since);
+        words.next();
+        if let Some(until) = words.peek().and_then(|w| parse_day(w)) {
+            // Inclusive end day.
+            filter.until = Some(until + chrono::Duration::days(1));
+            words.next();
+        }
+    }
+
+    let plugin = words.collect::<Vec<_>>().join(" ");
+    if !plugin.is_empty() && plugin != "*" {
+        filter.plugin = Some(plugin);
+    }
+    filter
+}
+
+/// `quarantine list|add <path> [detection]|restore <id> [path]|purge <id>`
+fn quarantine_command(vault: &Mutex<Vault>, args: &str) {
+    let (sub, rest) = args.split_once(' ').unwrap_or((args, ""));
+    let rest = rest.trim();
+    let mut vault = vault.lock().unwrap();
+
+    match (sub, rest) {
+        ("list", _) | ("", _) => {
+            if vault.entries().is_empty() {
+                println!("[CORE] Quarantine is empty");
+            }
+            for e in vault.entries() {
+                println!(
+                    "- {} | {} | {} | {} | {} bytes | sha256 {} | by {}",
+                    e.id,
+                    e.quarantined_at.format("%Y-%m-%d %H:%M:%S"),
+                    e.original_path.display(),
+                    e.detection,
+                    e.size,
+                    e.sha256,
+                    e.source
+                );
+            }
+        }
+        ("add", path) if !path.is_empty() => {
+            let (path, detection) = path.split_once(' ').unwrap_or((path, "manual"));
+            match vault.quarantine(Path::new(path), detection.trim(), "cli") {
+                Ok(e) => println!("[CORE] Quarantined {path} as {}", e.id),
+                Err(e) => println!("[CORE](ERROR) Cannot quarantine {path}: {e}"),
+            }
+        }
+        ("restore", id) if !id.is_empty() => {
+            let (id, to) = match id.split_once(' ') {
+                Some((id, to)) => (id, Some(Path::new(to.trim()))),
+                None => (id, None),
+            };
+            match vault.restore(id, to) {
+                Ok(path) => println!("[CORE] Restored {id} to {}", path.display()),
+                Err(e) => println!("[CORE](ERROR) Cannot restore {id}: {e}"),
+            }
+        }
+        ("purge", id) if !id.is_empty() => match vault.purge(id) {
+            Ok(e) => println!("[CORE] Purged {id} ({})", e.original_path.display()),
+            Err(e) => println!("[CORE](ERROR) Cannot purge {id}: {e}"),
+        },
+        _ => println!(
+            "[CORE](INPUT ERROR) Usage: quarantine list | add <PATH> [DETECTION] | restore <ID> [PATH] | purge <ID>"
+        ),
+    }
+}
+
+/// `rules status|update|rollback [version]`
+fn rules_command(
+    store: &Mutex<RuleStore>,
+    args: &str,
+    pm: &Mutex<PluginManager>,
+    history: &Mutex<HistoryStore>,
+) {
+    let (sub, rest) = args.split_once(' ').unwrap_or((args, ""));
+    let rest = rest.trim();
+
+    match (sub, rest) {
+        ("status", _) | ("", _) => {
+            let store = store.lock().unwrap();
+            let current = store.current().map(|v| v.version.clone());
+            if store.versions().is_empty() {
+                println!("[CORE] No rule bundle installed");
+            }
+            for v in store.versions() {
+                let marker = if Some(&v.version) == current.as_ref() {
+                    " (active)"
+                } else {
+                    ""
+                };
+                println!(
+                    "- {}{} | {} | sha256 {} | from {}",
+                    v.version,
+                    marker,
+                    v.installed_at.format("%Y-%m-%d %H:%M:%S"),
+                    v.sha256,
+                    v.source
+                );
+            }
+            if let Some(held) = store.held() {
+                println!(
+                    "[CORE] Scheduled updates skip versions up to {held} since a rollback, `rules update` lifts the hold"
+                );
+            }
+        }
+        ("update", _) => {
+            // The store lock is released before the reload takes the history and plugin locks.
+            let outcome = store.lock().unwrap().update_manual();
+            match outcome {
+                Ok(UpdateOutcome::Installed(v)) => {
+                    println!("[CORE] Rules {} installed", v.version);
+                    updater::reload_plugins(pm, history);
+                }
+                Ok(UpdateOutcome::UpToDate(current)) => {
+                    println!("[CORE] Rules are up to date ({current})")
+                }
+                Err(e) => println!("[CORE](ERROR) Rule update failed: {e}"),
+            }
+        }
+        ("rollback", version) => {
+            let version = (!version.is_empty()).then_some(version);
+            let outcome = store.lock().unwrap().rollback(version);
+            match outcome {
+                Ok(v) => {
+                    println!("[CORE] Rules {} active again", v.version);
+                    updater::reload_plugins(pm, history);
+                }
+                Err(e) => println!("[CORE](ERROR) Cannot roll back: {e}"),
+            }
+        }
+        _ => println!("[CORE](INPUT ERROR) Usage: rules status | update | rollback [VERSION]"),
+    }
+}
 
 fn main() {
-    let mut pm = PluginManager::new(PLUGIN_DIR_PATH, LogLevel::Info);
+    let config = DaemonConfig::load().unwrap_or_else(|e| {
+        println!("[CORE](ERROR) Invalid config, using defaults: {e}");
+        DaemonConfig::default()
+    });
+
+    let history = match HistoryStore::open(config.history_path(), config.history.clone()) {
+        Ok(h) => Arc::new(Mutex::new(h)),
+        Err(e) => {
+            eprintln!(
+                "[CORE](ERROR) Cannot open history {}: {e}",
+                config.history_path().display()
+            );
+            std::process::exit(1);
+        }
+    };
+
+    let vault = match Vault::open(config.quarantine_dir()) {
+        Ok(v) => Some(Arc::new(Mutex::new(v))),
+        Err(e) => {
+            println!(
+                "[CORE](ERROR) Cannot open quarantine {}: {e}",
+                config.quarantine_dir().display()
+            );
+            None
+        }
+    };
+
+    let rules = match RuleStore::open(config.rules_dir(), &config.updates) {
+        Ok(store) => Some(Arc::new(Mutex::new(store))),
+        Err(e) => {
+            println!(
+                "[CORE](ERROR) Cannot open rule store {}: {e}",
+                config.rules_dir().display()
+            );
+            None
+        }
+    };
+
+    let mut pm = PluginManager::new(&config.plugins_dir, LogLevel::Info);
+    pm.set_limit_policy(config.limits.to_policy());
+    pm.set_default_user(config.plugin_user.clone());
+    pm.set_data_dir(&config.data_dir);
+    pm.set_shutdown_grace(Duration::from_secs(config.shutdown_grace_secs));
+    pm.set_broker(Arc::new(FileBroker::new(&config.broker, vault.clone())));
+
+    let events = pm.subscribe();
+    {
+        let history = Arc::clone(&history);
+        std::thread::spawn(move || record_events(events, history));
+    }
 
     pm.scan_dir();
     pm.list_plugins();
 
+    let pm = Arc::new(Mutex::new(pm));
+    let scheduler = Arc::new(Mutex::new(Scheduler::new(
+        &config.jobs,
+        Arc::clone(&pm),
+        Arc::clone(&history),
+    )));
+    Scheduler::spawn(Arc::clone(&scheduler));
+    if let Some(rules) = &rules {
+        RuleStore::spawn(Arc::clone(rules), Arc::clone(&pm), Arc::clone(&history));
+    }
+
     loop {
         print!("$> ");
         io::stdout().flush().unwrap();
@@ -28,15 +275,130 @@ fn main() {
 
         match cmd {
             "info" => {
-                let plugins = pm.list_plugins();
+                let plugins = pm.lock().unwrap().list_plugins();
                 for plugin in plugins {
-                    println!("- PID: {} | NAME: {} | PATH: {} | FUNCTIONS: {:?}", plugin.pid, plugin.name, plugin.path.display(), plugin.functions);
+                    println!(
+                        "- PID: {} | NAME: {} | PATH: {} | USER: {} | FUNCTIONS: {:?}",
+                        plugin.pid,
+                        plugin.name,
+                        plugin.path.display(),
+                        plugin.run_as.as_deref().unwrap_or("-"),
+                        plugin.functions
+                    );
+                }
+            }
+            "history" => {
+                let rest: Vec<&str> = parts.collect();
+                let filter = parse_history_filter(&rest.join(" "));
+                let history = history.lock().unwrap();
+                let entries = history.query(&filter);
+                if entries.is_empty() {
+                    println!("[CORE] No history entries");
+                }
+                for e in entries {
+                    let duration = e
+                        .ended_at
+                        .map(|end| format!("{}ms", (end - e.started_at).num_milliseconds()))
+                        .unwrap_or_else(|| "-".to_string());
+                    let job = e.job.as_deref().map(|j| format!(" [{j}]")).unwrap_or_default();
+                    println!(
+                        "- #{}{} | {} | {} | {}({}) | {:?} | {}",
+                        e.id,
+                        job,
+                        e.started_at.format("%Y-%m-%d %H:%M:%S"),
+                        e.plugin,
+                        e.function,
+                        e.args.join(", "),
+                        e.outcome,
+                        duration
+                    );
+                }
+            }
+            "result" => {
+                let id: u64 = match parts.next().map(str::parse) {
+                    Some(Ok(id)) => id,
+                    _ => {
+                        println!("[CORE](INPUT ERROR) Usage: result <HISTORY_ID>");
+                        continue;
+                    }
+                };
+                match history.lock().unwrap().get(id) {
+                    Some(e) => {
+                        println!("{}", e.result.as_deref().unwrap_or("<no result>"));
+                        if let Some(len) = e.result_len {
+                            println!("[CORE] (truncated, full result was {len} bytes)");
+                        }
+                    }
+                    None => println!("[CORE](ERROR) No history entry #{id}"),
+                }
+            }
+            "stats" => {
+                for (plugin, usage) in pm.lock().unwrap().usage() {
+                    match usage {
+                        Ok(u) => {
+                            let cpu = u
+                                .cpu_percent
+                                .map(|p| format!("{p:.1}%"))
+                                .unwrap_or_else(|| "-".to_string());
+                            println!(
+                                "- PID: {} | NAME: {} | CPU: {} ({:.2?}) | RSS: {} KiB | IO: {}r/{}w bytes | FDS: {}",
+                                plugin.pid,
+                                plugin.name,
+                                cpu,
+                                u.cpu_time,
+                                u.rss_bytes / 1024,
+                                u.read_bytes,
+                                u.write_bytes,
+                                u.open_fds
+                            );
+                        }
+                        Err(e) => println!(
+                            "[CORE](ERROR) Cannot read usage of {} ({}): {e}",
+                            plugin.name, plugin.pid
+                        ),
+                    }
+                }
+            }
+            "jobs" => {
+                let scheduler = scheduler.lock().unwrap();
+                if scheduler.jobs().is_empty() {
+                    println!("[CORE] No scheduled jobs");
+                }
+                for job in scheduler.jobs() {
+                    let next = job
+                        .next_run()
+                        .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                        .unwrap_or_else(|| "never".to_string());
+                    println!(
+                        "- {} | {} | {}::{}({}) | next: {}",
+                        job.config.name,
+                        job.config.schedule,
+                        job.config.plugin,
+                        job.config.function,
+                        job.config.args.join(", "),
+                        next
+                    );
+                }
+            }
+            "quarantine" => {
+                let rest: Vec<&str> = parts.collect();
+                match &vault {
+                    Some(vault) => quarantine_command(vault, &rest.join(" ")),
+                    None => println!("[CORE](ERROR) Quarantine vault unavailable"),
+                }
+            }
+            "rules" => {
+                let rest: Vec<&str> = parts.collect();
+                match &rules {
+                    Some(rules) => rules_command(rules, &rest.join(" "), &pm, &history),
+                    None => println!("[CORE](ERROR) Rule store unavailable"),
                 }
             }
             "refresh" => {
-                pm.scan_dir();
+                pm.lock().unwrap().scan_dir();
             }
             "exit" | "quit" => {
+                pm.lock().unwrap().shutdown_all();
                 break;
             }
             "restart" => {
@@ -54,7 +416,7 @@ fn main() {
                         continue;
                     }
                 };
-                pm.restart_plugin(pid);
+                pm.lock().unwrap().restart_plugin(pid);
             }
             "kill" => {
                 let pid_str = parts.next();
@@ -71,7 +433,7 @@ fn main() {
                         continue;
                     }
                 };
-                pm.kill_plugin(pid);
+                pm.lock().unwrap().kill_plugin(pid);
             }
 
             
@@ -114,10 +476,28 @@ fn main() {
                         .collect()
                 };
 
-                let call_payload = ipc_protocol::ipc_payload::CallPayload { fn_name, args };
+                let call_payload = ipc_protocol::ipc_payload::CallPayload {
+                    fn_name: fn_name.clone(),
+                    args: args.clone(),
+                };
+                // Lock order is history then plugin manager, as in the scheduler.
+                let mut history = history.lock().unwrap();
+                let mut pm = pm.lock().unwrap();
+                let plugin_name = pm
+                    .list_plugins()
+                    .into_iter()
+                    .find(|p| p.pid == pid)
+                    .map(|p| p.name)
+                    .unwrap_or_default();
 
+                // The history lock is held while sending so the result cannot be recorded before the call.
                 match pm.send_call(pid, call_payload) {
-                    Ok(req_id) => println!("[CORE] CALL sent (request_id={req_id})"),
+                    Ok(req_id) => {
+                        println!("[CORE] CALL sent (request_id={req_id})");
+                        if let Err(e) = history.start(&plugin_name, pid, req_id, &fn_name, &args, None) {
+                            println!("[CORE](ERROR) Failed to write history: {e}");
+                        }
+                    }
                     Err(e) => println!("[CORE](ERROR) Failed to send CALL: {e}"),
                 }
             }
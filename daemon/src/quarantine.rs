@@ -0,0 +1,365 @@
+use std::ffi::OsStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::OwnedFd;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt, fchown};
+use std::path::{Path, PathBuf};
+
+use chacha20::ChaCha20;
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chrono::{DateTime, Utc};
+use nix::fcntl::{OFlag, open, openat};
+use nix::sys::stat::Mode;
+use nix::unistd::{UnlinkatFlags, unlinkat};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+static KEY_FILE: &str = "vault.key";
+static INDEX_FILE: &str = "index.json";
+/// Extension of the encrypted copies, named after the entry id.
+static ITEM_EXTENSION: &str = "qbin";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+/// Files are encrypted in chunks of this size, so large ones are never held in memory.
+const CHUNK: usize = 1024 * 1024;
+
+/// A quarantined file, as kept in the vault index.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantineEntry {
+    pub id: String,
+    pub original_path: PathBuf,
+    /// Permission bits of the original file.
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    /// SHA-256 of the original contents, checked again on restore.
+    pub sha256: String,
+    /// What flagged the file, e.g. `malware:Linux_Trojan_Mirai`.
+    pub detection: String,
+    /// Plugin or command that asked for the quarantine.
+    pub source: String,
+    pub quarantined_at: DateTime<Utc>,
+}
+
+/// Root-only directory holding flagged files encrypted with ChaCha20 under a
+/// per-vault key, with a nonce derived from the entry id.
+///
+/// Encrypted copies are never executable and do not match the rules they were
+/// flagged by, so scanning the data directory does not raise them again.
+pub struct Vault {
+    dir: PathBuf,
+    key: [u8; KEY_LEN],
+    entries: Vec<QuarantineEntry>,
+}
+
+impl Vault {
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+
+        let key = Self::load_key(&dir.join(KEY_FILE))?;
+        let index = dir.join(INDEX_FILE);
+        let entries = if index.exists() {
+            serde_json::from_slice(&fs::read(&index)?).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {e}", index.display()),
+                )
+            })?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self { dir, key, entries })
+    }
+
+    fn load_key(path: &Path) -> io::Result<[u8; KEY_LEN]> {
+        if path.exists() {
+            return fs::read(path)?.try_into().map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is not a {KEY_LEN} byte key", path.display()),
+                )
+            });
+        }
+
+        let key: [u8; KEY_LEN] = rand::random();
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)?;
+        file.write_all(&key)?;
+        file.sync_all()?;
+        Ok(key)
+    }
+
+    pub fn entries(&self) -> &[QuarantineEntry] {
+        &self.entries
+    }
+
+    fn item_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.{ITEM_EXTENSION}"))
+    }
+
+    /// Rewrites the index through a temporary file so a crash never leaves it half written.
+    fn save_index(&self) -> io::Result<()> {
+        let tmp = self.dir.join(format!("{INDEX_FILE}.tmp"));
+        let json = serde_json::to_vec_pretty(&self.entries).map_err(io::Error::other)?;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp)?;
+        file.write_all(&json)?;
+        file.sync_all()?;
+        fs::rename(tmp, self.dir.join(INDEX_FILE))
+    }
+
+    /// Moves `path` into the vault. Only regular files are accepted, symlinks are not followed.
+    pub fn quarantine(
+        &mut self,
+        path: &Path,
+        detection: &str,
+        source: &str,
+    ) -> io::Result<QuarantineEntry> {
+        if !path.is_absolute() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "path must be absolute",
+            ));
+        }
+        let (Some(parent), Some(file_name)) = (path.parent(), path.file_name()) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid path"));
+        };
+        let dir = open(parent, OFlag::O_DIRECTORY | OFlag::O_CLOEXEC, Mode::empty())?;
+        self.quarantine_at(&dir, file_name, path, detection, source)
+    }
+
+    /// Moves `file_name` in the open directory `dir` into the vault, `path`
+    /// being what it is recorded as. The file is opened without following
+    /// symlinks and described from that descriptor, then unlinked from `dir`.
+    pub fn quarantine_at(
+        &mut self,
+        dir: &OwnedFd,
+        file_name: &OsStr,
+        path: &Path,
+        detection: &str,
+        source: &str,
+    ) -> io::Result<QuarantineEntry> {
+        if path.starts_with(&self.dir) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "file is already in the vault",
+            ));
+        }
+        // Non-blocking so a fifo put in place of the file cannot stall the open.
+        let mut input = File::from(openat(
+            dir,
+            file_name,
+            OFlag::O_RDONLY | OFlag::O_NOFOLLOW | OFlag::O_NONBLOCK | OFlag::O_CLOEXEC,
+            Mode::empty(),
+        )?);
+        let metadata = input.metadata()?;
+        if !metadata.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only regular files can be quarantined",
+            ));
+        }
+
+        let id = format!("{:016x}", rand::random::<u64>());
+        let item = self.item_path(&id);
+        let sha256 = match self.seal(&id, &mut input, &item) {
+            Ok(sha256) => sha256,
+            Err(e) => {
+                let _ = fs::remove_file(&item);
+                return Err(e);
+            }
+        };
+
+        let entry = QuarantineEntry {
+            id,
+            original_path: path.to_path_buf(),
+            mode: metadata.mode() & 0o7777,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            size: metadata.len(),
+            sha256,
+            detection: detection.to_string(),
+            source: source.to_string(),
+            quarantined_at: Utc::now(),
+        };
+        self.entries.push(entry.clone());
+        if let Err(e) = self.save_index() {
+            self.entries.pop();
+            let _ = fs::remove_file(&item);
+            return Err(e);
+        }
+
+        // The original only goes once the encrypted copy and its index entry are on disk.
+        unlinkat(dir, file_name, UnlinkatFlags::NoRemoveDir)?;
+        Ok(entry)
+    }
+
+    /// Writes the encrypted copy of `input` to `item` and returns the SHA-256 of the plain contents.
+    fn seal(&self, id: &str, input: &mut File, item: &Path) -> io::Result<String> {
+        let mut output = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(item)?;
+
+        let mut hasher = Sha256::new();
+        let mut cipher = self.cipher(id);
+        let mut buf = vec![0u8; CHUNK];
+        loop {
+            let n = input.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            apply(&mut cipher, &mut buf[..n])?;
+            output.write_all(&buf[..n])?;
+        }
+        output.sync_all()?;
+        Ok(hex::encode(hasher.finalize()))
+    }
+
+    fn position(&self, id: &str) -> io::Result<usize> {
+        self.entries.iter().position(|e| e.id == id).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("no quarantine entry {id}"))
+        })
+    }
+
+    /// Decrypts entry `id` to its original path, or to `to` when given, and drops it from the vault.
+    /// Existing files are never overwritten. Owner and permissions are restored as recorded.
+    pub fn restore(&mut self, id: &str, to: Option<&Path>) -> io::Result<PathBuf> {
+        let index = self.position(id)?;
+        let entry = self.entries[index].clone();
+        let target = to.unwrap_or(&entry.original_path).to_path_buf();
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Stays private until the contents are verified.
+        let output = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&target)?;
+        if let Err(e) = self.unseal(&entry, output) {
+            let _ = fs::remove_file(&target);
+            return Err(e);
+        }
+
+        self.entries.remove(index);
+        self.save_index()?;
+        fs::remove_file(self.item_path(id))?;
+        Ok(target)
+    }
+
+    fn unseal(&self, entry: &QuarantineEntry, mut output: File) -> io::Result<()> {
+        let mut input = File::open(self.item_path(&entry.id))?;
+
+        let mut hasher = Sha256::new();
+        let mut cipher = self.cipher(&entry.id);
+        let mut buf = vec![0u8; CHUNK];
+        loop {
+            let n = input.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            apply(&mut cipher, &mut buf[..n])?;
+            hasher.update(&buf[..n]);
+            output.write_all(&buf[..n])?;
+        }
+
+        if hex::encode(hasher.finalize()) != entry.sha256 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("quarantine entry {} is corrupted", entry.id),
+            ));
+        }
+        output.sync_all()?;
+
+        fchown(&output, Some(entry.uid), Some(entry.gid))?;
+        output.set_permissions(fs::Permissions::from_mode(entry.mode))
+    }
+
+    /// Each entry gets its own nonce, taken from the hash of its random id.
+    fn cipher(&self, id: &str) -> ChaCha20 {
+        let digest = Sha256::digest(id.as_bytes());
+        let nonce: [u8; NONCE_LEN] = digest[..NONCE_LEN].try_into().expect("digest is longer");
+        ChaCha20::new(&self.key.into(), &nonce.into())
+    }
+
+    /// Deletes entry `id` and its encrypted copy for good.
+    pub fn purge(&mut self, id: &str) -> io::Result<QuarantineEntry> {
+        let index = self.position(id)?;
+        let entry = self.entries.remove(index);
+        self.save_index()?;
+
+        match fs::remove_file(self.item_path(id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(entry),
+        }
+    }
+}
+
+/// Fails rather than panics past the 256 GiB a ChaCha20 keystream covers.
+fn apply(cipher: &mut ChaCha20, data: &mut [u8]) -> io::Result<()> {
+    cipher
+        .try_apply_keystream(data)
+        .map_err(|_| io::Error::new(io::ErrorKind::FileTooLarge, "file too large to encrypt"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("griffon-vault-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn sealed_files_restore_unchanged() {
+        let dir = scratch("roundtrip");
+        let mut vault = Vault::open(dir.join("vault")).unwrap();
+        let original = dir.join("sample");
+        let contents: Vec<u8> = (0..3 * CHUNK / 2).map(|i| (i % 251) as u8).collect();
+        fs::write(&original, &contents).unwrap();
+        fs::set_permissions(&original, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let entry = vault.quarantine(&original, "test", "unit").unwrap();
+        assert!(!original.exists());
+        assert_eq!(entry.size, contents.len() as u64);
+        assert_ne!(fs::read(vault.item_path(&entry.id)).unwrap(), contents);
+
+        let restored = vault.restore(&entry.id, None).unwrap();
+        assert_eq!(fs::read(&restored).unwrap(), contents);
+        assert_eq!(fs::metadata(&restored).unwrap().mode() & 0o7777, 0o640);
+        assert!(vault.entries().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn symlinks_are_not_followed() {
+        let dir = scratch("symlink");
+        let mut vault = Vault::open(dir.join("vault")).unwrap();
+        fs::write(dir.join("target"), b"kept").unwrap();
+        symlink(dir.join("target"), dir.join("link")).unwrap();
+
+        assert!(vault.quarantine(&dir.join("link"), "test", "unit").is_err());
+        assert!(dir.join("target").exists() && dir.join("link").exists());
+        assert!(vault.entries().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
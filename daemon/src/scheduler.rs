@@ -0,0 +1,307 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use cron::Schedule;
+use ipc_protocol::ipc_payload::CallPayload;
+use plugin_manager::PluginManager;
+
+use crate::config::JobConfig;
+use crate::history::{HistoryStore, Outcome};
+
+static POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+/// Crontab day-of-week numbers 0-6 from Sunday, as names the `cron` crate reads alike.
+static WEEKDAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// Upper bound on how long the scheduler sleeps, so clock jumps and suspends are noticed.
+const MAX_SLEEP: Duration = Duration::from_secs(30);
+
+pub struct ScheduledJob {
+    pub config: JobConfig,
+    schedule: Schedule,
+    next_run: Option<DateTime<Local>>,
+    /// History id of the last call issued by this job.
+    last_call: Option<u64>,
+}
+
+impl ScheduledJob {
+    pub fn new(config: JobConfig) -> Result<Self, String> {
+        let schedule = parse_schedule(&config.schedule).map_err(|e| {
+            format!(
+                "job '{}': bad schedule '{}': {e}",
+                config.name, config.schedule
+            )
+        })?;
+        let next_run = schedule.upcoming(Local).next();
+
+        Ok(Self {
+            config,
+            schedule,
+            next_run,
+            last_call: None,
+        })
+    }
+
+    pub fn next_run(&self) -> Option<DateTime<Local>> {
+        self.next_run
+    }
+}
+
+/// Cron expressions are accepted with or without the leading seconds field.
+/// The day of week is numbered as in crontab: 0-7, Sunday being 0 and 7.
+pub fn parse_schedule(expr: &str) -> Result<Schedule, String> {
+    let mut fields: Vec<String> = expr.split_whitespace().map(str::to_string).collect();
+    if fields.len() == 5 {
+        fields.insert(0, "0".to_string());
+    }
+    // The `cron` crate numbers weekdays 1-7 from Sunday.
+    if let Some(weekday) = fields.get_mut(5) {
+        *weekday = crontab_weekdays(weekday)?;
+    }
+    Schedule::from_str(&fields.join(" ")).map_err(|e| e.to_string())
+}
+
+/// Rewrites the numeric items of a crontab day-of-week field (`1`, `1-5`, `*/2`,
+/// `5/1`) as weekday names. Named items are left to the `cron` crate.
+fn crontab_weekdays(field: &str) -> Result<String, String> {
+    if field == "*" || field == "?" {
+        return Ok(field.to_string());
+    }
+    let day = |s: &str| match s.parse::<usize>() {
+        Ok(n) if n <= 7 => Ok(n),
+        _ => Err(format!("bad day of week {s}, expected 0-7")),
+    };
+
+    let mut days = [false; 7];
+    let mut named = Vec::new();
+    for item in field.split(',') {
+        if item.chars().any(|c| c.is_ascii_alphabetic()) {
+            named.push(item.to_string());
+            continue;
+        }
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => match step.parse::<usize>() {
+                Ok(step) if step > 0 => (range, Some(step)),
+                _ => return Err(format!("bad step in day of week {item}")),
+            },
+            None => (item, None),
+        };
+        let (first, last) = match (range, range.split_once('-')) {
+            ("*", _) => (0, 6),
+            (_, Some((first, last))) => (day(first)?, day(last)?),
+            // `5/2` runs from 5 to the end of the week.
+            (single, None) if step.is_some() => (day(single)?, 7),
+            (single, None) => (day(single)?, day(single)?),
+        };
+        if first > last {
+            return Err(format!("bad range in day of week {item}"));
+        }
+        for n in (first..=last).step_by(step.unwrap_or(1)) {
+            days[n % 7] = true;
+        }
+    }
+
+    let mut items: Vec<String> = WEEKDAYS
+        .iter()
+        .zip(days)
+        .filter(|(_, set)| *set)
+        .map(|(name, _)| name.to_string())
+        .collect();
+    items.extend(named);
+    Ok(items.join(","))
+}
+
+/// Runs the `[[job]]` entries of the config at their cron times.
+pub struct Scheduler {
+    jobs: Vec<ScheduledJob>,
+    pm: Arc<Mutex<PluginManager>>,
+    history: Arc<Mutex<HistoryStore>>,
+}
+
+impl Scheduler {
+    /// Invalid or disabled jobs are reported and left out.
+    pub fn new(
+        configs: &[JobConfig],
+        pm: Arc<Mutex<PluginManager>>,
+        history: Arc<Mutex<HistoryStore>>,
+    ) -> Self {
+        let mut jobs = Vec::new();
+
+        for config in configs.iter().filter(|c| c.enabled) {
+            match ScheduledJob::new(config.clone()) {
+                Ok(job) => jobs.push(job),
+                Err(e) => println!("[SCHEDULER](ERROR) {e}"),
+            }
+        }
+
+        Self { jobs, pm, history }
+    }
+
+    pub fn jobs(&self) -> &[ScheduledJob] {
+        &self.jobs
+    }
+
+    pub fn spawn(scheduler: Arc<Mutex<Self>>) -> JoinHandle<()> {
+        thread::spawn(move || {
+            loop {
+                let sleep_for = scheduler.lock().unwrap().tick(Local::now());
+                thread::sleep(sleep_for);
+            }
+        })
+    }
+
+    /// Fires every due job and returns how long to wait before the next tick.
+    fn tick(&mut self, now: DateTime<Local>) -> Duration {
+        for index in 0..self.jobs.len() {
+            let due = self.jobs[index].next_run.is_some_and(|t| t <= now);
+            if !due {
+                continue;
+            }
+            self.fire(index);
+            // Runs missed while the machine was asleep are collapsed into a single one.
+            let job = &mut self.jobs[index];
+            job.next_run = job.schedule.after(&now).next();
+        }
+
+        self.jobs
+            .iter()
+            .filter_map(|j| j.next_run)
+            .min()
+            .and_then(|next| (next - now).to_std().ok())
+            .map_or(MAX_SLEEP, |d| d.min(MAX_SLEEP))
+    }
+
+    fn fire(&mut self, index: usize) {
+        let job = &self.jobs[index];
+        let name = job.config.name.clone();
+
+        if job.config.skip_on_battery && on_battery() {
+            println!("[SCHEDULER](INFO) Job '{name}' skipped: running on battery");
+            return;
+        }
+
+        let mut history = self.history.lock().unwrap();
+
+        let still_running = job
+            .last_call
+            .and_then(|id| history.get(id))
+            .is_some_and(|e| e.outcome == Outcome::Running);
+        if still_running {
+            println!("[SCHEDULER](INFO) Job '{name}' skipped: previous run still active");
+            return;
+        }
+
+        let mut pm = self.pm.lock().unwrap();
+        let Some(plugin) = pm
+            .list_plugins()
+            .into_iter()
+            .find(|p| p.name == job.config.plugin)
+        else {
+            println!(
+                "[SCHEDULER](ERROR) Job '{name}': plugin '{}' is not running",
+                job.config.plugin
+            );
+            return;
+        };
+
+        let call = CallPayload {
+            fn_name: job.config.function.clone(),
+            args: job.config.args.clone(),
+        };
+        let request_id = match pm.send_call(plugin.pid, call) {
+            Ok(id) => id,
+            Err(e) => {
+                println!("[SCHEDULER](ERROR) Job '{name}': failed to send CALL: {e}");
+                return;
+            }
+        };
+
+        match history.start(
+            &plugin.name,
+            plugin.pid,
+            request_id,
+            &job.config.function,
+            &job.config.args,
+            Some(&name),
+        ) {
+            Ok(id) => {
+                self.jobs[index].last_call = Some(id);
+                println!("[SCHEDULER](INFO) Job '{name}' started (request_id={request_id})");
+            }
+            Err(e) => println!("[SCHEDULER](ERROR) Failed to write history: {e}"),
+        }
+    }
+}
+
+/// True when a battery is discharging and no mains/USB supply is online.
+/// Machines without `/sys/class/power_supply` are treated as plugged in.
+pub fn on_battery() -> bool {
+    let Ok(entries) = fs::read_dir(POWER_SUPPLY_DIR) else {
+        return false;
+    };
+
+    let mut discharging = false;
+    for entry in entries.filter_map(|e| e.ok()) {
+        let dir = entry.path();
+        match read_attr(&dir, "type").as_deref() {
+            Some("Mains") | Some("USB") if read_attr(&dir, "online").as_deref() == Some("1") => {
+                return false;
+            }
+            Some("Battery") if read_attr(&dir, "status").as_deref() == Some("Discharging") => {
+                discharging = true;
+            }
+            _ => {}
+        }
+    }
+    discharging
+}
+
+fn read_attr(dir: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(dir.join(name))
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, Weekday};
+
+    fn next_weekday(expr: &str) -> Weekday {
+        parse_schedule(expr)
+            .unwrap()
+            .upcoming(Local)
+            .next()
+            .unwrap()
+            .weekday()
+    }
+
+    #[test]
+    fn weekdays_follow_crontab_numbering() {
+        assert_eq!(next_weekday("0 4 * * 1"), Weekday::Mon);
+        assert_eq!(next_weekday("0 4 * * 0"), Weekday::Sun);
+        assert_eq!(next_weekday("0 4 * * 7"), Weekday::Sun);
+        assert_eq!(next_weekday("0 0 4 * * 6"), Weekday::Sat);
+    }
+
+    #[test]
+    fn weekday_lists_ranges_and_steps() {
+        assert_eq!(crontab_weekdays("1-5").unwrap(), "MON,TUE,WED,THU,FRI");
+        assert_eq!(crontab_weekdays("0,7").unwrap(), "SUN");
+        assert_eq!(crontab_weekdays("*/2").unwrap(), "SUN,TUE,THU,SAT");
+        assert_eq!(crontab_weekdays("5/1").unwrap(), "SUN,FRI,SAT");
+        assert_eq!(crontab_weekdays("MON-FRI").unwrap(), "MON-FRI");
+        assert_eq!(crontab_weekdays("*").unwrap(), "*");
+    }
+
+    #[test]
+    fn bad_weekdays_are_rejected() {
+        assert!(parse_schedule("0 4 * * 8").is_err());
+        assert!(parse_schedule("0 4 * * 5-1").is_err());
+        assert!(parse_schedule("0 4 * * */0").is_err());
+    }
+}
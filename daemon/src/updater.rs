@@ -0,0 +1,698 @@
+use std::cmp::Ordering;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{OpenOptionsExt, symlink};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::{DateTime, Local, Utc};
+use ed25519_dalek::{Signature, VerifyingKey};
+use flate2::read::GzDecoder;
+use ipc_protocol::ipc_payload::CallPayload;
+use plugin_manager::PluginManager;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::UpdatesConfig;
+use crate::history::HistoryStore;
+use crate::scheduler::parse_schedule;
+
+static STATE_FILE: &str = "state.json";
+static VERSIONS_DIR: &str = "versions";
+/// Symlink to the active version, what plugins point their `rules_dir` under.
+static CURRENT_LINK: &str = "current";
+/// Where a bundle is unpacked before it is known to be complete.
+static STAGING_DIR: &str = ".staging";
+/// At the root of a mirror: names the bundle to install.
+static INDEX_FILE: &str = "latest.json";
+/// At the root of a bundle, covered by the signature unlike the index.
+static MANIFEST_FILE: &str = "manifest.json";
+/// Detached signature of a bundle: `<bundle>.sig`, the base64 Ed25519 signature of its bytes.
+static SIGNATURE_SUFFIX: &str = ".sig";
+/// Plugins announcing this function are asked to reload once the active rule set changed.
+static RELOAD_FUNCTION: &str = "reload_rules";
+/// Job name recorded in the history for the reload calls.
+static RELOAD_JOB: &str = "rules-update";
+/// Upper bound on how long the update thread sleeps, so clock jumps and suspends are noticed.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// `latest.json`: the newest bundle of a mirror.
+#[derive(Debug, Deserialize)]
+struct MirrorIndex {
+    version: String,
+    /// File name of the bundle, next to the index.
+    bundle: String,
+}
+
+/// `manifest.json` inside a bundle.
+#[derive(Debug, Deserialize)]
+struct BundleManifest {
+    version: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstalledVersion {
+    pub version: String,
+    /// SHA-256 of the bundle as downloaded.
+    pub sha256: String,
+    /// Where the bundle was fetched from.
+    pub source: String,
+    pub installed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoreState {
+    current: Option<String>,
+    /// Versions still on disk, oldest install first.
+    installed: Vec<InstalledVersion>,
+    /// Highest version rolled back from. Scheduled updates skip it and anything
+    /// older, until a manual `rules update` lifts the hold.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    held: Option<String>,
+}
+
+pub enum UpdateOutcome {
+    /// The mirror offers nothing newer than the active version.
+    UpToDate(String),
+    Installed(InstalledVersion),
+}
+
+enum Source {
+    Http(String),
+    /// A mirror directory holding `latest.json`.
+    Dir(PathBuf),
+    /// A bundle file, its signature next to it.
+    Bundle(PathBuf),
+}
+
+impl Source {
+    fn parse(source: &str) -> Self {
+        if source.starts_with("http://") || source.starts_with("https://") {
+            return Self::Http(source.trim_end_matches('/').to_string());
+        }
+        let path = PathBuf::from(source.strip_prefix("file://").unwrap_or(source));
+        if path.is_file() {
+            Self::Bundle(path)
+        } else {
+            Self::Dir(path)
+        }
+    }
+}
+
+/// Rule bundles installed under `<data_dir>/rules`, one directory per version.
+///
+/// A bundle is a gzipped tarball holding `manifest.json` and the content
+/// plugins load, conventionally `rules/` and `hashes/`. It is only unpacked
+/// once its signature checks against one of the configured keys, and only
+/// made active once fully unpacked, by replacing the `current` symlink.
+pub struct RuleStore {
+    dir: PathBuf,
+    config: UpdatesConfig,
+    keys: Vec<VerifyingKey>,
+    state: StoreState,
+}
+
+impl RuleStore {
+    pub fn open<P: AsRef<Path>>(dir: P, config: &UpdatesConfig) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(dir.join(VERSIONS_DIR))?;
+
+        let keys = config
+            .public_keys
+            .iter()
+            .map(|key| parse_key(key))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let state_path = dir.join(STATE_FILE);
+        let state = if state_path.exists() {
+            serde_json::from_slice(&fs::read(&state_path)?).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {e}", state_path.display()),
+                )
+            })?
+        } else {
+            StoreState::default()
+        };
+
+        Ok(Self {
+            dir,
+            config: config.clone(),
+            keys,
+            state,
+        })
+    }
+
+    pub fn schedule(&self) -> Option<&str> {
+        self.config.schedule.as_deref()
+    }
+
+    pub fn current(&self) -> Option<&InstalledVersion> {
+        let current = self.state.current.as_deref()?;
+        self.state.installed.iter().find(|v| v.version == current)
+    }
+
+    pub fn versions(&self) -> &[InstalledVersion] {
+        &self.state.installed
+    }
+
+    /// Version at or below which updates are skipped since a rollback.
+    pub fn held(&self) -> Option<&str> {
+        self.state.held.as_deref()
+    }
+
+    /// `rules update`: lifts the hold left by a rollback, then updates.
+    pub fn update_manual(&mut self) -> io::Result<UpdateOutcome> {
+        if self.state.held.take().is_some() {
+            self.save_state()?;
+        }
+        self.update()
+    }
+
+    /// Installs the newest bundle of the configured source if it is newer than
+    /// the active one and than the version held back by a rollback.
+    pub fn update(&mut self) -> io::Result<UpdateOutcome> {
+        let source = self.config.source.clone().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no source set in the [updates] config",
+            )
+        })?;
+        if self.keys.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no public_keys set in the [updates] config, bundles cannot be verified",
+            ));
+        }
+
+        let (offered, bundle_name) = match Source::parse(&source) {
+            Source::Bundle(path) => (None, path.display().to_string()),
+            Source::Http(_) | Source::Dir(_) => {
+                let index: MirrorIndex = serde_json::from_slice(&self.fetch(&source, INDEX_FILE)?)
+                    .map_err(|e| {
+                        io::Error::new(io::ErrorKind::InvalidData, format!("{INDEX_FILE}: {e}"))
+                    })?;
+                if !is_file_name(&index.bundle) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{INDEX_FILE}: bad bundle name {}", index.bundle),
+                    ));
+                }
+                // Nothing is downloaded when the index already says there is nothing new.
+                if let Some(current) = self.newer_or_current(&index.version) {
+                    return Ok(UpdateOutcome::UpToDate(current));
+                }
+                (Some(index.version), index.bundle)
+            }
+        };
+
+        let bundle = self.fetch(&source, &bundle_name)?;
+        let signature = self.fetch(&source, &format!("{bundle_name}{SIGNATURE_SUFFIX}"))?;
+        self.verify(&bundle, &signature)?;
+
+        let staging = self.dir.join(STAGING_DIR);
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        unpack(&bundle, &staging, self.config.max_unpacked_mb * 1024 * 1024)?;
+
+        let manifest: BundleManifest =
+            serde_json::from_slice(&fs::read(staging.join(MANIFEST_FILE))?).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{MANIFEST_FILE}: {e}"))
+            })?;
+        let version = manifest.version;
+        if !is_file_name(&version) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bad bundle version {version}"),
+            ));
+        }
+        if offered.as_ref().is_some_and(|offered| *offered != version) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{INDEX_FILE} announces {} but the bundle is {version}",
+                    offered.unwrap_or_default()
+                ),
+            ));
+        }
+        // The signed version is what protects against a mirror serving an old bundle.
+        if let Some(current) = self.newer_or_current(&version) {
+            fs::remove_dir_all(&staging)?;
+            return Ok(UpdateOutcome::UpToDate(current));
+        }
+
+        let target = self.version_dir(&version);
+        if target.exists() {
+            fs::remove_dir_all(&target)?;
+        }
+        fs::rename(&staging, &target)?;
+
+        let installed = InstalledVersion {
+            version: version.clone(),
+            sha256: hex::encode(Sha256::digest(&bundle)),
+            source: bundle_source(&source, &bundle_name),
+            installed_at: Utc::now(),
+        };
+        self.state.installed.retain(|v| v.version != version);
+        self.state.installed.push(installed.clone());
+        self.activate(&version)?;
+        self.prune();
+        Ok(UpdateOutcome::Installed(installed))
+    }
+
+    /// Makes `version`, or the version installed before the active one, active again.
+    /// The version rolled back from is held: scheduled updates will not reinstall it.
+    pub fn rollback(&mut self, version: Option<&str>) -> io::Result<InstalledVersion> {
+        let current = self.state.current.as_deref();
+        let target = match version {
+            Some(version) => self.state.installed.iter().find(|v| v.version == version),
+            None => self
+                .state
+                .installed
+                .iter()
+                .rev()
+                .find(|v| Some(v.version.as_str()) != current),
+        }
+        .cloned()
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                match version {
+                    Some(version) => format!("version {version} is not installed"),
+                    None => "no previous version to roll back to".to_string(),
+                },
+            )
+        })?;
+        if Some(target.version.as_str()) == current {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is already active", target.version),
+            ));
+        }
+
+        let previous = current.map(str::to_string);
+        if let Some(previous) = previous
+            && self
+                .state
+                .held
+                .as_ref()
+                .is_none_or(|held| compare_versions(&previous, held) == Ordering::Greater)
+        {
+            self.state.held = Some(previous);
+        }
+        self.activate(&target.version)?;
+        Ok(target)
+    }
+
+    /// The active version when `offered` is not newer than it, or than the held version.
+    fn newer_or_current(&self, offered: &str) -> Option<String> {
+        let current = self.state.current.as_deref()?;
+        let newer = |than: &str| compare_versions(offered, than) == Ordering::Greater;
+        let wanted = newer(current) && self.state.held.as_deref().is_none_or(newer);
+        (!wanted).then(|| current.to_string())
+    }
+
+    fn version_dir(&self, version: &str) -> PathBuf {
+        self.dir.join(VERSIONS_DIR).join(version)
+    }
+
+    /// Local paths and URLs are read alike, relative to the mirror root.
+    fn fetch(&self, source: &str, name: &str) -> io::Result<Vec<u8>> {
+        let limit = self.config.max_bundle_mb * 1024 * 1024;
+        match Source::parse(source) {
+            Source::Http(base) => {
+                let url = format!("{base}/{name}");
+                let agent: ureq::Agent = ureq::Agent::config_builder()
+                    .timeout_global(Some(Duration::from_secs(self.config.timeout_secs)))
+                    .build()
+                    .into();
+                agent
+                    .get(&url)
+                    .call()
+                    .and_then(|mut response| {
+                        response.body_mut().with_config().limit(limit).read_to_vec()
+                    })
+                    .map_err(|e| io::Error::other(format!("{url}: {e}")))
+            }
+            Source::Dir(dir) => read_limited(&dir.join(name), limit),
+            // `name` is the bundle itself or its signature, both given as full paths.
+            Source::Bundle(_) => read_limited(Path::new(name), limit),
+        }
+    }
+
+    fn verify(&self, bundle: &[u8], signature: &[u8]) -> io::Result<()> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let raw = BASE64
+            .decode(String::from_utf8_lossy(signature).trim())
+            .map_err(|_| invalid("signature is not base64"))?;
+        let signature = Signature::from_slice(&raw).map_err(|_| invalid("malformed signature"))?;
+        if self
+            .keys
+            .iter()
+            .any(|key| key.verify_strict(bundle, &signature).is_ok())
+        {
+            Ok(())
+        } else {
+            Err(invalid("bundle signature does not match any public key"))
+        }
+    }
+
+    /// Points `current` at `version` through a rename, so readers see either set, never neither.
+    fn activate(&mut self, version: &str) -> io::Result<()> {
+        let link = self.dir.join(CURRENT_LINK);
+        let tmp = self.dir.join(format!("{CURRENT_LINK}.tmp"));
+        let _ = fs::remove_file(&tmp);
+        symlink(Path::new(VERSIONS_DIR).join(version), &tmp)?;
+        fs::rename(&tmp, &link)?;
+
+        self.state.current = Some(version.to_string());
+        self.save_state()
+    }
+
+    /// Removes the oldest versions beyond `keep_versions`, never the active one.
+    fn prune(&mut self) {
+        let current = self.state.current.clone();
+        let mut previous = self
+            .state
+            .installed
+            .iter()
+            .filter(|v| Some(&v.version) != current.as_ref())
+            .count();
+        let mut kept = Vec::new();
+        for version in self.state.installed.drain(..) {
+            if previous > self.config.keep_versions && Some(&version.version) != current.as_ref() {
+                previous -= 1;
+                let dir = self.dir.join(VERSIONS_DIR).join(&version.version);
+                if let Err(e) = fs::remove_dir_all(&dir) {
+                    println!("[UPDATER](WARN) Cannot remove {}: {e}", dir.display());
+                }
+                continue;
+            }
+            kept.push(version);
+        }
+        self.state.installed = kept;
+        if let Err(e) = self.save_state() {
+            println!("[UPDATER](ERROR) Cannot save the rule store state: {e}");
+        }
+    }
+
+    /// Rewrites the state through a temporary file so a crash never leaves it half written.
+    fn save_state(&self) -> io::Result<()> {
+        let tmp = self.dir.join(format!("{STATE_FILE}.tmp"));
+        let json = serde_json::to_vec_pretty(&self.state).map_err(io::Error::other)?;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o644)
+            .open(&tmp)?;
+        file.write_all(&json)?;
+        file.sync_all()?;
+        fs::rename(tmp, self.dir.join(STATE_FILE))
+    }
+
+    /// Runs `update` at the configured schedule and asks plugins to reload after each install.
+    pub fn spawn(
+        store: Arc<Mutex<Self>>,
+        pm: Arc<Mutex<PluginManager>>,
+        history: Arc<Mutex<HistoryStore>>,
+    ) -> Option<JoinHandle<()>> {
+        let expr = store.lock().unwrap().schedule()?.to_string();
+        let schedule = match parse_schedule(&expr) {
+            Ok(schedule) => schedule,
+            Err(e) => {
+                println!("[UPDATER](ERROR) Bad schedule '{expr}': {e}");
+                return None;
+            }
+        };
+
+        Some(thread::spawn(move || {
+            let mut next_run = schedule.upcoming(Local).next();
+            while let Some(next) = next_run {
+                let now = Local::now();
+                if next > now {
+                    let wait = (next - now).to_std().unwrap_or(MAX_SLEEP);
+                    thread::sleep(wait.min(MAX_SLEEP));
+                    continue;
+                }
+                // Runs missed while the machine was asleep are collapsed into a single one.
+                next_run = schedule.after(&now).next();
+
+                let outcome = store.lock().unwrap().update();
+                match outcome {
+                    Ok(UpdateOutcome::Installed(installed)) => {
+                        println!("[UPDATER](INFO) Rules {} installed", installed.version);
+                        reload_plugins(&pm, &history);
+                    }
+                    Ok(UpdateOutcome::UpToDate(_)) => {}
+                    Err(e) => println!("[UPDATER](ERROR) Update failed: {e}"),
+                }
+            }
+        }))
+    }
+}
+
+/// Asks every running plugin that can reload its rules to do so.
+pub fn reload_plugins(pm: &Mutex<PluginManager>, history: &Mutex<HistoryStore>) {
+    // Lock order is history then plugin manager, as in the scheduler.
+    let mut history = history.lock().unwrap();
+    let mut pm = pm.lock().unwrap();
+    let plugins = pm
+        .list_plugins()
+        .into_iter()
+        .filter(|p| p.functions.iter().any(|f| f == RELOAD_FUNCTION));
+
+    for plugin in plugins {
+        let call = CallPayload {
+            fn_name: RELOAD_FUNCTION.to_string(),
+            args: Vec::new(),
+        };
+        match pm.send_call(plugin.pid, call) {
+            Ok(request_id) => {
+                println!(
+                    "[UPDATER](INFO) Asked {} ({}) to reload its rules",
+                    plugin.name, plugin.pid
+                );
+                if let Err(e) = history.start(
+                    &plugin.name,
+                    plugin.pid,
+                    request_id,
+                    RELOAD_FUNCTION,
+                    &[],
+                    Some(RELOAD_JOB),
+                ) {
+                    println!("[UPDATER](ERROR) Failed to write history: {e}");
+                }
+            }
+            Err(e) => println!(
+                "[UPDATER](ERROR) Cannot ask {} ({}) to reload: {e}",
+                plugin.name, plugin.pid
+            ),
+        }
+    }
+}
+
+fn parse_key(key: &str) -> io::Result<VerifyingKey> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("bad public key {key}: expected 32 base64 encoded bytes"),
+        )
+    };
+    let raw: [u8; 32] = BASE64
+        .decode(key.trim())
+        .map_err(|_| invalid())?
+        .try_into()
+        .map_err(|_| invalid())?;
+    VerifyingKey::from_bytes(&raw).map_err(|_| invalid())
+}
+
+fn read_limited(path: &Path, limit: u64) -> io::Result<Vec<u8>> {
+    let file = File::open(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+    let mut data = Vec::new();
+    file.take(limit + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > limit {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is larger than max_bundle_mb", path.display()),
+        ));
+    }
+    Ok(data)
+}
+
+/// Unpacks regular files and directories only, and only below `dest`.
+/// Fails once the entries add up to more than `limit` bytes, so a small
+/// compressed bundle cannot fill the disk.
+fn unpack(bundle: &[u8], dest: &Path, limit: u64) -> io::Result<()> {
+    fs::create_dir_all(dest)?;
+    let mut archive = tar::Archive::new(GzDecoder::new(bundle));
+    let mut total = 0u64;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        total = total.saturating_add(entry.size());
+        if total > limit {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "bundle unpacks to more than max_unpacked_mb",
+            ));
+        }
+        let path = entry.path()?.into_owned();
+        if !path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bundle entry {} points outside the bundle", path.display()),
+            ));
+        }
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_dir() {
+            fs::create_dir_all(dest.join(&path))?;
+        } else if entry_type.is_file() {
+            if let Some(parent) = dest.join(&path).parent() {
+                fs::create_dir_all(parent)?;
+            }
+            entry.unpack(dest.join(&path))?;
+        }
+    }
+    Ok(())
+}
+
+/// True for a single, non-hidden path component: safe to join to a directory.
+fn is_file_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && !name.contains(['/', '\\'])
+        && name.chars().all(|c| !c.is_control())
+}
+
+fn bundle_source(source: &str, bundle: &str) -> String {
+    match Source::parse(source) {
+        Source::Bundle(_) => bundle.to_string(),
+        Source::Http(base) => format!("{base}/{bundle}"),
+        Source::Dir(dir) => dir.join(bundle).display().to_string(),
+    }
+}
+
+/// Dot and dash separated parts, numbers compared as numbers: `2026.10.2 < 2026.10.10`.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let mut left = a.split(['.', '-']);
+    let mut right = b.split(['.', '-']);
+    loop {
+        let order = match (left.next(), right.next()) {
+            (None, None) => return Ordering::Equal,
+            (Some(_), None) => return Ordering::Greater,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(x), Some(y)) => match (x.parse::<u64>(), y.parse::<u64>()) {
+                (Ok(x), Ok(y)) => x.cmp(&y),
+                _ => x.cmp(y),
+            },
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("griffon-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// Writes the path straight into the header, as tar::Builder refuses `..` and absolute paths.
+    fn bundle(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (path, data) in entries {
+            let mut header = tar::Header::new_old();
+            header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_cksum();
+            builder.append(&header, *data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn versions_compare_by_number() {
+        assert_eq!(compare_versions("2026.10.2", "2026.10.10"), Ordering::Less);
+        assert_eq!(
+            compare_versions("2026.10.19", "2026.10.19"),
+            Ordering::Equal
+        );
+        assert_eq!(
+            compare_versions("2026.10.19-1", "2026.10.19"),
+            Ordering::Greater
+        );
+        assert_eq!(compare_versions("2026.9", "2026.10.1"), Ordering::Less);
+    }
+
+    #[test]
+    fn unpack_keeps_entries_below_dest() {
+        let dest = temp_dir("unpack");
+        unpack(&bundle(&[("rules/a.yar", b"rule a {}")]), &dest, 1024).unwrap();
+        assert_eq!(fs::read(dest.join("rules/a.yar")).unwrap(), b"rule a {}");
+
+        for path in ["../escape", "rules/../../escape", "/tmp/escape"] {
+            let err = unpack(&bundle(&[(path, b"x")]), &dest, 1024).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{path}");
+        }
+        assert!(!dest.parent().unwrap().join("escape").exists());
+        fs::remove_dir_all(dest).unwrap();
+    }
+
+    #[test]
+    fn unpack_stops_at_the_size_limit() {
+        let dest = temp_dir("unpack-limit");
+        let data = [0u8; 600];
+        let err = unpack(&bundle(&[("a", &data), ("b", &data)]), &dest, 1000).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(!dest.join("b").exists());
+        fs::remove_dir_all(dest).unwrap();
+    }
+
+    #[test]
+    fn rollback_holds_until_manual_update() {
+        let dir = temp_dir("rule-store");
+        let mut store = RuleStore::open(&dir, &UpdatesConfig::default()).unwrap();
+        for version in ["1", "2"] {
+            fs::create_dir_all(store.version_dir(version)).unwrap();
+            store.state.installed.push(InstalledVersion {
+                version: version.to_string(),
+                sha256: String::new(),
+                source: String::new(),
+                installed_at: Utc::now(),
+            });
+        }
+        store.activate("2").unwrap();
+
+        assert_eq!(store.rollback(None).unwrap().version, "1");
+        assert_eq!(store.held(), Some("2"));
+        assert_eq!(store.newer_or_current("2").as_deref(), Some("1"));
+        assert_eq!(store.newer_or_current("3"), None);
+
+        // The hold survives a restart.
+        let mut store = RuleStore::open(&dir, &UpdatesConfig::default()).unwrap();
+        assert_eq!(store.held(), Some("2"));
+        // No source is configured, the update fails but the hold is lifted.
+        assert!(store.update_manual().is_err());
+        assert_eq!(store.held(), None);
+        assert_eq!(store.newer_or_current("2"), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,6 +1,24 @@
 use abi_stable::StableAbi;
 use abi_stable::library::RootModule;
+use abi_stable::sabi_extern_fn;
 use abi_stable::std_types::{RResult, RString, RVec, Tuple2};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Set by the plugin manager on every runner, see [`data_dir`].
+pub static DATA_DIR_ENV: &str = "GRIFFON_DATA_DIR";
+/// Data directory of a plugin running outside of the daemon.
+static DEFAULT_DATA_DIR: &str = "./data";
+
+/// Services the runner offers to the plugin it hosts.
+#[repr(C)]
+#[derive(StableAbi, Copy, Clone)]
+pub struct HostApi {
+    /// Forwards a JSON-encoded privileged operation to the core,
+    /// e.g. `{"op":"delete_files","paths":["/var/cache/foo"]}` or
+    /// `{"op":"quarantine_file","path":"/tmp/x","detection":"malware:Evil"}`.
+    pub broker: extern "C" fn(RString) -> RResult<RString, RString>,
+}
 
 #[repr(C)]
 #[derive(StableAbi)]
@@ -8,6 +26,42 @@ use abi_stable::std_types::{RResult, RString, RVec, Tuple2};
 pub struct PluginI {
     pub init: extern "C" fn() -> RResult<RVec<Tuple2<RString, RString>>, RString>,
     pub handle_message: extern "C" fn(RString) -> RString,
+    /// Called by the runner right after loading. Plugins built before it existed simply lack it.
+    pub set_host: extern "C" fn(HostApi),
+    /// Called before the runner exits so the plugin can stop its threads and flush its state.
+    pub shutdown: extern "C" fn() -> RResult<(), RString>,
+}
+
+static HOST: OnceLock<HostApi> = OnceLock::new();
+
+/// Default `set_host` implementation: keeps the host API for [`broker_request`].
+#[sabi_extern_fn]
+pub extern "C" fn install_host(host: HostApi) {
+    let _ = HOST.set(host);
+}
+
+/// `shutdown` implementation for plugins with nothing to release.
+#[sabi_extern_fn]
+pub extern "C" fn noop_shutdown() -> RResult<(), RString> {
+    RResult::ROk(())
+}
+
+/// Asks the core to run a privileged operation, see [`HostApi::broker`].
+pub fn broker_request(request: &str) -> Result<String, String> {
+    let host = HOST
+        .get()
+        .ok_or_else(|| "no host API installed".to_string())?;
+    (host.broker)(RString::from(request))
+        .into_result()
+        .map(|s| s.into_string())
+        .map_err(|e| e.into_string())
+}
+
+/// The daemon data directory, where plugins keep the state they persist.
+pub fn data_dir() -> PathBuf {
+    std::env::var_os(DATA_DIR_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_DIR))
 }
 
 #[repr(C)]
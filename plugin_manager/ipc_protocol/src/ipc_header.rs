@@ -16,6 +16,9 @@ pub enum MsgType {
     Log = 5,
     Heartbeat = 6,
     Error = 7,
+    BrokerRequest = 8,
+    BrokerReply = 9,
+    Shutdown = 10,
 }
 
 impl MsgType {
@@ -28,6 +31,9 @@ impl MsgType {
             5 => MsgType::Log,
             6 => MsgType::Heartbeat,
             7 => MsgType::Error,
+            8 => MsgType::BrokerRequest,
+            9 => MsgType::BrokerReply,
+            10 => MsgType::Shutdown,
             _ => return None,
         })
     }
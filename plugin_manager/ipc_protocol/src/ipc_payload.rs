@@ -28,6 +28,27 @@ pub struct ErrorPayload {
     pub message: String,
 }
 
+/// Privileged operations a runner may ask the core to perform on its behalf.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BrokerOp {
+    /// Remove regular files or symlinks; directories are refused.
+    DeleteFiles { paths: Vec<String> },
+    /// Move a flagged regular file into the quarantine vault.
+    QuarantineFile { path: String, detection: String },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BrokerRequestPayload {
+    pub op: BrokerOp,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BrokerReplyPayload {
+    pub ok: bool,
+    pub output: String,
+}
+
 #[derive(Debug)]
 pub enum Message {
     Hello,
@@ -49,6 +70,19 @@ pub enum Message {
     },
 
     Heartbeat,
+
+    /// Asks the runner to stop its plugin and exit.
+    Shutdown,
+
+    BrokerRequest {
+        request_id: u32,
+        data: BrokerRequestPayload,
+    },
+
+    BrokerReply {
+        request_id: u32,
+        data: BrokerReplyPayload,
+    },
 }
 
 impl Message {
@@ -56,6 +90,7 @@ impl Message {
         match self {
             Message::Hello => Ok(Frame::new(MsgType::Hello, 0, Vec::new())),
             Message::Heartbeat => Ok(Frame::new(MsgType::Heartbeat, 0, Vec::new())),
+            Message::Shutdown => Ok(Frame::new(MsgType::Shutdown, 0, Vec::new())),
 
             Message::HelloOk(p) => Ok(Frame::new(MsgType::HelloOk, 0, to_cbor(&p)?)),
 
@@ -70,6 +105,18 @@ impl Message {
             Message::Error { request_id, data } => {
                 Ok(Frame::new(MsgType::Error, request_id, to_cbor(&data)?))
             }
+
+            Message::BrokerRequest { request_id, data } => Ok(Frame::new(
+                MsgType::BrokerRequest,
+                request_id,
+                to_cbor(&data)?,
+            )),
+
+            Message::BrokerReply { request_id, data } => Ok(Frame::new(
+                MsgType::BrokerReply,
+                request_id,
+                to_cbor(&data)?,
+            )),
         }
     }
 }
@@ -88,6 +135,7 @@ pub fn decode_frame(frame: Frame) -> io::Result<Message> {
     match frame.mtype {
         MsgType::Hello => Ok(Message::Hello),
         MsgType::Heartbeat => Ok(Message::Heartbeat),
+        MsgType::Shutdown => Ok(Message::Shutdown),
 
         MsgType::HelloOk => {
             let p: HelloOkPayload = from_cbor(&frame.payload)?;
@@ -118,6 +166,22 @@ pub fn decode_frame(frame: Frame) -> io::Result<Message> {
             })
         }
 
+        MsgType::BrokerRequest => {
+            let p: BrokerRequestPayload = from_cbor(&frame.payload)?;
+            Ok(Message::BrokerRequest {
+                request_id: frame.request_id,
+                data: p,
+            })
+        }
+
+        MsgType::BrokerReply => {
+            let p: BrokerReplyPayload = from_cbor(&frame.payload)?;
+            Ok(Message::BrokerReply {
+                request_id: frame.request_id,
+                data: p,
+            })
+        }
+
         // You can implement Log later:
         // MsgType::Log => ...
         _ => Err(io::Error::new(
@@ -1,4 +1,9 @@
+mod privileges;
+mod resources;
+
 use nix::libc;
+use nix::sys::signal::{Signal, kill};
+use nix::unistd::Pid;
 use nix::sys::socket::{AddressFamily, SockFlag, SockType, socketpair};
 use std::fs::read_dir;
 use std::io;
@@ -6,16 +11,37 @@ use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd};
 use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
 use std::process::{Child, Command};
+use std::time::{Duration, Instant};
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{Arc, Mutex};
+
+use ipc_protocol::ipc_payload::{
+    BrokerReplyPayload, CallPayload, Message, recv_message, send_message,
+};
+use privileges::RunAs;
+use resources::{CpuSample, Enforcement};
 
-use ipc_protocol::ipc_payload::{CallPayload, Message, recv_message, send_message};
+pub use privileges::{Broker, BrokerCaller, PluginManifest};
+pub use resources::{LimitPolicy, ResourceLimits, ResourceUsage};
 
 static RUNNER_BINARY: &str = "./target/debug/runner";
+/// Tells runners the daemon data directory, read back by `interface::data_dir`.
+static DATA_DIR_ENV: &str = "GRIFFON_DATA_DIR";
+
+/// Default time a runner gets to exit after `Shutdown`, and again after SIGTERM.
+const DEFAULT_SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+const TERM_GRACE: Duration = Duration::from_secs(2);
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
 #[derive(Debug)]
 struct RunningPlugin {
     process: Child,
-    fd: std::os::unix::net::UnixStream,
+    /// Shared with the reader thread, which answers broker requests on it.
+    fd: Arc<Mutex<UnixStream>>,
     pub plugin_info: PluginInfo,
+    cgroup: Option<PathBuf>,
+    last_cpu: Option<CpuSample>,
 }
 
 #[derive(Debug, Clone)]
@@ -24,6 +50,30 @@ pub struct PluginInfo {
     pub name: String,
     pub path: PathBuf,
     pub functions: Vec<String>,
+    /// User the runner was switched to, `None` when it runs as the daemon user.
+    pub run_as: Option<String>,
+}
+
+/// How a call sent with [`PluginManager::send_call`] ended.
+#[derive(Debug, Clone)]
+pub enum CallOutcome {
+    Result { ok: bool, output: String },
+    Error { code: u32, message: String },
+}
+
+/// Events forwarded by the plugin reader threads to the subscriber, if any.
+#[derive(Debug, Clone)]
+pub enum PluginEvent {
+    CallCompleted {
+        pid: u32,
+        plugin: String,
+        request_id: u32,
+        outcome: CallOutcome,
+    },
+    Exited {
+        pid: u32,
+        plugin: String,
+    },
 }
 
 #[derive(Debug, PartialEq, PartialOrd, Copy, Clone)]
@@ -39,6 +89,12 @@ pub struct PluginManager {
     plugins_list: Vec<RunningPlugin>,
     pub log_level: LogLevel,
     next_request_id: u32,
+    events: Option<Sender<PluginEvent>>,
+    limits: LimitPolicy,
+    default_user: Option<String>,
+    broker: Option<Arc<dyn Broker>>,
+    shutdown_grace: Duration,
+    data_dir: Option<PathBuf>,
 }
 
 impl PluginManager {
@@ -48,9 +104,49 @@ impl PluginManager {
             plugins_list: Vec::new(),
             log_level,
             next_request_id: 0,
+            events: None,
+            limits: LimitPolicy::default(),
+            default_user: None,
+            broker: None,
+            shutdown_grace: DEFAULT_SHUTDOWN_GRACE,
+            data_dir: None,
         }
     }
 
+    /// How long a runner may take to exit after being asked to shut down, before SIGTERM.
+    pub fn set_shutdown_grace(&mut self, grace: Duration) {
+        self.shutdown_grace = grace;
+    }
+
+    /// Directory plugins started from now on keep their state under.
+    pub fn set_data_dir<P: AsRef<Path>>(&mut self, dir: P) {
+        self.data_dir = Some(dir.as_ref().to_path_buf());
+    }
+
+    /// User runners are started as when their manifest does not set `run_as`.
+    pub fn set_default_user(&mut self, user: Option<String>) {
+        self.default_user = user;
+    }
+
+    /// Handler for privileged operations requested by plugins started from now on.
+    /// Without one, every broker request is refused.
+    pub fn set_broker(&mut self, broker: Arc<dyn Broker>) {
+        self.broker = Some(broker);
+    }
+
+    /// Sets the limits applied to runners launched from now on.
+    pub fn set_limit_policy(&mut self, policy: LimitPolicy) {
+        self.limits = policy;
+    }
+
+    /// Returns a receiver for call results and plugin exits.
+    /// Only plugins started after this call report to it; calling it again replaces the previous receiver.
+    pub fn subscribe(&mut self) -> Receiver<PluginEvent> {
+        let (tx, rx) = channel();
+        self.events = Some(tx);
+        rx
+    }
+
     fn log(&self, level: LogLevel, msg: &str) {
         if level >= self.log_level {
             match level {
@@ -77,6 +173,36 @@ impl PluginManager {
             .collect()
     }
 
+    /// Samples `/proc` for the runner with the given PID.
+    pub fn plugin_usage(&mut self, pid: u32) -> io::Result<ResourceUsage> {
+        let plugin = self
+            .plugins_list
+            .iter_mut()
+            .find(|p| p.process.id() == pid)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "plugin pid not found"))?;
+
+        let (usage, sample) = resources::sample_usage(pid, plugin.last_cpu)?;
+        plugin.last_cpu = Some(sample);
+        Ok(usage)
+    }
+
+    /// Samples every running plugin.
+    pub fn usage(&mut self) -> Vec<(PluginInfo, io::Result<ResourceUsage>)> {
+        let pids: Vec<u32> = self.plugins_list.iter().map(|p| p.process.id()).collect();
+        pids.into_iter()
+            .map(|pid| {
+                let usage = self.plugin_usage(pid);
+                let info = self
+                    .plugins_list
+                    .iter()
+                    .find(|p| p.process.id() == pid)
+                    .map(|p| p.plugin_info.clone())
+                    .expect("pid taken from plugins_list");
+                (info, usage)
+            })
+            .collect()
+    }
+
     pub fn scan_dir(&mut self) {
         let mut current_paths = Vec::new();
 
@@ -112,18 +238,20 @@ impl PluginManager {
     pub fn kill_plugin(&mut self, pid: u32) {
         if let Some(pos) = self.plugins_list.iter().position(|p| p.process.id() == pid) {
             let mut plugin = self.plugins_list.remove(pos);
-            match plugin.process.kill() {
-                Ok(_) => self.log(LogLevel::Debug, &format!("Plugin PID {pid} killed")),
-                Err(e) => self.log(
-                    LogLevel::Error,
-                    &format!("Failed to kill plugin PID {pid}: {e}"),
-                ),
-            }
+            self.stop_runner(&mut plugin);
+            self.log(LogLevel::Debug, &format!("Plugin PID {pid} stopped"));
         } else {
             self.log(LogLevel::Warn, &format!("No plugin found with PID {pid}"));
         }
     }
 
+    /// Stops every plugin, e.g. before the daemon exits.
+    pub fn shutdown_all(&mut self) {
+        while let Some(mut plugin) = self.plugins_list.pop() {
+            self.stop_runner(&mut plugin);
+        }
+    }
+
     pub fn send_call(&mut self, pid: u32, call: CallPayload) -> io::Result<u32> {
         let request_id = self.alloc_request_id();
 
@@ -138,7 +266,7 @@ impl PluginManager {
             .find(|p| p.process.id() == pid)
             .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "plugin pid not found"))?;
 
-        send_message(&mut plugin.fd, msg)?;
+        send_message(&mut *plugin.fd.lock().unwrap(), msg)?;
 
         Ok(request_id)
     }
@@ -176,7 +304,12 @@ impl PluginManager {
 
         let handshake_res = {
             let last = self.plugins_list.last_mut().unwrap();
-            read_plugin_messages(last, self.log_level)
+            read_plugin_messages(
+                last,
+                self.log_level,
+                self.events.clone(),
+                self.broker.clone(),
+            )
         };
 
         if let Err(e) = handshake_res {
@@ -189,6 +322,7 @@ impl PluginManager {
                 ),
             );
             let _ = bad.process.kill();
+            self.reap(&mut bad);
         }
     }
 
@@ -198,13 +332,56 @@ impl PluginManager {
             LogLevel::Debug,
             &format!("Plugin removed {}", plugin.plugin_info.name),
         );
+        self.stop_runner(&mut plugin);
+    }
+
+    /// Asks the runner to shut down, then escalates to SIGTERM and SIGKILL
+    /// if it is still alive after each grace period.
+    fn stop_runner(&self, plugin: &mut RunningPlugin) {
+        let pid = plugin.process.id();
+
+        let asked = send_message(&mut *plugin.fd.lock().unwrap(), Message::Shutdown);
+        if asked.is_ok() && wait_exit(&mut plugin.process, self.shutdown_grace) {
+            self.reap(plugin);
+            return;
+        }
+
+        self.log(
+            LogLevel::Warn,
+            &format!("Plugin PID {pid} did not shut down in time, sending SIGTERM"),
+        );
+        if kill(Pid::from_raw(pid as i32), Signal::SIGTERM).is_ok()
+            && wait_exit(&mut plugin.process, TERM_GRACE)
+        {
+            self.reap(plugin);
+            return;
+        }
+
+        self.log(LogLevel::Warn, &format!("Plugin PID {pid} killed"));
         if let Err(e) = plugin.process.kill() {
-            self.log(LogLevel::Error, &format!("Failed to kill plugin PID : {e}"));
+            self.log(
+                LogLevel::Error,
+                &format!("Failed to kill plugin PID {pid}: {e}"),
+            );
+        }
+        self.reap(plugin);
+    }
+
+    /// Waits for an exited runner and removes its cgroup.
+    fn reap(&self, plugin: &mut RunningPlugin) {
+        let _ = plugin.process.wait();
+        if let Some(dir) = &plugin.cgroup
+            && let Err(e) = resources::remove_cgroup(dir)
+        {
+            self.log(
+                LogLevel::Warn,
+                &format!("Failed to remove cgroup {}: {e}", dir.display()),
+            );
         }
     }
 
     fn is_shared_library(path: &Path) -> bool {
-        path.is_file() && path.extension().map_or(false, |ext| ext == "so")
+        path.is_file() && path.extension().is_some_and(|ext| ext == "so")
     }
 
     fn launch_runner(&self, plugin_path: &Path) -> Result<RunningPlugin, String> {
@@ -220,29 +397,59 @@ impl PluginManager {
         )
         .map_err(|e| format!("socketpair failed: {e}"))?;
 
+        let manifest = PluginManifest::load(plugin_path).map_err(|e| e.to_string())?;
+        let user = manifest.run_as.as_deref().or(self.default_user.as_deref());
+        let run_as = RunAs::resolve(user, manifest.group.as_deref())?;
+        let run_as_name = run_as.as_ref().map(|r| r.user.clone());
+
+        // Last fallible step before the spawn, whose failure removes the cgroup.
+        let limits = self.limits.limits_for(plugin_path);
+        let enforcement = Enforcement::prepare(&self.limits, plugin_path, limits)?;
+        if enforcement.is_degraded() {
+            self.log(
+                LogLevel::Warn,
+                &format!("cgroup v2 unavailable, CPU limit for {name} only lowers its priority"),
+            );
+        }
+        let cgroup = enforcement.cgroup_dir().map(Path::to_path_buf);
+
         let mut cmd = Command::new(RUNNER_BINARY);
         cmd.arg(path);
+        if let Some(dir) = &self.data_dir {
+            cmd.env(DATA_DIR_ENV, dir);
+        }
 
         unsafe {
             cmd.pre_exec(move || {
                 if libc::dup2(runner_fd.as_raw_fd(), 3) == -1 {
                     return Err(io::Error::last_os_error());
                 }
-                Ok(())
+                enforcement.apply_in_child()?;
+                // Dropping privileges comes last: joining the cgroup needs them.
+                match &run_as {
+                    Some(run_as) => run_as.apply_in_child(),
+                    None => Ok(()),
+                }
             });
         }
 
-        let child = cmd
-            .spawn()
-            .map_err(|e| format!("failed to spawn runner: {e}"))?;
-        let core_stream =
-            unsafe { std::os::unix::net::UnixStream::from_raw_fd(core_fd.into_raw_fd()) };
+        let child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                if let Some(dir) = &cgroup {
+                    let _ = resources::remove_cgroup(dir);
+                }
+                return Err(format!("failed to spawn runner: {e}"));
+            }
+        };
+        let core_stream = unsafe { UnixStream::from_raw_fd(core_fd.into_raw_fd()) };
 
         let plugininfo = PluginInfo {
             pid: child.id(),
             name,
             path: plugin_path.to_path_buf(),
             functions: Vec::new(),
+            run_as: run_as_name,
         };
 
         self.log(
@@ -255,21 +462,42 @@ impl PluginManager {
 
         Ok(RunningPlugin {
             process: child,
-            fd: core_stream,
+            fd: Arc::new(Mutex::new(core_stream)),
             plugin_info: plugininfo,
+            cgroup,
+            last_cpu: None,
         })
     }
 }
 
-fn read_plugin_messages(plugin: &mut RunningPlugin, log_level: LogLevel) -> io::Result<()> {
-    let mut fd_clone = plugin
-        .fd
+/// Polls until the process exits or `timeout` elapses; returns whether it exited.
+fn wait_exit(process: &mut Child, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match process.try_wait() {
+            Ok(Some(_)) => return true,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(EXIT_POLL_INTERVAL),
+            _ => return false,
+        }
+    }
+}
+
+fn read_plugin_messages(
+    plugin: &mut RunningPlugin,
+    log_level: LogLevel,
+    events: Option<Sender<PluginEvent>>,
+    broker: Option<Arc<dyn Broker>>,
+) -> io::Result<()> {
+    let writer = Arc::clone(&plugin.fd);
+    let mut fd_clone = writer
+        .lock()
+        .unwrap()
         .try_clone()
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Failed to clone fd: {e}")))?;
+        .map_err(|e| io::Error::other(format!("Failed to clone fd: {e}")))?;
 
     let pid = plugin.process.id();
 
-    send_message(&mut fd_clone, Message::Hello)?;
+    send_message(&mut *writer.lock().unwrap(), Message::Hello)?;
 
     let hello_ok = match recv_message(&mut fd_clone)? {
         Message::HelloOk(p) => p,
@@ -291,6 +519,16 @@ fn read_plugin_messages(plugin: &mut RunningPlugin, log_level: LogLevel) -> io::
     plugin.plugin_info.functions = hello_ok.functions;
 
     let name = plugin.plugin_info.name.clone();
+    let caller = BrokerCaller {
+        pid,
+        name: name.clone(),
+        library: plugin
+            .plugin_info
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    };
 
     if log_level >= LogLevel::Info {
         println!(
@@ -299,6 +537,12 @@ fn read_plugin_messages(plugin: &mut RunningPlugin, log_level: LogLevel) -> io::
         );
     }
 
+    let emit = move |event: PluginEvent| {
+        if let Some(tx) = &events {
+            let _ = tx.send(event);
+        }
+    };
+
     std::thread::spawn(move || {
         loop {
             let msg = match recv_message(&mut fd_clone) {
@@ -309,6 +553,10 @@ fn read_plugin_messages(plugin: &mut RunningPlugin, log_level: LogLevel) -> io::
                             "[PLUGIN_MANAGER](INFO) Plugin {name} ({pid}) closed / recv error: {e}"
                         );
                     }
+                    emit(PluginEvent::Exited {
+                        pid,
+                        plugin: name.clone(),
+                    });
                     break;
                 }
             };
@@ -321,6 +569,15 @@ fn read_plugin_messages(plugin: &mut RunningPlugin, log_level: LogLevel) -> io::
                             data.ok, data.output
                         );
                     }
+                    emit(PluginEvent::CallCompleted {
+                        pid,
+                        plugin: name.clone(),
+                        request_id,
+                        outcome: CallOutcome::Result {
+                            ok: data.ok,
+                            output: data.output,
+                        },
+                    });
                 }
                 Message::Error { request_id, data } => {
                     if log_level >= LogLevel::Error {
@@ -329,6 +586,39 @@ fn read_plugin_messages(plugin: &mut RunningPlugin, log_level: LogLevel) -> io::
                             data.code, data.message
                         );
                     }
+                    emit(PluginEvent::CallCompleted {
+                        pid,
+                        plugin: name.clone(),
+                        request_id,
+                        outcome: CallOutcome::Error {
+                            code: data.code,
+                            message: data.message,
+                        },
+                    });
+                }
+                Message::BrokerRequest { request_id, data } => {
+                    let reply = match &broker {
+                        Some(broker) => broker.handle(&caller, data.op),
+                        None => Err("privileged operations are disabled".to_string()),
+                    };
+                    if log_level >= LogLevel::Info {
+                        println!(
+                            "[PLUGIN_MANAGER](INFO) Plugin {name} ({pid}) BROKER id={request_id} ok={}",
+                            reply.is_ok()
+                        );
+                    }
+                    let data = match reply {
+                        Ok(output) => BrokerReplyPayload { ok: true, output },
+                        Err(output) => BrokerReplyPayload { ok: false, output },
+                    };
+                    let msg = Message::BrokerReply { request_id, data };
+                    if let Err(e) = send_message(&mut *writer.lock().unwrap(), msg)
+                        && log_level >= LogLevel::Error
+                    {
+                        eprintln!(
+                            "[PLUGIN_MANAGER](ERROR) Plugin {name} ({pid}) failed to send broker reply: {e}"
+                        );
+                    }
                 }
                 Message::Heartbeat => {
                     if log_level >= LogLevel::Debug {
@@ -0,0 +1,108 @@
+use nix::unistd::{Gid, Group, Uid, User, geteuid, setgid, setgroups, setuid};
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ipc_protocol::ipc_payload::BrokerOp;
+
+/// Optional `<plugin>.toml` file installed next to the plugin library.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PluginManifest {
+    /// User the runner is started as. `root` keeps the daemon privileges.
+    pub run_as: Option<String>,
+    /// Group of the runner, defaults to the primary group of `run_as`.
+    pub group: Option<String>,
+}
+
+impl PluginManifest {
+    pub fn path_for(plugin_path: &Path) -> PathBuf {
+        plugin_path.with_extension("toml")
+    }
+
+    /// A missing manifest yields the defaults.
+    pub fn load(plugin_path: &Path) -> io::Result<Self> {
+        let path = Self::path_for(plugin_path);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let raw = fs::read_to_string(&path)?;
+        toml::from_str(&raw).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {e}", path.display()),
+            )
+        })
+    }
+}
+
+/// Identity a runner switches to between `fork` and `exec`.
+#[derive(Debug, Clone)]
+pub(crate) struct RunAs {
+    pub user: String,
+    uid: Uid,
+    gid: Gid,
+}
+
+impl RunAs {
+    /// Resolves the user and group before forking; `Ok(None)` means "keep the current identity".
+    pub(crate) fn resolve(user: Option<&str>, group: Option<&str>) -> Result<Option<Self>, String> {
+        let Some(user) = user else {
+            return Ok(None);
+        };
+
+        let entry = User::from_name(user)
+            .map_err(|e| format!("cannot look up user {user}: {e}"))?
+            .ok_or_else(|| format!("unknown user {user}"))?;
+
+        let gid = match group {
+            Some(group) => {
+                Group::from_name(group)
+                    .map_err(|e| format!("cannot look up group {group}: {e}"))?
+                    .ok_or_else(|| format!("unknown group {group}"))?
+                    .gid
+            }
+            None => entry.gid,
+        };
+
+        if entry.uid == geteuid() {
+            return Ok(None);
+        }
+        if !geteuid().is_root() {
+            return Err(format!(
+                "cannot run as {user}: the daemon is not running as root"
+            ));
+        }
+
+        Ok(Some(Self {
+            user: user.to_string(),
+            uid: entry.uid,
+            gid,
+        }))
+    }
+
+    /// Runs in the forked child right before `exec`, after every step that needs privileges.
+    pub(crate) fn apply_in_child(&self) -> io::Result<()> {
+        setgroups(&[self.gid])?;
+        setgid(self.gid)?;
+        setuid(self.uid)?;
+        Ok(())
+    }
+}
+
+/// Plugin on whose behalf a broker operation is requested.
+#[derive(Debug, Clone)]
+pub struct BrokerCaller {
+    pub pid: u32,
+    pub name: String,
+    /// Library file name, as used in limit and broker policies.
+    pub library: String,
+}
+
+/// Performs privileged operations requested by runners.
+/// Implementations decide what each plugin is allowed to do.
+pub trait Broker: Send + Sync {
+    /// Returns the output sent back to the plugin, or the reason for refusing.
+    fn handle(&self, caller: &BrokerCaller, op: BrokerOp) -> Result<String, String>;
+}
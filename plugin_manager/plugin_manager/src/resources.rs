@@ -0,0 +1,312 @@
+use nix::libc;
+use nix::sys::resource::{Resource, setrlimit};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+static PROC_DIR: &str = "/proc";
+static CGROUP_MOUNT: &str = "/sys/fs/cgroup";
+const CPU_PERIOD_USEC: u64 = 100_000;
+/// Niceness applied when a CPU cap is requested but cgroup v2 is not usable.
+const FALLBACK_NICENESS: libc::c_int = 10;
+
+/// Resources used by a runner process, read from `/proc/<pid>`.
+#[derive(Debug, Clone, Default)]
+pub struct ResourceUsage {
+    /// User + system CPU time since the runner started.
+    pub cpu_time: Duration,
+    /// CPU usage since the previous sample, 100.0 being one full core.
+    /// `None` on the first sample.
+    pub cpu_percent: Option<f32>,
+    pub rss_bytes: u64,
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub open_fds: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ResourceLimits {
+    /// CPU cap in percent of one core (200 = two cores). Needs cgroup v2.
+    pub cpu_percent: Option<u32>,
+    /// `memory.max` under cgroup v2, `RLIMIT_DATA` without it.
+    pub memory_bytes: Option<u64>,
+    pub max_open_files: Option<u64>,
+}
+
+impl ResourceLimits {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Each limit set here, the one of `fallback` otherwise.
+    pub fn or(self, fallback: Self) -> Self {
+        Self {
+            cpu_percent: self.cpu_percent.or(fallback.cpu_percent),
+            memory_bytes: self.memory_bytes.or(fallback.memory_bytes),
+            max_open_files: self.max_open_files.or(fallback.max_open_files),
+        }
+    }
+}
+
+/// Limits applied to runners when they are launched.
+#[derive(Debug, Clone)]
+pub struct LimitPolicy {
+    pub default: ResourceLimits,
+    /// Overrides keyed by plugin library file name (e.g. `libgriffon_cleaner.so`).
+    pub per_plugin: HashMap<String, ResourceLimits>,
+    /// Delegated cgroup v2 directory under which one child group per runner is created.
+    pub cgroup_root: PathBuf,
+}
+
+impl Default for LimitPolicy {
+    fn default() -> Self {
+        Self {
+            default: ResourceLimits::default(),
+            per_plugin: HashMap::new(),
+            cgroup_root: Path::new(CGROUP_MOUNT).join("griffon"),
+        }
+    }
+}
+
+impl LimitPolicy {
+    /// Per-plugin limits override the defaults one by one, unset ones are inherited.
+    pub fn limits_for(&self, plugin_path: &Path) -> ResourceLimits {
+        plugin_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| self.per_plugin.get(n))
+            .map_or(self.default, |limits| limits.or(self.default))
+    }
+}
+
+/// How limits are enforced for one runner, prepared before `fork` so that
+/// `pre_exec` only has to make async-signal-safe calls.
+#[derive(Debug)]
+pub(crate) enum Enforcement {
+    None,
+    Cgroup {
+        dir: PathBuf,
+        procs: CString,
+        rlimits: ResourceLimits,
+    },
+    Rlimit {
+        limits: ResourceLimits,
+    },
+}
+
+impl Enforcement {
+    pub(crate) fn prepare(
+        policy: &LimitPolicy,
+        plugin_path: &Path,
+        limits: ResourceLimits,
+    ) -> Result<Self, String> {
+        if limits.is_empty() {
+            return Ok(Enforcement::None);
+        }
+
+        let name = plugin_path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "plugin".to_string());
+
+        match create_cgroup(&policy.cgroup_root, &name, &limits) {
+            Ok(dir) => {
+                let procs = CString::new(dir.join("cgroup.procs").as_os_str().as_bytes())
+                    .map_err(|e| format!("bad cgroup path: {e}"))?;
+                // Open files are not a cgroup resource, keep the rlimit for them.
+                let rlimits = ResourceLimits {
+                    max_open_files: limits.max_open_files,
+                    ..ResourceLimits::default()
+                };
+                Ok(Enforcement::Cgroup {
+                    dir,
+                    procs,
+                    rlimits,
+                })
+            }
+            Err(_) => Ok(Enforcement::Rlimit { limits }),
+        }
+    }
+
+    pub(crate) fn cgroup_dir(&self) -> Option<&Path> {
+        match self {
+            Enforcement::Cgroup { dir, .. } => Some(dir),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn is_degraded(&self) -> bool {
+        matches!(self, Enforcement::Rlimit { limits } if limits.cpu_percent.is_some())
+    }
+
+    /// Runs in the forked child right before `exec`.
+    pub(crate) fn apply_in_child(&self) -> io::Result<()> {
+        match self {
+            Enforcement::None => Ok(()),
+            Enforcement::Cgroup { procs, rlimits, .. } => {
+                join_cgroup(procs)?;
+                apply_rlimits(rlimits)
+            }
+            Enforcement::Rlimit { limits } => {
+                if limits.cpu_percent.is_some() {
+                    unsafe {
+                        libc::setpriority(libc::PRIO_PROCESS, 0, FALLBACK_NICENESS);
+                    }
+                }
+                apply_rlimits(limits)
+            }
+        }
+    }
+}
+
+fn create_cgroup(root: &Path, name: &str, limits: &ResourceLimits) -> io::Result<PathBuf> {
+    if !Path::new(CGROUP_MOUNT).join("cgroup.controllers").exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "cgroup v2 is not mounted",
+        ));
+    }
+
+    fs::create_dir_all(root)?;
+    // The root only holds runner groups, so it may delegate controllers to them.
+    fs::write(root.join("cgroup.subtree_control"), "+cpu +memory")?;
+
+    let dir = (0u32..)
+        .map(|i| root.join(format!("{name}-{i}")))
+        .find(|p| !p.exists())
+        .expect("unbounded range");
+    fs::create_dir(&dir)?;
+
+    let configured = (|| {
+        if let Some(pct) = limits.cpu_percent {
+            let quota = CPU_PERIOD_USEC * u64::from(pct) / 100;
+            fs::write(dir.join("cpu.max"), format!("{quota} {CPU_PERIOD_USEC}"))?;
+        }
+        if let Some(bytes) = limits.memory_bytes {
+            fs::write(dir.join("memory.max"), bytes.to_string())?;
+        }
+        Ok(())
+    })();
+
+    if let Err(e) = configured {
+        let _ = fs::remove_dir(&dir);
+        return Err(e);
+    }
+    Ok(dir)
+}
+
+fn join_cgroup(procs: &CString) -> io::Result<()> {
+    unsafe {
+        let fd = libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // Writing "0" moves the calling process.
+        let written = libc::write(fd, b"0".as_ptr().cast(), 1);
+        libc::close(fd);
+        if written != 1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Memory is capped through `RLIMIT_DATA`, not `RLIMIT_AS`: YARA runners reserve
+/// gigabytes of address space they never touch, which `RLIMIT_AS` would count.
+fn apply_rlimits(limits: &ResourceLimits) -> io::Result<()> {
+    if let Some(bytes) = limits.memory_bytes {
+        setrlimit(Resource::RLIMIT_DATA, bytes, bytes)?;
+    }
+    if let Some(files) = limits.max_open_files {
+        setrlimit(Resource::RLIMIT_NOFILE, files, files)?;
+    }
+    Ok(())
+}
+
+/// Removes a runner cgroup once its process has exited.
+pub(crate) fn remove_cgroup(dir: &Path) -> io::Result<()> {
+    fs::remove_dir(dir)
+}
+
+/// CPU time of the previous sample, used to compute `cpu_percent`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CpuSample {
+    at: Instant,
+    cpu_time: Duration,
+}
+
+pub(crate) fn sample_usage(
+    pid: u32,
+    previous: Option<CpuSample>,
+) -> io::Result<(ResourceUsage, CpuSample)> {
+    let proc_dir = Path::new(PROC_DIR).join(pid.to_string());
+
+    let cpu_time = read_cpu_time(&proc_dir)?;
+    let now = Instant::now();
+    let cpu_percent = previous.and_then(|prev| {
+        let wall = now.duration_since(prev.at).as_secs_f32();
+        (wall > 0.0).then(|| cpu_time.saturating_sub(prev.cpu_time).as_secs_f32() / wall * 100.0)
+    });
+
+    // /proc/<pid>/io is only readable by the owner (or root), keep the sample without it.
+    let (read_bytes, write_bytes) = read_io(&proc_dir).unwrap_or((0, 0));
+
+    let usage = ResourceUsage {
+        cpu_time,
+        cpu_percent,
+        rss_bytes: read_rss(&proc_dir)?,
+        read_bytes,
+        write_bytes,
+        open_fds: fs::read_dir(proc_dir.join("fd"))?.count(),
+    };
+    Ok((usage, CpuSample { at: now, cpu_time }))
+}
+
+fn read_cpu_time(proc_dir: &Path) -> io::Result<Duration> {
+    let stat = fs::read_to_string(proc_dir.join("stat"))?;
+    // The command name may contain spaces and parentheses, fields start after the last ')'.
+    let after_comm = stat
+        .rsplit_once(')')
+        .map(|(_, rest)| rest)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed stat"))?;
+    let fields: Vec<&str> = after_comm.split_whitespace().collect();
+
+    // utime and stime are fields 14 and 15 of stat, i.e. 11 and 12 after the state field.
+    let parse = |i: usize| -> io::Result<u64> {
+        fields
+            .get(i)
+            .and_then(|f| f.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed stat"))
+    };
+    let ticks = parse(11)? + parse(12)?;
+
+    let hz = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as u64;
+    Ok(Duration::from_micros(ticks * 1_000_000 / hz))
+}
+
+fn read_rss(proc_dir: &Path) -> io::Result<u64> {
+    let status = fs::read_to_string(proc_dir.join("status"))?;
+    let kib = status
+        .lines()
+        .find_map(|l| l.strip_prefix("VmRSS:"))
+        .and_then(|v| v.split_whitespace().next())
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(0);
+    Ok(kib * 1024)
+}
+
+fn read_io(proc_dir: &Path) -> io::Result<(u64, u64)> {
+    let io_stats = fs::read_to_string(proc_dir.join("io"))?;
+    let field = |name: &str| {
+        io_stats
+            .lines()
+            .find_map(|l| l.strip_prefix(name))
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .unwrap_or(0)
+    };
+    Ok((field("read_bytes:"), field("write_bytes:")))
+}
@@ -1,15 +1,116 @@
 use abi_stable::library::lib_header_from_path;
 use abi_stable::std_types::{RResult, RString, Tuple2};
-use interface::{PluginRef, PluginRoot_Ref};
+use interface::{HostApi, PluginRef, PluginRoot_Ref};
 
 use ipc_protocol::ipc_payload::{
-    recv_message, send_message, CallPayload, ErrorPayload, HelloOkPayload, Message, ResultPayload,
+    recv_message, send_message, BrokerOp, BrokerReplyPayload, BrokerRequestPayload, CallPayload,
+    ErrorPayload, HelloOkPayload, Message, ResultPayload,
 };
 
+use std::collections::HashMap;
 use std::io;
 use std::os::fd::FromRawFd;
+use std::os::unix::net::UnixStream;
 use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+/// How long a plugin waits for the core to answer a broker request.
+const BROKER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Socket to the core, shared by the main loop and plugin threads using the broker.
+struct CoreLink {
+    writer: Mutex<UnixStream>,
+    pending: Mutex<HashMap<u32, Sender<BrokerReplyPayload>>>,
+    next_broker_id: AtomicU32,
+}
+
+static CORE: OnceLock<CoreLink> = OnceLock::new();
+
+impl CoreLink {
+    fn send(&self, msg: Message) -> io::Result<()> {
+        send_message(&mut *self.writer.lock().unwrap(), msg)
+    }
+}
+
+/// Reads the socket on its own thread: broker replies wake the waiting plugin
+/// thread, everything else goes to the main loop.
+fn spawn_reader(mut sock: UnixStream, fallback_name: String) -> Receiver<Message> {
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        loop {
+            let msg = match recv_message(&mut sock) {
+                Ok(m) => m,
+                Err(e) => {
+                    eprintln!("[RUNNER {fallback_name}](INFO) IPC closed / recv error: {e}");
+                    break;
+                }
+            };
+
+            match msg {
+                Message::BrokerReply { request_id, data } => {
+                    let waiter = CORE
+                        .get()
+                        .and_then(|core| core.pending.lock().unwrap().remove(&request_id));
+                    if let Some(waiter) = waiter {
+                        let _ = waiter.send(data);
+                    }
+                }
+                other => {
+                    if tx.send(other).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        // Wake up plugin threads still waiting on the broker.
+        if let Some(core) = CORE.get() {
+            core.pending.lock().unwrap().clear();
+        }
+    });
+
+    rx
+}
+
+extern "C" fn host_broker(request: RString) -> RResult<RString, RString> {
+    match broker_call(request.as_str()) {
+        Ok(output) => RResult::ROk(RString::from(output)),
+        Err(e) => RResult::RErr(RString::from(e)),
+    }
+}
+
+fn broker_call(request: &str) -> Result<String, String> {
+    let op: BrokerOp =
+        serde_json::from_str(request).map_err(|e| format!("invalid broker request: {e}"))?;
+    let core = CORE.get().ok_or("core link not ready")?;
+
+    let request_id = core.next_broker_id.fetch_add(1, Ordering::Relaxed);
+    let (tx, rx) = mpsc::channel();
+    core.pending.lock().unwrap().insert(request_id, tx);
+
+    let sent = core.send(Message::BrokerRequest {
+        request_id,
+        data: BrokerRequestPayload { op },
+    });
+    if let Err(e) = sent {
+        core.pending.lock().unwrap().remove(&request_id);
+        return Err(format!("failed to send broker request: {e}"));
+    }
+
+    match rx.recv_timeout(BROKER_TIMEOUT) {
+        Ok(reply) if reply.ok => Ok(reply.output),
+        Ok(reply) => Err(reply.output),
+        Err(_) => {
+            core.pending.lock().unwrap().remove(&request_id);
+            Err("broker request timed out or core disconnected".to_string())
+        }
+    }
+}
 
 struct LoadedPlugin {
     root: PluginRoot_Ref,
@@ -58,6 +159,27 @@ fn spawn_start_if_exists(plugin: &mut LoadedPlugin) {
     }
 }
 
+fn install_host_api(plugin: &LoadedPlugin) {
+    let plugin_ref: &PluginRef = &plugin.root.plugin();
+    if let Some(set_host) = plugin_ref.set_host() {
+        set_host(HostApi {
+            broker: host_broker,
+        });
+    }
+}
+
+fn shutdown_plugin(plugin: &LoadedPlugin) {
+    let file_name = plugin.path.display().to_string();
+    let plugin_ref: &PluginRef = &plugin.root.plugin();
+
+    let Some(shutdown_fn) = plugin_ref.shutdown() else {
+        return;
+    };
+    if let RResult::RErr(e) = shutdown_fn() {
+        eprintln!("[RUNNER {file_name}] shutdown() ERROR: {}", e.as_str());
+    }
+}
+
 fn load_plugin(path: &Path) -> Result<LoadedPlugin, String> {
     let header = lib_header_from_path(path).map_err(|e| format!("header load failed: {e}"))?;
 
@@ -74,11 +196,11 @@ fn load_plugin(path: &Path) -> Result<LoadedPlugin, String> {
 }
 
 fn is_shared_library(path: &Path) -> bool {
-    path.is_file() && path.extension().map_or(false, |ext| ext == "so")
+    path.is_file() && path.extension().is_some_and(|ext| ext == "so")
 }
 
 fn parse_functions(s: &str) -> Vec<String> {
-    s.split(|c| c == '/' || c == ',')
+    s.split(['/', ','])
         .map(|x| x.trim().to_string())
         .filter(|x| !x.is_empty())
         .collect()
@@ -145,24 +267,27 @@ fn main() {
         exit(1);
     });
 
-    spawn_start_if_exists(&mut plugin);
-
     let fd = 3;
-    let mut sock = unsafe { std::os::unix::net::UnixStream::from_raw_fd(fd) };
+    let sock = unsafe { UnixStream::from_raw_fd(fd) };
+    let reader = sock.try_clone().unwrap_or_else(|e| {
+        eprintln!("[RUNNER](ERROR) Failed to clone IPC socket: {e}");
+        exit(1);
+    });
+    let core = CORE.get_or_init(|| CoreLink {
+        writer: Mutex::new(sock),
+        pending: Mutex::new(HashMap::new()),
+        next_broker_id: AtomicU32::new(1),
+    });
+    let inbox = spawn_reader(reader, fallback_name.clone());
 
-    loop {
-        let msg = match recv_message(&mut sock) {
-            Ok(m) => m,
-            Err(e) => {
-                eprintln!("[RUNNER {fallback_name}](INFO) IPC closed / recv error: {e}");
-                break;
-            }
-        };
+    install_host_api(&plugin);
+    spawn_start_if_exists(&mut plugin);
 
+    for msg in inbox {
         match msg {
             Message::Hello => {
                 let payload = build_hello_ok(&plugin, &fallback_name);
-                if let Err(e) = send_message(&mut sock, Message::HelloOk(payload)) {
+                if let Err(e) = core.send(Message::HelloOk(payload)) {
                     eprintln!("[RUNNER {fallback_name}](ERROR) failed to send HelloOk: {e}");
                     break;
                 }
@@ -171,9 +296,7 @@ fn main() {
             Message::Call { request_id, data } => match handle_call(&plugin, data) {
                 Ok(output) => {
                     let res = ResultPayload { ok: true, output };
-                    if let Err(e) =
-                        send_message(&mut sock, Message::Result { request_id, data: res })
-                    {
+                    if let Err(e) = core.send(Message::Result { request_id, data: res }) {
                         eprintln!(
                             "[RUNNER {fallback_name}](ERROR) failed to send Result (id={request_id}): {e}"
                         );
@@ -185,13 +308,10 @@ fn main() {
                         code: 1,
                         message: e.to_string(),
                     };
-                    let _ = send_message(
-                        &mut sock,
-                        Message::Error {
-                            request_id,
-                            data: err,
-                        },
-                    );
+                    let _ = core.send(Message::Error {
+                        request_id,
+                        data: err,
+                    });
                 }
             },
 
@@ -199,6 +319,11 @@ fn main() {
                 // TODO : Heartbeat
             }
 
+            Message::Shutdown => {
+                eprintln!("[RUNNER {fallback_name}](INFO) Shutdown requested");
+                break;
+            }
+
             other => {
                 eprintln!(
                     "[RUNNER {fallback_name}](WARN) Unexpected message from core: {:?}",
@@ -207,4 +332,7 @@ fn main() {
             }
         }
     }
+
+    // Also reached when the core goes away, so the plugin always gets a chance to clean up.
+    shutdown_plugin(&plugin);
 }
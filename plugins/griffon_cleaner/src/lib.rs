@@ -17,7 +17,7 @@ use abi_stable::{
     sabi_extern_fn,
     std_types::{RResult, RString, RVec, Tuple2},
 };
-use interface::{PluginI, PluginRoot, PluginRoot_Ref};
+use interface::{PluginI, PluginRoot, PluginRoot_Ref, install_host, noop_shutdown};
 
 pub type CleanerResult<T> = Result<T, CleanerError>;
 
@@ -246,6 +246,8 @@ pub fn get_library() -> PluginRoot_Ref {
         plugin: PluginI {
             init,
             handle_message,
+            set_host: install_host,
+            shutdown: noop_shutdown,
         }
             .leak_into_prefix(),
     }
@@ -4,6 +4,7 @@ use crate::{CleanerModule, CleanerResult, ExecutionContext, ModuleReport};
 use crate::cache_paths::{KNOWN_CACHE_PATHS, expand_home, CacheCategory};
 use std::{fs};
 use std::path::{Path};
+use std::io::ErrorKind;
 use walkdir::WalkDir;
 use std::collections::hash_map::Entry;
 use crate::TypeStats;
@@ -85,7 +86,7 @@ impl CacheCleaner {
                 let size = metadata.len();
 
                 if !dry_run {
-                    if let Err(e) = fs::remove_file(file_path) {
+                    if let Err(e) = remove_file(file_path) {
                         report.warnings.push(format!(
                             "Impossible de supprimer {}: {e}",
                             file_path.display()
@@ -133,6 +134,28 @@ impl CacheCleaner {
     }
 }
 
+/// Supprime un fichier, en passant par le broker du daemon si le plugin n'a pas les droits
+/// (ex: /var/cache quand le runner tourne sans root).
+fn remove_file(path: &Path) -> Result<(), String> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+            let request = serde_json::json!({
+                "op": "delete_files",
+                "paths": [path],
+            });
+            let output = interface::broker_request(&request.to_string())?;
+            let reply: serde_json::Value =
+                serde_json::from_str(&output).map_err(|e| e.to_string())?;
+            match reply["failed"].as_array().and_then(|f| f.first()) {
+                Some(failure) => Err(failure["error"].as_str().unwrap_or("refusé").to_string()),
+                None => Ok(()),
+            }
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
 impl CleanerModule for CacheCleaner {
     fn id(&self) -> &'static str {
         "cache"
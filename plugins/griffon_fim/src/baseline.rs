@@ -0,0 +1,219 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use walkdir::WalkDir;
+
+use crate::config::FimConfig;
+
+/// Bumped whenever `Baseline` changes in a way older files cannot be read as.
+const FORMAT_VERSION: u32 = 1;
+const HASH_BUFFER: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+    /// Devices, sockets and fifos: only their metadata is recorded.
+    Other,
+}
+
+/// What is known about one path: enough to tell whether it was replaced,
+/// rewritten, re-permissioned or handed to another owner.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileRecord {
+    pub kind: FileKind,
+    /// Permission bits, setuid, setgid and sticky included.
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    pub mtime: i64,
+    /// Regular files only, missing when the file could not be read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Symlinks only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+/// A snapshot of the configured paths. It keeps the paths and exclusions it
+/// was taken with so later checks compare the same set of files.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Baseline {
+    pub version: u32,
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+    pub paths: Vec<PathBuf>,
+    pub exclude: Vec<String>,
+    pub one_file_system: bool,
+    pub files: BTreeMap<String, FileRecord>,
+}
+
+impl Baseline {
+    /// Walks `paths`, recording every entry. Entries that cannot be read are
+    /// reported in the returned errors, files that cannot be hashed are kept without a hash.
+    pub fn take(
+        paths: &[PathBuf],
+        exclude: &[String],
+        one_file_system: bool,
+    ) -> Result<(Self, Vec<String>), String> {
+        let excluded = glob_set(exclude)?;
+        let mut files = BTreeMap::new();
+        let mut errors = Vec::new();
+
+        for root in paths {
+            let walk = WalkDir::new(root)
+                .follow_links(false)
+                .same_file_system(one_file_system)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_entry(|e| !excluded.is_match(e.path()));
+            for entry in walk {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        errors.push(e.to_string());
+                        continue;
+                    }
+                };
+                match record(entry.path()) {
+                    Ok((record, error)) => {
+                        errors.extend(error);
+                        files.insert(entry.path().display().to_string(), record);
+                    }
+                    Err(e) => errors.push(format!("{}: {e}", entry.path().display())),
+                }
+            }
+        }
+
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let baseline = Self {
+            version: FORMAT_VERSION,
+            created,
+            paths: paths.to_vec(),
+            exclude: exclude.to_vec(),
+            one_file_system,
+            files,
+        };
+        Ok((baseline, errors))
+    }
+
+    /// A new snapshot of the files this baseline covers.
+    pub fn retake(&self) -> Result<(Self, Vec<String>), String> {
+        Self::take(&self.paths, &self.exclude, self.one_file_system)
+    }
+
+    pub fn from_config(config: &FimConfig) -> Result<(Self, Vec<String>), String> {
+        Self::take(&config.paths, &config.exclude, config.one_file_system)
+    }
+
+    /// `Ok(None)` when no baseline was taken yet.
+    pub fn load(path: &Path) -> Result<Option<Self>, String> {
+        let raw = match fs::read(path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("{}: {e}", path.display())),
+        };
+        let baseline: Self =
+            serde_json::from_slice(&raw).map_err(|e| format!("{}: {e}", path.display()))?;
+        if baseline.version != FORMAT_VERSION {
+            return Err(format!(
+                "{} has format {}, expected {FORMAT_VERSION}: take a new baseline",
+                path.display(),
+                baseline.version
+            ));
+        }
+        Ok(Some(baseline))
+    }
+
+    /// Writes the baseline through a temporary file next to it, so a crash
+    /// never leaves a truncated one behind.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        fs::rename(tmp, path)
+    }
+}
+
+fn glob_set(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).map_err(|e| format!("bad pattern {pattern}: {e}"))?);
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// The record of `path` and, for a file that could not be hashed, why.
+fn record(path: &Path) -> io::Result<(FileRecord, Option<String>)> {
+    let metadata = fs::symlink_metadata(path)?;
+    let file_type = metadata.file_type();
+    let kind = if file_type.is_file() {
+        FileKind::File
+    } else if file_type.is_dir() {
+        FileKind::Dir
+    } else if file_type.is_symlink() {
+        FileKind::Symlink
+    } else {
+        FileKind::Other
+    };
+
+    let mut error = None;
+    let sha256 = match kind {
+        FileKind::File => match hash(path) {
+            Ok(digest) => Some(digest),
+            Err(e) => {
+                error = Some(format!("{}: {e}", path.display()));
+                None
+            }
+        },
+        _ => None,
+    };
+    let target = match kind {
+        FileKind::Symlink => Some(fs::read_link(path)?.display().to_string()),
+        _ => None,
+    };
+
+    let record = FileRecord {
+        kind,
+        mode: metadata.mode() & 0o7777,
+        uid: metadata.uid(),
+        gid: metadata.gid(),
+        size: metadata.size(),
+        mtime: metadata.mtime(),
+        sha256,
+        target,
+    };
+    Ok((record, error))
+}
+
+fn hash(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; HASH_BUFFER];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => break,
+            n => hasher.update(&buffer[..n]),
+        }
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
@@ -0,0 +1,92 @@
+use serde::Deserialize;
+use static_analysis::{RuleFilter, ScanOptions};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Looked up in the working directory when `GRIFFON_FIM_CONFIG` is not set.
+static DEFAULT_CONFIG_PATH: &str = "./griffon_fim.toml";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FimConfig {
+    /// Files and directories recorded by `baseline`, directories recursively.
+    pub paths: Vec<PathBuf>,
+    /// Globs matched against full paths, matching files and directories are left out.
+    pub exclude: Vec<String>,
+    /// Do not descend into directories on another filesystem than the path.
+    pub one_file_system: bool,
+    /// Where the baseline is kept between runs, by default `fim/baseline.json`
+    /// in the daemon data directory.
+    pub baseline_path: PathBuf,
+    /// Scan added and modified files with the rules, hash feeds and heuristics.
+    pub scan_changed: bool,
+    pub rules_dir: PathBuf,
+    pub cache_dir: PathBuf,
+    pub hashes_dir: PathBuf,
+    /// Larger changed files are reported but not scanned.
+    pub max_file_size_mb: u64,
+    pub categories: RuleFilter,
+}
+
+impl Default for FimConfig {
+    fn default() -> Self {
+        Self {
+            paths: [
+                "/etc",
+                "/usr/bin",
+                "/usr/sbin",
+                "/usr/local/bin",
+                "/usr/local/sbin",
+            ]
+            .iter()
+            .map(PathBuf::from)
+            .collect(),
+            exclude: vec!["/etc/adjtime".to_string(), "**/*.swp".to_string()],
+            one_file_system: true,
+            baseline_path: interface::data_dir().join("fim").join("baseline.json"),
+            scan_changed: true,
+            rules_dir: interface::data_dir().join("rules/current/rules"),
+            cache_dir: interface::data_dir().join("yara-cache"),
+            hashes_dir: interface::data_dir().join("rules/current/hashes"),
+            max_file_size_mb: 64,
+            categories: RuleFilter::default(),
+        }
+    }
+}
+
+impl FimConfig {
+    /// Loads the config from `GRIFFON_FIM_CONFIG` or `./griffon_fim.toml`.
+    /// A missing file is not an error: the defaults are used instead.
+    pub fn load() -> io::Result<Self> {
+        let path = std::env::var_os("GRIFFON_FIM_CONFIG")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH));
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::from_file(&path)
+    }
+
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let raw = fs::read_to_string(path)?;
+        toml::from_str(&raw).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {e}", path.display()),
+            )
+        })
+    }
+
+    /// Changed files are scanned one at a time, archives as raw bytes.
+    pub fn scan_options(&self) -> ScanOptions {
+        ScanOptions {
+            threads: 1,
+            max_file_size: Some(self.max_file_size_mb * 1024 * 1024),
+            archives: None,
+            filter: self.categories.clone(),
+            ..ScanOptions::default()
+        }
+    }
+}
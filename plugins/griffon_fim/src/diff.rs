@@ -0,0 +1,255 @@
+use serde::Serialize;
+use static_analysis::{FileScanner, HashDatabase, ScanResult, load_yara_rules_cached};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::baseline::{Baseline, FileKind, FileRecord};
+use crate::config::FimConfig;
+
+/// One path that differs from the baseline.
+#[derive(Debug, Serialize)]
+pub struct Change {
+    pub path: String,
+    /// What differs for a modified path: `type`, `content`, `target`, `mode`, `owner`, `group`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changed: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<FileRecord>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<FileRecord>,
+    /// Added and rewritten regular files, when `scan_changed` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scan: Option<ScanResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scan_error: Option<String>,
+}
+
+impl Change {
+    fn new(path: &str, before: Option<&FileRecord>, after: Option<&FileRecord>) -> Self {
+        Self {
+            path: path.to_string(),
+            changed: Vec::new(),
+            before: before.cloned(),
+            after: after.cloned(),
+            scan: None,
+            scan_error: None,
+        }
+    }
+
+    fn detected(&self) -> bool {
+        self.scan.as_ref().is_some_and(|r| !r.is_clean())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckReport {
+    /// When the baseline compared against was taken, seconds since the Unix epoch.
+    pub baseline_created: u64,
+    pub files_checked: usize,
+    pub added: Vec<Change>,
+    pub removed: Vec<Change>,
+    pub modified: Vec<Change>,
+    /// Changed files the static analyzer detects as malicious.
+    pub detected: usize,
+    /// The current state replaced the baseline, these changes will not be reported again.
+    pub accepted: bool,
+    /// Paths that could not be walked or hashed, usually for lack of privileges.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+/// Compares the files covered by `baseline` with their current state.
+/// With `accept`, the current state becomes the baseline once reported.
+pub fn check(config: &FimConfig, baseline: &Baseline, accept: bool) -> Result<CheckReport, String> {
+    let (current, errors) = baseline.retake()?;
+
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    let mut modified = Vec::new();
+    for (path, before) in &baseline.files {
+        match current.files.get(path) {
+            None => removed.push(Change::new(path, Some(before), None)),
+            Some(after) => {
+                let changed = differences(before, after);
+                if !changed.is_empty() {
+                    modified.push(Change {
+                        changed,
+                        ..Change::new(path, Some(before), Some(after))
+                    });
+                }
+            }
+        }
+    }
+    for (path, after) in &current.files {
+        if !baseline.files.contains_key(path) {
+            added.push(Change::new(path, None, Some(after)));
+        }
+    }
+
+    if config.scan_changed {
+        scan_changes(config, added.iter_mut().chain(modified.iter_mut()));
+    }
+
+    let accepted = accept
+        && match current.save(&config.baseline_path) {
+            Ok(()) => true,
+            Err(e) => {
+                println!("[FIM](ERROR) Cannot save the baseline: {e}");
+                false
+            }
+        };
+
+    let detected = added
+        .iter()
+        .chain(&modified)
+        .filter(|c| c.detected())
+        .count();
+    Ok(CheckReport {
+        baseline_created: baseline.created,
+        files_checked: current.files.len(),
+        added,
+        removed,
+        modified,
+        detected,
+        accepted,
+        errors,
+    })
+}
+
+/// A file that could not be hashed on either side is compared by size and
+/// modification time instead.
+fn differences(before: &FileRecord, after: &FileRecord) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    let rewritten = match (&before.sha256, &after.sha256) {
+        (Some(old), Some(new)) => old != new,
+        _ => before.size != after.size || before.mtime != after.mtime,
+    };
+    if before.kind != after.kind {
+        changed.push("type");
+    } else if before.kind == FileKind::File && rewritten {
+        changed.push("content");
+    } else if before.target != after.target {
+        changed.push("target");
+    }
+    if before.mode != after.mode {
+        changed.push("mode");
+    }
+    if before.uid != after.uid {
+        changed.push("owner");
+    }
+    if before.gid != after.gid {
+        changed.push("group");
+    }
+    changed
+}
+
+/// Added regular files and files whose contents changed.
+fn needs_scan(change: &Change) -> bool {
+    change
+        .after
+        .as_ref()
+        .is_some_and(|after| after.kind == FileKind::File)
+        && (change.before.is_none()
+            || change
+                .changed
+                .iter()
+                .any(|c| matches!(*c, "type" | "content")))
+}
+
+/// The rules are only loaded when there is something to scan.
+fn scan_changes<'c>(config: &FimConfig, changes: impl Iterator<Item = &'c mut Change>) {
+    let changes: Vec<&mut Change> = changes.filter(|c| needs_scan(c)).collect();
+    if changes.is_empty() {
+        return;
+    }
+
+    let (rules, _) = load_yara_rules_cached(&config.rules_dir, &config.cache_dir);
+    let mut options = config.scan_options();
+    match HashDatabase::load_dir(&config.hashes_dir) {
+        Ok(hashes) if !hashes.is_empty() => options.hashes = Some(Arc::new(hashes)),
+        Ok(_) => {}
+        Err(e) => println!("[FIM](ERROR) Cannot load hash feeds: {e}"),
+    }
+    let mut scanner = FileScanner::new(&rules, &options);
+
+    for change in changes {
+        match scanner.scan_file(Path::new(&change.path)) {
+            // The ELF details would drown the report, its findings are kept.
+            Ok(result) => {
+                change.scan = Some(ScanResult {
+                    elf: None,
+                    ..result
+                })
+            }
+            Err(e) => change.scan_error = Some(e.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(sha256: Option<&str>) -> FileRecord {
+        FileRecord {
+            kind: FileKind::File,
+            mode: 0o644,
+            uid: 0,
+            gid: 0,
+            size: 10,
+            mtime: 1_700_000_000,
+            sha256: sha256.map(str::to_string),
+            target: None,
+        }
+    }
+
+    #[test]
+    fn hashes_decide_when_both_are_known() {
+        let before = file(Some("aa"));
+        assert!(differences(&before, &file(Some("aa"))).is_empty());
+        assert_eq!(differences(&before, &file(Some("bb"))), ["content"]);
+        // Touched but identical: not a rewrite.
+        let touched = FileRecord {
+            mtime: 1_800_000_000,
+            ..file(Some("aa"))
+        };
+        assert!(differences(&before, &touched).is_empty());
+    }
+
+    #[test]
+    fn unhashed_files_fall_back_to_size_and_mtime() {
+        let before = file(None);
+        assert!(differences(&before, &file(Some("aa"))).is_empty());
+        let grown = FileRecord {
+            size: 20,
+            ..file(Some("aa"))
+        };
+        assert_eq!(differences(&before, &grown), ["content"]);
+        let touched = FileRecord {
+            mtime: 1_800_000_000,
+            ..file(None)
+        };
+        assert_eq!(differences(&file(Some("aa")), &touched), ["content"]);
+    }
+
+    #[test]
+    fn metadata_changes_are_listed() {
+        let changed = FileRecord {
+            mode: 0o4755,
+            uid: 1000,
+            gid: 1000,
+            ..file(Some("aa"))
+        };
+        assert_eq!(
+            differences(&file(Some("aa")), &changed),
+            ["mode", "owner", "group"]
+        );
+        let link = FileRecord {
+            kind: FileKind::Symlink,
+            sha256: None,
+            target: Some("/tmp/x".to_string()),
+            ..file(None)
+        };
+        assert_eq!(differences(&file(Some("aa")), &link), ["type"]);
+    }
+}
@@ -0,0 +1,139 @@
+mod baseline;
+mod config;
+mod diff;
+
+use abi_stable::{
+    export_root_module,
+    prefix_type::PrefixTypeTrait,
+    sabi_extern_fn,
+    std_types::{RResult, RString, RVec, Tuple2},
+};
+use interface::{PluginI, PluginRoot, PluginRoot_Ref, install_host, noop_shutdown};
+use serde::Serialize;
+
+use baseline::Baseline;
+use config::FimConfig;
+
+#[derive(Debug, Serialize)]
+struct BaselineReport {
+    baseline_path: String,
+    files: usize,
+    /// Paths that could not be walked or hashed, usually for lack of privileges.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<String>,
+}
+
+fn to_json<T: Serialize>(value: &T) -> RString {
+    match serde_json::to_string(value) {
+        Ok(json) => RString::from(json),
+        Err(e) => RString::from(format!("ERR json serialize: {e}")),
+    }
+}
+
+/// The config is read on each call, like the persistence audit.
+fn load_config() -> FimConfig {
+    FimConfig::load().unwrap_or_else(|e| {
+        println!("[FIM](ERROR) Bad config, using defaults: {e}");
+        FimConfig::default()
+    })
+}
+
+/// Records the configured paths, replacing any previous baseline.
+fn take_baseline() -> Result<BaselineReport, String> {
+    let config = load_config();
+    let (baseline, errors) = Baseline::from_config(&config)?;
+    baseline
+        .save(&config.baseline_path)
+        .map_err(|e| format!("cannot save {}: {e}", config.baseline_path.display()))?;
+    println!(
+        "[FIM](INFO) Baseline of {} files saved to {}",
+        baseline.files.len(),
+        config.baseline_path.display()
+    );
+    Ok(BaselineReport {
+        baseline_path: config.baseline_path.display().to_string(),
+        files: baseline.files.len(),
+        errors,
+    })
+}
+
+fn run_check(accept: bool) -> Result<diff::CheckReport, String> {
+    let config = load_config();
+    let baseline = Baseline::load(&config.baseline_path)?.ok_or_else(|| {
+        format!(
+            "no baseline at {}, run baseline first",
+            config.baseline_path.display()
+        )
+    })?;
+    let report = diff::check(&config, &baseline, accept)?;
+    println!(
+        "[FIM](INFO) {} files checked: {} added, {} removed, {} modified, {} detected",
+        report.files_checked,
+        report.added.len(),
+        report.removed.len(),
+        report.modified.len(),
+        report.detected
+    );
+    Ok(report)
+}
+
+fn reply<T: Serialize>(result: Result<T, String>) -> RString {
+    match result {
+        Ok(value) => to_json(&value),
+        Err(e) => RString::from(format!("ERR {e}")),
+    }
+}
+
+#[sabi_extern_fn]
+pub extern "C" fn init() -> RResult<RVec<Tuple2<RString, RString>>, RString> {
+    let mut info = RVec::new();
+
+    info.push(Tuple2(RString::from("author"), RString::from("Griffon")));
+    info.push(Tuple2(
+        RString::from("name"),
+        RString::from("file_integrity"),
+    ));
+    info.push(Tuple2(
+        RString::from("description"),
+        RString::from("Reports files added, removed or modified since a recorded baseline"),
+    ));
+    info.push(Tuple2(
+        RString::from("function"),
+        RString::from("baseline/check"),
+    ));
+
+    RResult::ROk(info)
+}
+
+#[sabi_extern_fn]
+extern "C" fn handle_message(msg: RString) -> RString {
+    println!("[FIM](msg) Received message: {}", msg.as_str());
+
+    let (function, arg) = match msg.as_str().split_once(' ') {
+        Some((function, arg)) => (function, arg.trim()),
+        None => (msg.as_str(), ""),
+    };
+    match (function, arg) {
+        ("fn:baseline", _) => reply(take_baseline()),
+        ("fn:check", "") => reply(run_check(false)),
+        ("fn:check", "accept") => reply(run_check(true)),
+        ("fn:check", other) => RString::from(format!(
+            "ERR check expects no argument or `accept`, got {other}"
+        )),
+        _ => RString::from(format!("ERR unknown function {}", msg.as_str())),
+    }
+}
+
+#[export_root_module]
+pub fn get_library() -> PluginRoot_Ref {
+    PluginRoot {
+        plugin: PluginI {
+            init,
+            handle_message,
+            set_host: install_host,
+            shutdown: noop_shutdown,
+        }
+        .leak_into_prefix(),
+    }
+    .leak_into_prefix()
+}
@@ -0,0 +1,308 @@
+use serde::Serialize;
+use static_analysis::{
+    FileScanner, Finding, HashDatabase, ScanResult, Severity, analyze_script,
+    load_yara_rules_cached,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::PersistenceConfig;
+use crate::locations::{self, Entry, Kind, forced_command};
+
+/// Where droppers stage payloads: world-writable and often in memory.
+static TEMP_DIRS: [&str; 3] = ["/tmp/", "/var/tmp/", "/dev/shm/"];
+/// Dot directories that legitimately hold user programs and units.
+static USUAL_DOT_DIRS: [&str; 2] = [".config", ".local"];
+
+/// An entry with what the audit found about it.
+#[derive(Debug, Serialize)]
+pub struct AuditedEntry {
+    #[serde(flatten)]
+    pub entry: Entry,
+    /// Modification time of `source`, seconds since the Unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<TargetScan>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub findings: Vec<Finding>,
+}
+
+impl AuditedEntry {
+    /// Anything above `Severity::Low`: worth a look.
+    pub fn is_flagged(&self) -> bool {
+        self.findings.iter().any(|f| f.severity > Severity::Low)
+    }
+}
+
+/// A file an entry runs or loads, and its scan.
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetScan {
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<ScanResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditReport {
+    pub entries_total: usize,
+    pub flagged: usize,
+    /// Persistence points that exist but could not be read, usually for lack of privileges.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+    /// Flagged entries only, unless every entry was asked for.
+    pub entries: Vec<AuditedEntry>,
+}
+
+/// Scans targets once each, however many entries point to them.
+struct TargetScanner<'r> {
+    scanner: Option<FileScanner<'r>>,
+    scanned: HashMap<PathBuf, TargetScan>,
+}
+
+impl TargetScanner<'_> {
+    fn scan(&mut self, path: &Path) -> Option<TargetScan> {
+        let scanner = self.scanner.as_mut()?;
+        if !path.is_file() {
+            return None;
+        }
+        let scan = self.scanned.entry(path.to_path_buf()).or_insert_with(|| {
+            match scanner.scan_file(path) {
+                // The ELF details would drown the report, its findings are kept.
+                Ok(result) => TargetScan {
+                    path: path.display().to_string(),
+                    result: Some(ScanResult {
+                        elf: None,
+                        ..result
+                    }),
+                    error: None,
+                },
+                Err(e) => TargetScan {
+                    path: path.display().to_string(),
+                    result: None,
+                    error: Some(e.to_string()),
+                },
+            }
+        });
+        Some(scan.clone())
+    }
+}
+
+/// Collects every persistence entry, scans what they point to and flags the unusual ones.
+pub fn audit(config: &PersistenceConfig, all: bool) -> AuditReport {
+    let (entries, errors) = locations::collect(config);
+
+    let (rules, _) = load_yara_rules_cached(&config.rules_dir, &config.cache_dir);
+    let mut options = config.scan_options();
+    match HashDatabase::load_dir(&config.hashes_dir) {
+        Ok(hashes) if !hashes.is_empty() => options.hashes = Some(Arc::new(hashes)),
+        Ok(_) => {}
+        Err(e) => println!("[PERSISTENCE](ERROR) Cannot load hash feeds: {e}"),
+    }
+    let mut targets = TargetScanner {
+        scanner: config
+            .scan_targets
+            .then(|| FileScanner::new(&rules, &options)),
+        scanned: HashMap::new(),
+    };
+
+    let recent = SystemTime::now() - Duration::from_secs(config.recent_days * 24 * 3600);
+    let audited: Vec<AuditedEntry> = entries
+        .into_iter()
+        .map(|entry| check(entry, &mut targets, recent))
+        .collect();
+
+    let flagged = audited.iter().filter(|e| e.is_flagged()).count();
+    AuditReport {
+        entries_total: audited.len(),
+        flagged,
+        errors,
+        entries: audited
+            .into_iter()
+            .filter(|e| all || e.is_flagged())
+            .collect(),
+    }
+}
+
+fn check(entry: Entry, targets: &mut TargetScanner, recent: SystemTime) -> AuditedEntry {
+    let mut findings = Vec::new();
+    let source_metadata = fs::metadata(&entry.source).ok();
+    let modified = source_metadata.as_ref().and_then(|m| m.modified().ok());
+
+    if let Some(when) = modified.filter(|&m| m > recent) {
+        findings.push(Finding::new(
+            "persistence.recently_modified",
+            Severity::Medium,
+            format!("{} changed {}", entry.source.display(), ago(when)),
+        ));
+    }
+    if source_metadata.is_some_and(|m| world_writable(&m)) {
+        findings.push(Finding::new(
+            "persistence.world_writable",
+            Severity::High,
+            format!("{} can be modified by any user", entry.source.display()),
+        ));
+    }
+
+    match entry.kind {
+        Kind::LdPreload => findings.push(Finding::new(
+            "persistence.ld_preload",
+            Severity::High,
+            format!(
+                "{} is loaded into every dynamically linked program, a common rootkit technique",
+                entry.value
+            ),
+        )),
+        Kind::AuthorizedKeys => {
+            if let Some(command) = forced_command(&entry.value) {
+                findings.push(Finding::new(
+                    "persistence.forced_command",
+                    Severity::Low,
+                    format!("key restricted to run `{command}`"),
+                ));
+                findings.extend(analyze_script(command.as_bytes()));
+            }
+        }
+        _ => {}
+    }
+    if entry.command {
+        findings.extend(analyze_script(entry.value.as_bytes()));
+    }
+
+    let program = entry.command.then(|| program(&entry.value)).flatten();
+    let mut scans = Vec::new();
+    for target in &entry.targets {
+        check_target(&entry, target, program.as_deref(), recent, &mut findings);
+        if let Some(scan) = targets.scan(target) {
+            if let Some(result) = &scan.result {
+                if !result.is_clean() {
+                    findings.push(Finding::new(
+                        "persistence.malicious_target",
+                        Severity::High,
+                        format!("{} is detected by the static analyzer", target.display()),
+                    ));
+                }
+                // Heuristic traits of the target (packed binary, dropper script) count for the entry.
+                findings.extend(result.findings.iter().cloned());
+            }
+            scans.push(scan);
+        }
+    }
+
+    AuditedEntry {
+        entry,
+        modified: modified
+            .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs()),
+        targets: scans,
+        findings,
+    }
+}
+
+/// Location, permissions and age of a file the entry runs.
+fn check_target(
+    entry: &Entry,
+    target: &Path,
+    program: Option<&Path>,
+    recent: SystemTime,
+    findings: &mut Vec<Finding>,
+) {
+    // The entry file itself was already checked, and startup files are dot files.
+    if target == entry.source {
+        return;
+    }
+    let shown = target.display();
+    if TEMP_DIRS.iter().any(|dir| target.starts_with(dir)) {
+        findings.push(Finding::new(
+            "persistence.temp_target",
+            Severity::High,
+            format!("runs {shown} from a temporary directory"),
+        ));
+    }
+    if hidden(target) {
+        findings.push(Finding::new(
+            "persistence.hidden_target",
+            Severity::Medium,
+            format!("{shown} is hidden in a dot file or directory"),
+        ));
+    }
+    let Ok(metadata) = fs::metadata(target) else {
+        if program == Some(target) {
+            findings.push(Finding::new(
+                "persistence.missing_target",
+                Severity::Low,
+                format!("{shown} does not exist, left over or not yet dropped"),
+            ));
+        }
+        return;
+    };
+    // Arguments such as `/` or a working directory are not run.
+    if !metadata.is_file() {
+        return;
+    }
+    if world_writable(&metadata) {
+        findings.push(Finding::new(
+            "persistence.world_writable",
+            Severity::High,
+            format!("{shown} can be modified by any user"),
+        ));
+    }
+    if let Ok(when) = metadata.modified()
+        && when > recent
+    {
+        findings.push(Finding::new(
+            "persistence.recently_modified",
+            Severity::Medium,
+            format!("{shown} changed {}", ago(when)),
+        ));
+    }
+    // System-wide entries run as root, they should not run what a user can change.
+    if entry.user.is_none() && metadata.uid() != 0 {
+        findings.push(Finding::new(
+            "persistence.user_owned_target",
+            Severity::Medium,
+            format!(
+                "{shown} is owned by uid {} but started by a system-wide entry",
+                metadata.uid()
+            ),
+        ));
+    }
+}
+
+/// The program of a command line when it is given by absolute path.
+fn program(command: &str) -> Option<PathBuf> {
+    let first = command.split_whitespace().next()?;
+    locations::command_targets(first).into_iter().next()
+}
+
+fn world_writable(metadata: &fs::Metadata) -> bool {
+    metadata.permissions().mode() & 0o002 != 0
+}
+
+fn hidden(path: &Path) -> bool {
+    path.components().any(|c| match c {
+        Component::Normal(name) => {
+            let name = name.to_string_lossy();
+            name.starts_with('.') && !USUAL_DOT_DIRS.contains(&name.as_ref())
+        }
+        _ => false,
+    })
+}
+
+fn ago(when: SystemTime) -> String {
+    let hours = SystemTime::now()
+        .duration_since(when)
+        .map_or(0, |d| d.as_secs() / 3600);
+    match hours {
+        0 => "within the last hour".to_string(),
+        1 => "an hour ago".to_string(),
+        2..=47 => format!("{hours} hours ago"),
+        _ => format!("{} days ago", hours / 24),
+    }
+}
@@ -0,0 +1,91 @@
+use serde::Deserialize;
+use static_analysis::{RuleFilter, ScanOptions};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Looked up in the working directory when `GRIFFON_PERSISTENCE_CONFIG` is not set.
+static DEFAULT_CONFIG_PATH: &str = "./griffon_persistence.toml";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PersistenceConfig {
+    pub rules_dir: PathBuf,
+    pub cache_dir: PathBuf,
+    pub hashes_dir: PathBuf,
+    /// Entries changed within this many days are flagged.
+    pub recent_days: u64,
+    /// Scan the files entries point to (cron commands, `ExecStart=`, preloaded
+    /// libraries...) with the rules, hash feeds and heuristics.
+    pub scan_targets: bool,
+    /// Searched for `*.service` and `*.timer` units, besides the user units of each home.
+    pub systemd_dirs: Vec<PathBuf>,
+    /// Homes are read from `/etc/passwd`, these are added to them.
+    pub extra_homes: Vec<PathBuf>,
+    /// Larger targets are listed but not scanned.
+    pub max_file_size_mb: u64,
+    pub categories: RuleFilter,
+}
+
+impl Default for PersistenceConfig {
+    fn default() -> Self {
+        Self {
+            rules_dir: interface::data_dir().join("rules/current/rules"),
+            cache_dir: interface::data_dir().join("yara-cache"),
+            hashes_dir: interface::data_dir().join("rules/current/hashes"),
+            recent_days: 7,
+            scan_targets: true,
+            systemd_dirs: [
+                "/etc/systemd/system",
+                "/etc/systemd/user",
+                "/run/systemd/system",
+                "/usr/lib/systemd/system",
+                "/usr/lib/systemd/user",
+                "/lib/systemd/system",
+                "/usr/local/lib/systemd/system",
+            ]
+            .iter()
+            .map(PathBuf::from)
+            .collect(),
+            extra_homes: Vec::new(),
+            max_file_size_mb: 64,
+            categories: RuleFilter::default(),
+        }
+    }
+}
+
+impl PersistenceConfig {
+    /// Loads the config from `GRIFFON_PERSISTENCE_CONFIG` or `./griffon_persistence.toml`.
+    /// A missing file is not an error: the defaults are used instead.
+    pub fn load() -> io::Result<Self> {
+        let path = std::env::var_os("GRIFFON_PERSISTENCE_CONFIG")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH));
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::from_file(&path)
+    }
+
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let raw = fs::read_to_string(path)?;
+        toml::from_str(&raw).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {e}", path.display()),
+            )
+        })
+    }
+
+    /// Targets are scanned one at a time, archives as raw bytes.
+    pub fn scan_options(&self) -> ScanOptions {
+        ScanOptions {
+            threads: 1,
+            max_file_size: Some(self.max_file_size_mb * 1024 * 1024),
+            archives: None,
+            filter: self.categories.clone(),
+            ..ScanOptions::default()
+        }
+    }
+}
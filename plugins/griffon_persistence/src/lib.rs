@@ -0,0 +1,85 @@
+mod audit;
+mod config;
+mod locations;
+
+use abi_stable::{
+    export_root_module,
+    prefix_type::PrefixTypeTrait,
+    sabi_extern_fn,
+    std_types::{RResult, RString, RVec, Tuple2},
+};
+use interface::{PluginI, PluginRoot, PluginRoot_Ref, install_host, noop_shutdown};
+use serde::Serialize;
+
+use config::PersistenceConfig;
+
+fn to_json<T: Serialize>(value: &T) -> RString {
+    match serde_json::to_string(value) {
+        Ok(json) => RString::from(json),
+        Err(e) => RString::from(format!("ERR json serialize: {e}")),
+    }
+}
+
+/// The config is read on each call, audits are rare and it saves a reload function.
+fn run_audit(all: bool) -> RString {
+    let config = PersistenceConfig::load().unwrap_or_else(|e| {
+        println!("[PERSISTENCE](ERROR) Bad config, using defaults: {e}");
+        PersistenceConfig::default()
+    });
+    let report = audit::audit(&config, all);
+    println!(
+        "[PERSISTENCE](INFO) {} entries audited, {} flagged",
+        report.entries_total, report.flagged
+    );
+    to_json(&report)
+}
+
+#[sabi_extern_fn]
+pub extern "C" fn init() -> RResult<RVec<Tuple2<RString, RString>>, RString> {
+    let mut info = RVec::new();
+
+    info.push(Tuple2(RString::from("author"), RString::from("Griffon")));
+    info.push(Tuple2(
+        RString::from("name"),
+        RString::from("persistence_audit"),
+    ));
+    info.push(Tuple2(
+        RString::from("description"),
+        RString::from("Audits crontabs, systemd units, startup files, preloads and SSH keys"),
+    ));
+    info.push(Tuple2(RString::from("function"), RString::from("audit")));
+
+    RResult::ROk(info)
+}
+
+#[sabi_extern_fn]
+extern "C" fn handle_message(msg: RString) -> RString {
+    println!("[PERSISTENCE](msg) Received message: {}", msg.as_str());
+
+    let (function, arg) = match msg.as_str().split_once(' ') {
+        Some((function, arg)) => (function, arg.trim()),
+        None => (msg.as_str(), ""),
+    };
+    match (function, arg) {
+        ("fn:audit", "") => run_audit(false),
+        ("fn:audit", "all") => run_audit(true),
+        ("fn:audit", other) => RString::from(format!(
+            "ERR audit expects no argument or `all`, got {other}"
+        )),
+        _ => RString::from(format!("ERR unknown function {}", msg.as_str())),
+    }
+}
+
+#[export_root_module]
+pub fn get_library() -> PluginRoot_Ref {
+    PluginRoot {
+        plugin: PluginI {
+            init,
+            handle_message,
+            set_host: install_host,
+            shutdown: noop_shutdown,
+        }
+        .leak_into_prefix(),
+    }
+    .leak_into_prefix()
+}
@@ -0,0 +1,552 @@
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::PersistenceConfig;
+
+static SYSTEM_CRONTABS: [&str; 2] = ["/etc/crontab", "/etc/cron.d"];
+/// Debian keeps user crontabs in `crontabs/`, RHEL directly in `cron/`.
+static USER_CRONTABS: [&str; 2] = ["/var/spool/cron/crontabs", "/var/spool/cron"];
+static CRON_SCRIPT_DIRS: [&str; 4] = [
+    "/etc/cron.hourly",
+    "/etc/cron.daily",
+    "/etc/cron.weekly",
+    "/etc/cron.monthly",
+];
+static ANACRONTAB: &str = "/etc/anacrontab";
+static SYSTEM_SHELL_RC: [&str; 6] = [
+    "/etc/profile",
+    "/etc/bash.bashrc",
+    "/etc/bashrc",
+    "/etc/zsh/zshrc",
+    "/etc/zshenv",
+    "/etc/environment",
+];
+static PROFILE_DIR: &str = "/etc/profile.d";
+static HOME_SHELL_RC: [&str; 7] = [
+    ".bashrc",
+    ".bash_profile",
+    ".bash_login",
+    ".bash_logout",
+    ".profile",
+    ".zshrc",
+    ".zprofile",
+];
+static LD_SO_PRELOAD: &str = "/etc/ld.so.preload";
+static XDG_AUTOSTART: &str = "/etc/xdg/autostart";
+static AUTHORIZED_KEYS: [&str; 2] = [".ssh/authorized_keys", ".ssh/authorized_keys2"];
+/// systemd keys whose value is a command line.
+static EXEC_KEYS: [&str; 7] = [
+    "ExecStart",
+    "ExecStartPre",
+    "ExecStartPost",
+    "ExecStop",
+    "ExecStopPost",
+    "ExecReload",
+    "ExecCondition",
+];
+/// systemd keys that make a timer fire.
+static TIMER_KEYS: [&str; 7] = [
+    "OnCalendar",
+    "OnBootSec",
+    "OnStartupSec",
+    "OnActiveSec",
+    "OnUnitActiveSec",
+    "OnUnitInactiveSec",
+    "Unit",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    Cron,
+    Systemd,
+    ShellRc,
+    LdPreload,
+    Autostart,
+    AuthorizedKeys,
+}
+
+/// One way something gets started again: a crontab line, a unit command, a startup file...
+#[derive(Debug, Clone, Serialize)]
+pub struct Entry {
+    pub kind: Kind,
+    /// File holding the entry.
+    pub source: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    /// The command, unit setting or key as written; the path itself for whole files.
+    pub value: String,
+    /// Account the entry runs as or belongs to, `None` for system-wide entries.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Files the entry runs or loads, the first one being the program when known.
+    #[serde(skip)]
+    pub targets: Vec<PathBuf>,
+    /// `value` is a command line, checked with the script heuristics.
+    #[serde(skip)]
+    pub command: bool,
+}
+
+/// A login account with a home directory to look into.
+struct Home {
+    user: String,
+    dir: PathBuf,
+}
+
+/// Walks the persistence points, collecting the entries and the places that could not be read.
+struct Collector {
+    entries: Vec<Entry>,
+    errors: Vec<String>,
+}
+
+impl Collector {
+    /// Missing files are expected, other failures (permissions) are reported.
+    fn read(&mut self, path: &Path) -> Option<String> {
+        match fs::read(path) {
+            Ok(raw) => Some(String::from_utf8_lossy(&raw).into_owned()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) if e.kind() == io::ErrorKind::IsADirectory => None,
+            Err(e) => {
+                self.errors.push(format!("{}: {e}", path.display()));
+                None
+            }
+        }
+    }
+
+    /// Regular files of `dir`, sorted. Missing directories give nothing.
+    fn files(&mut self, dir: &Path) -> Vec<PathBuf> {
+        let read_dir = match fs::read_dir(dir) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Vec::new(),
+            Err(e) => {
+                self.errors.push(format!("{}: {e}", dir.display()));
+                return Vec::new();
+            }
+        };
+        let mut files: Vec<PathBuf> = read_dir
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_ok_and(|t| t.is_file() || t.is_symlink()))
+            .map(|e| e.path())
+            .filter(|p| p.is_file())
+            .collect();
+        files.sort();
+        files
+    }
+
+    /// A command line found in a crontab, unit or desktop file.
+    fn push_command(
+        &mut self,
+        kind: Kind,
+        source: &Path,
+        line: usize,
+        command: &str,
+        user: Option<&str>,
+    ) {
+        self.entries.push(Entry {
+            kind,
+            source: source.to_path_buf(),
+            line: Some(line),
+            value: command.to_string(),
+            user: user.map(str::to_string),
+            targets: command_targets(command),
+            command: true,
+        });
+    }
+
+    /// A file that is itself run or sourced.
+    fn push_file(&mut self, kind: Kind, path: &Path, user: Option<&str>) {
+        self.entries.push(Entry {
+            kind,
+            source: path.to_path_buf(),
+            line: None,
+            value: path.display().to_string(),
+            user: user.map(str::to_string),
+            targets: vec![path.to_path_buf()],
+            command: false,
+        });
+    }
+}
+
+/// Every persistence entry on the system, and what could not be read.
+pub fn collect(config: &PersistenceConfig) -> (Vec<Entry>, Vec<String>) {
+    let mut collector = Collector {
+        entries: Vec::new(),
+        errors: Vec::new(),
+    };
+    let homes = homes(config);
+
+    cron(&mut collector);
+    systemd(&mut collector, config, &homes);
+    shell_rc(&mut collector, &homes);
+    ld_preload(&mut collector);
+    autostart(&mut collector, &homes);
+    authorized_keys(&mut collector, &homes);
+
+    (collector.entries, collector.errors)
+}
+
+/// Accounts from `/etc/passwd` whose home exists, plus `extra_homes`.
+fn homes(config: &PersistenceConfig) -> Vec<Home> {
+    let mut seen = HashSet::new();
+    let mut homes = Vec::new();
+    let passwd = fs::read_to_string("/etc/passwd").unwrap_or_default();
+    for line in passwd.lines() {
+        let fields: Vec<&str> = line.split(':').collect();
+        let [user, _, _, _, _, dir, ..] = fields[..] else {
+            continue;
+        };
+        let dir = PathBuf::from(dir);
+        // System accounts share `/`, `/nonexistent` or service directories.
+        if dir == Path::new("/") || !dir.is_dir() || !seen.insert(dir.clone()) {
+            continue;
+        }
+        homes.push(Home {
+            user: user.to_string(),
+            dir,
+        });
+    }
+    for dir in &config.extra_homes {
+        if seen.insert(dir.clone()) {
+            let user = dir
+                .file_name()
+                .map_or_else(String::new, |n| n.to_string_lossy().into_owned());
+            homes.push(Home {
+                user,
+                dir: dir.clone(),
+            });
+        }
+    }
+    homes
+}
+
+/// Lines that configure something, without comments, blanks and `VAR=value` settings.
+fn meaningful_lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines().enumerate().filter_map(|(i, line)| {
+        let line = line.trim();
+        let assignment = line
+            .split_once('=')
+            .is_some_and(|(name, _)| !name.is_empty() && !name.contains(char::is_whitespace));
+        (!line.is_empty() && !line.starts_with('#') && !assignment).then_some((i + 1, line))
+    })
+}
+
+/// What follows the first `n` whitespace-separated fields of `line`.
+fn after_fields(line: &str, n: usize) -> Option<(Vec<&str>, &str)> {
+    let mut fields = Vec::with_capacity(n);
+    let mut rest = line.trim_start();
+    for _ in 0..n {
+        let end = rest.find(char::is_whitespace)?;
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    (!rest.is_empty()).then_some((fields, rest))
+}
+
+/// `(user, command)` of a crontab line, the user coming from the line in system crontabs.
+fn parse_cron_line(line: &str, system: bool) -> Option<(Option<&str>, &str)> {
+    // `@reboot` and friends replace the five time fields.
+    let time_fields = if line.starts_with('@') { 1 } else { 5 };
+    if system {
+        let (fields, command) = after_fields(line, time_fields + 1)?;
+        Some((fields.last().copied(), command))
+    } else {
+        let (_, command) = after_fields(line, time_fields)?;
+        Some((None, command))
+    }
+}
+
+fn cron(collector: &mut Collector) {
+    let mut system = Vec::new();
+    for path in SYSTEM_CRONTABS.iter().map(Path::new) {
+        if path.is_dir() {
+            system.extend(collector.files(path));
+        } else {
+            system.push(path.to_path_buf());
+        }
+    }
+    for path in system {
+        let Some(text) = collector.read(&path) else {
+            continue;
+        };
+        for (line, content) in meaningful_lines(&text) {
+            if let Some((user, command)) = parse_cron_line(content, true) {
+                collector.push_command(Kind::Cron, &path, line, command, user);
+            }
+        }
+    }
+
+    for dir in USER_CRONTABS.iter().map(Path::new) {
+        for path in collector.files(dir) {
+            let Some(text) = collector.read(&path) else {
+                continue;
+            };
+            let user = path.file_name().map(|n| n.to_string_lossy().into_owned());
+            for (line, content) in meaningful_lines(&text) {
+                if let Some((_, command)) = parse_cron_line(content, false) {
+                    collector.push_command(Kind::Cron, &path, line, command, user.as_deref());
+                }
+            }
+        }
+    }
+
+    for dir in CRON_SCRIPT_DIRS.iter().map(Path::new) {
+        for path in collector.files(dir) {
+            collector.push_file(Kind::Cron, &path, None);
+        }
+    }
+
+    if let Some(text) = collector.read(Path::new(ANACRONTAB)) {
+        for (line, content) in meaningful_lines(&text) {
+            // period, delay and job identifier.
+            if let Some((_, command)) = after_fields(content, 3) {
+                collector.push_command(Kind::Cron, Path::new(ANACRONTAB), line, command, None);
+            }
+        }
+    }
+}
+
+fn systemd(collector: &mut Collector, config: &PersistenceConfig, homes: &[Home]) {
+    let mut dirs: Vec<(PathBuf, Option<&str>)> = config
+        .systemd_dirs
+        .iter()
+        .map(|d| (d.clone(), None))
+        .collect();
+    for home in homes {
+        dirs.push((
+            home.dir.join(".config/systemd/user"),
+            Some(home.user.as_str()),
+        ));
+    }
+
+    // `/lib` is a link to `/usr/lib` on merged-usr systems.
+    let mut seen = HashSet::new();
+    for (dir, user) in dirs {
+        if !seen.insert(fs::canonicalize(&dir).unwrap_or_else(|_| dir.clone())) {
+            continue;
+        }
+        for path in collector.files(&dir) {
+            let extension = path.extension().and_then(|e| e.to_str());
+            if !matches!(extension, Some("service" | "timer")) {
+                continue;
+            }
+            // Masked units point to /dev/null and fail `files` already.
+            let Some(text) = collector.read(&path) else {
+                continue;
+            };
+            let mut timer = Vec::new();
+            for (line, content) in text.lines().enumerate() {
+                let Some((key, value)) = content.trim().split_once('=') else {
+                    continue;
+                };
+                let (key, value) = (key.trim(), value.trim());
+                if value.is_empty() {
+                    continue;
+                }
+                if EXEC_KEYS.contains(&key) {
+                    collector.push_command(Kind::Systemd, &path, line + 1, value, user);
+                } else if TIMER_KEYS.contains(&key) {
+                    timer.push(format!("{key}={value}"));
+                }
+            }
+            if extension == Some("timer") && !timer.is_empty() {
+                collector.entries.push(Entry {
+                    kind: Kind::Systemd,
+                    source: path.clone(),
+                    line: None,
+                    value: timer.join(" "),
+                    user: user.map(str::to_string),
+                    targets: Vec::new(),
+                    command: false,
+                });
+            }
+        }
+    }
+}
+
+fn shell_rc(collector: &mut Collector, homes: &[Home]) {
+    for path in SYSTEM_SHELL_RC.iter().map(Path::new) {
+        if path.is_file() {
+            collector.push_file(Kind::ShellRc, path, None);
+        }
+    }
+    for path in collector.files(Path::new(PROFILE_DIR)) {
+        collector.push_file(Kind::ShellRc, &path, None);
+    }
+    for home in homes {
+        for name in HOME_SHELL_RC {
+            let path = home.dir.join(name);
+            if path.is_file() {
+                collector.push_file(Kind::ShellRc, &path, Some(&home.user));
+            }
+        }
+    }
+}
+
+fn ld_preload(collector: &mut Collector) {
+    let path = Path::new(LD_SO_PRELOAD);
+    let Some(text) = collector.read(path) else {
+        return;
+    };
+    for (line, content) in text.lines().enumerate() {
+        let content = content.split('#').next().unwrap_or_default();
+        // Libraries are separated by spaces or colons.
+        for library in content
+            .split(|c: char| c.is_whitespace() || c == ':')
+            .filter(|l| !l.is_empty())
+        {
+            collector.entries.push(Entry {
+                kind: Kind::LdPreload,
+                source: path.to_path_buf(),
+                line: Some(line + 1),
+                value: library.to_string(),
+                user: None,
+                targets: vec![PathBuf::from(library)],
+                command: false,
+            });
+        }
+    }
+}
+
+fn autostart(collector: &mut Collector, homes: &[Home]) {
+    let mut dirs = vec![(PathBuf::from(XDG_AUTOSTART), None)];
+    for home in homes {
+        dirs.push((home.dir.join(".config/autostart"), Some(home.user.as_str())));
+    }
+    for (dir, user) in dirs {
+        for path in collector.files(&dir) {
+            if path.extension().is_none_or(|e| e != "desktop") {
+                continue;
+            }
+            let Some(text) = collector.read(&path) else {
+                continue;
+            };
+            for (line, content) in text.lines().enumerate() {
+                if let Some(command) = content.trim().strip_prefix("Exec=") {
+                    collector.push_command(Kind::Autostart, &path, line + 1, command, user);
+                }
+            }
+        }
+    }
+}
+
+fn authorized_keys(collector: &mut Collector, homes: &[Home]) {
+    for home in homes {
+        for name in AUTHORIZED_KEYS {
+            let path = home.dir.join(name);
+            let Some(text) = collector.read(&path) else {
+                continue;
+            };
+            for (line, content) in text.lines().enumerate() {
+                let content = content.trim();
+                if content.is_empty() || content.starts_with('#') {
+                    continue;
+                }
+                // A forced command runs whatever the client asks for.
+                let targets = forced_command(content)
+                    .map(command_targets)
+                    .unwrap_or_default();
+                collector.entries.push(Entry {
+                    kind: Kind::AuthorizedKeys,
+                    source: path.clone(),
+                    line: Some(line + 1),
+                    value: shorten_key(content),
+                    user: Some(home.user.clone()),
+                    targets,
+                    command: false,
+                });
+            }
+        }
+    }
+}
+
+/// The `command="..."` option of an `authorized_keys` line.
+pub fn forced_command(line: &str) -> Option<&str> {
+    let start = line.find("command=\"")? + "command=\"".len();
+    let end = line[start..].find('"')?;
+    Some(&line[start..start + end])
+}
+
+/// Keeps the options, type and comment of a key line, only the ends of the key itself.
+fn shorten_key(line: &str) -> String {
+    line.split_whitespace()
+        .map(|field| {
+            if field.len() > 40 && field.starts_with("AAAA") {
+                format!("{}...{}", &field[..12], &field[field.len() - 8..])
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Absolute paths in a command line, the program first. systemd prefixes
+/// (`-/usr/bin/x`, `@/bin/y`) and quotes are stripped.
+pub fn command_targets(command: &str) -> Vec<PathBuf> {
+    let mut targets: Vec<PathBuf> = Vec::new();
+    let separators = |c: char| c.is_whitespace() || ";|&()`<>".contains(c);
+    for token in command.split(separators) {
+        let token = token
+            .trim_start_matches(['@', '-', ':', '+', '!', '$'])
+            .trim_matches(['"', '\'']);
+        if !token.starts_with('/') || token == "/dev/null" {
+            continue;
+        }
+        let path = PathBuf::from(token);
+        if !targets.contains(&path) {
+            targets.push(path);
+        }
+    }
+    targets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cron_lines_split_into_user_and_command() {
+        assert_eq!(
+            parse_cron_line("*/5 * * * *  /usr/bin/backup --all", false),
+            Some((None, "/usr/bin/backup --all"))
+        );
+        assert_eq!(
+            parse_cron_line(
+                "0 3 * * 1 root /usr/sbin/logrotate /etc/logrotate.conf",
+                true
+            ),
+            Some((Some("root"), "/usr/sbin/logrotate /etc/logrotate.conf"))
+        );
+        assert_eq!(
+            parse_cron_line("@reboot /tmp/.x/run", false),
+            Some((None, "/tmp/.x/run"))
+        );
+        assert_eq!(
+            parse_cron_line("@reboot\tnobody\t/tmp/.x/run", true),
+            Some((Some("nobody"), "/tmp/.x/run"))
+        );
+        // No command after the schedule, or no user in a system crontab.
+        assert_eq!(parse_cron_line("* * * * *", false), None);
+        assert_eq!(parse_cron_line("* * * * * /bin/true", true), None);
+    }
+
+    #[test]
+    fn command_targets_are_the_absolute_paths() {
+        assert_eq!(
+            command_targets("/bin/sh -c '/tmp/.x/run >/dev/null 2>&1'"),
+            [PathBuf::from("/bin/sh"), PathBuf::from("/tmp/.x/run")]
+        );
+        assert_eq!(
+            command_targets("-/usr/bin/env \"/opt/app/start\";/opt/app/start|@/bin/logger"),
+            [
+                PathBuf::from("/usr/bin/env"),
+                PathBuf::from("/opt/app/start"),
+                PathBuf::from("/bin/logger"),
+            ]
+        );
+        assert!(command_targets("PATH=/usr/bin run-parts etc").is_empty());
+    }
+}
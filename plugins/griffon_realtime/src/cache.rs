@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::os::unix::fs::MetadataExt;
+
+/// Outcome of checking a file, as remembered by the cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Clean,
+    /// Too large, excluded or failed to scan; access is allowed.
+    Unscanned,
+    /// Short description of what matched, e.g. `malware:Linux_Trojan_Mirai`.
+    Infected(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct FileId {
+    dev: u64,
+    ino: u64,
+}
+
+/// State of the file when it was checked. Any change means the verdict is stale.
+/// `ctime` catches writes that restore `mtime`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    mtime: i64,
+    mtime_nsec: i64,
+    ctime: i64,
+    ctime_nsec: i64,
+    size: u64,
+}
+
+/// Verdicts keyed by inode and change times, so unchanged files are not
+/// rescanned every time they are opened. Emptied on rule reloads.
+pub struct VerdictCache {
+    capacity: usize,
+    entries: HashMap<FileId, (Stamp, Verdict)>,
+}
+
+fn key(metadata: &Metadata) -> (FileId, Stamp) {
+    let id = FileId {
+        dev: metadata.dev(),
+        ino: metadata.ino(),
+    };
+    let stamp = Stamp {
+        mtime: metadata.mtime(),
+        mtime_nsec: metadata.mtime_nsec(),
+        ctime: metadata.ctime(),
+        ctime_nsec: metadata.ctime_nsec(),
+        size: metadata.size(),
+    };
+    (id, stamp)
+}
+
+impl VerdictCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, metadata: &Metadata) -> Option<Verdict> {
+        let (id, stamp) = key(metadata);
+        self.entries
+            .get(&id)
+            .filter(|(cached, _)| *cached == stamp)
+            .map(|(_, verdict)| verdict.clone())
+    }
+
+    /// `Unscanned` is never kept: a failed open or scan is retried on the next access.
+    pub fn insert(&mut self, metadata: &Metadata, verdict: Verdict) {
+        if self.capacity == 0 || verdict == Verdict::Unscanned {
+            return;
+        }
+        // Starting over is cheaper than tracking use order, and rare with a sensible capacity.
+        if self.entries.len() >= self.capacity {
+            self.entries.clear();
+        }
+        let (id, stamp) = key(metadata);
+        self.entries.insert(id, (stamp, verdict));
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("griffon-{name}-{}", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn changed_files_lose_their_verdict() {
+        let path = temp_file("verdict", "a");
+        let mut cache = VerdictCache::new(10);
+        cache.insert(&fs::metadata(&path).unwrap(), Verdict::Clean);
+        assert_eq!(
+            cache.get(&fs::metadata(&path).unwrap()),
+            Some(Verdict::Clean)
+        );
+
+        // Same size, but the change time moves.
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        fs::write(&path, "b").unwrap();
+        assert_eq!(cache.get(&fs::metadata(&path).unwrap()), None);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn unscanned_files_are_not_remembered() {
+        let path = temp_file("unscanned", "a");
+        let metadata = fs::metadata(&path).unwrap();
+        let mut cache = VerdictCache::new(10);
+        cache.insert(&metadata, Verdict::Unscanned);
+        assert_eq!(cache.get(&metadata), None);
+        assert_eq!(cache.len(), 0);
+
+        cache.insert(&metadata, Verdict::Infected("malware:Test".to_string()));
+        assert_eq!(
+            cache.get(&metadata),
+            Some(Verdict::Infected("malware:Test".to_string()))
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn a_full_cache_starts_over() {
+        let paths: Vec<_> = (0..3)
+            .map(|i| temp_file(&format!("capacity-{i}"), "a"))
+            .collect();
+        let mut cache = VerdictCache::new(2);
+        for path in &paths {
+            cache.insert(&fs::metadata(path).unwrap(), Verdict::Clean);
+        }
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&fs::metadata(&paths[0]).unwrap()), None);
+        assert_eq!(
+            cache.get(&fs::metadata(&paths[2]).unwrap()),
+            Some(Verdict::Clean)
+        );
+
+        // A capacity of 0 disables the cache.
+        let mut cache = VerdictCache::new(0);
+        cache.insert(&fs::metadata(&paths[0]).unwrap(), Verdict::Clean);
+        assert_eq!(cache.len(), 0);
+        for path in paths {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+use static_analysis::{RuleFilter, ScanOptions};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Looked up in the working directory when `GRIFFON_REALTIME_CONFIG` is not set.
+static DEFAULT_CONFIG_PATH: &str = "./griffon_realtime.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    /// fanotify when the runner has `CAP_SYS_ADMIN`, inotify otherwise.
+    Auto,
+    Fanotify,
+    Inotify,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RealtimeConfig {
+    pub rules_dir: PathBuf,
+    pub cache_dir: PathBuf,
+    pub hashes_dir: PathBuf,
+    /// Start monitoring as soon as the plugin is loaded.
+    pub autostart: bool,
+    pub backend: Backend,
+    /// With fanotify the whole mount holding each path is watched,
+    /// with inotify every directory below each path.
+    pub mounts: Vec<PathBuf>,
+    /// Globs matched against full paths, matching files are never scanned.
+    pub exclude: Vec<String>,
+    pub scan_on_open: bool,
+    pub scan_on_exec: bool,
+    pub scan_on_close_write: bool,
+    /// Deny execution of detected files (fanotify only).
+    pub block_executables: bool,
+    /// Ask the daemon to quarantine detected files, needs `quarantine_roots`
+    /// covering the watched paths in the broker policy of this plugin.
+    pub quarantine: bool,
+    /// Larger files are let through unscanned, this bounds the delay added to `exec`.
+    pub max_file_size_mb: u64,
+    pub timeout_secs: u64,
+    /// Threads answering fanotify events. A slow scan holds up one of them,
+    /// not every access to the watched mounts.
+    pub workers: usize,
+    /// Verdicts remembered, keyed by inode and modification time.
+    pub cache_entries: usize,
+    pub categories: RuleFilter,
+}
+
+impl Default for RealtimeConfig {
+    fn default() -> Self {
+        Self {
+            rules_dir: interface::data_dir().join("rules/current/rules"),
+            cache_dir: interface::data_dir().join("yara-cache"),
+            hashes_dir: interface::data_dir().join("rules/current/hashes"),
+            autostart: true,
+            backend: Backend::Auto,
+            mounts: vec![PathBuf::from("/home"), PathBuf::from("/tmp")],
+            exclude: Vec::new(),
+            scan_on_open: false,
+            scan_on_exec: true,
+            scan_on_close_write: true,
+            block_executables: true,
+            quarantine: false,
+            max_file_size_mb: 64,
+            timeout_secs: 10,
+            workers: 4,
+            cache_entries: 100_000,
+            categories: RuleFilter::default(),
+        }
+    }
+}
+
+impl RealtimeConfig {
+    /// Loads the config from `GRIFFON_REALTIME_CONFIG` or `./griffon_realtime.toml`.
+    /// A missing file is not an error: the defaults are used instead.
+    pub fn load() -> io::Result<Self> {
+        let path = std::env::var_os("GRIFFON_REALTIME_CONFIG")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH));
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::from_file(&path)
+    }
+
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let raw = fs::read_to_string(path)?;
+        toml::from_str(&raw).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {e}", path.display()),
+            )
+        })
+    }
+
+    /// Archives are scanned as raw bytes: unpacking them would stall the file access being checked.
+    pub fn scan_options(&self) -> ScanOptions {
+        ScanOptions {
+            threads: 1,
+            max_file_size: Some(self.max_file_size_mb * 1024 * 1024),
+            timeout: Duration::from_secs(self.timeout_secs),
+            archives: None,
+            filter: self.categories.clone(),
+            ..ScanOptions::default()
+        }
+    }
+
+    pub fn exclude_set(&self) -> io::Result<GlobSet> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &self.exclude {
+            let glob = Glob::new(pattern).map_err(|e| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("{pattern}: {e}"))
+            })?;
+            builder.add(glob);
+        }
+        builder.build().map_err(io::Error::other)
+    }
+}
@@ -0,0 +1,193 @@
+use nix::errno::Errno;
+use nix::fcntl::AT_FDCWD;
+use nix::sys::fanotify::{
+    EventFFlags, Fanotify, FanotifyEvent, FanotifyResponse, InitFlags, MarkFlags, MaskFlags,
+    Response,
+};
+use static_analysis::FileScanner;
+use std::fs::{self, File};
+use std::os::fd::{AsFd, AsRawFd};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use crate::cache::Verdict;
+use crate::config::RealtimeConfig;
+use crate::monitor::{Engine, Monitor, wait_readable};
+
+/// Creates the fanotify group and marks every configured mount.
+/// Fails with `EPERM` when the runner lacks `CAP_SYS_ADMIN`.
+pub fn init(config: &RealtimeConfig) -> nix::Result<Fanotify> {
+    let fanotify = Fanotify::init(
+        InitFlags::FAN_CLASS_CONTENT | InitFlags::FAN_CLOEXEC | InitFlags::FAN_NONBLOCK,
+        EventFFlags::O_RDONLY | EventFFlags::O_LARGEFILE | EventFFlags::O_CLOEXEC,
+    )?;
+
+    let mut mask = MaskFlags::empty();
+    if config.scan_on_exec {
+        mask |= if config.block_executables {
+            MaskFlags::FAN_OPEN_EXEC_PERM
+        } else {
+            MaskFlags::FAN_OPEN_EXEC
+        };
+    }
+    if config.scan_on_open {
+        mask |= MaskFlags::FAN_OPEN;
+    }
+    if config.scan_on_close_write {
+        mask |= MaskFlags::FAN_CLOSE_WRITE;
+    }
+
+    let mut marked = 0;
+    for mount in &config.mounts {
+        match fanotify.mark(
+            MarkFlags::FAN_MARK_ADD | MarkFlags::FAN_MARK_MOUNT,
+            mask,
+            AT_FDCWD,
+            Some(mount.as_path()),
+        ) {
+            Ok(()) => marked += 1,
+            Err(e) => println!(
+                "[REALTIME](WARN) Cannot watch mount of {}: {e}",
+                mount.display()
+            ),
+        }
+    }
+    if marked == 0 {
+        return Err(Errno::ENOENT);
+    }
+    Ok(fanotify)
+}
+
+fn event_name(mask: MaskFlags) -> &'static str {
+    if mask.intersects(MaskFlags::FAN_OPEN_EXEC_PERM | MaskFlags::FAN_OPEN_EXEC) {
+        "exec"
+    } else if mask.contains(MaskFlags::FAN_CLOSE_WRITE) {
+        "close_write"
+    } else {
+        "open"
+    }
+}
+
+/// Handles events until the monitor is stopped. Events are answered by
+/// `config.workers` threads, so a slow scan only holds up the accesses queued
+/// behind it on its own thread. Closing the group on return lets the kernel
+/// allow any permission event still pending.
+pub fn run(monitor: &Monitor, fanotify: Fanotify) {
+    let workers = monitor.engine().config.workers.max(1);
+    let (sender, receiver) = mpsc::channel();
+    let receiver = Mutex::new(receiver);
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| work(monitor, &fanotify, &receiver));
+        }
+        read_events(monitor, &fanotify, sender);
+    });
+}
+
+/// Hands events to the workers until the monitor is stopped. Returning drops
+/// `sender`, which ends the workers.
+fn read_events(monitor: &Monitor, fanotify: &Fanotify, sender: Sender<FanotifyEvent>) {
+    while !monitor.stopped() {
+        match wait_readable(fanotify.as_fd()) {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                println!("[REALTIME](ERROR) fanotify poll failed, stopping: {e}");
+                return;
+            }
+        }
+        let events = match fanotify.read_events() {
+            Ok(events) => events,
+            Err(Errno::EAGAIN) | Err(Errno::EINTR) => continue,
+            Err(e) => {
+                println!("[REALTIME](ERROR) fanotify read failed, stopping: {e}");
+                return;
+            }
+        };
+        for event in events {
+            if sender.send(event).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Answers events with a scanner of its own, rebuilt when the rules are reloaded.
+/// Events left once the monitor is stopped are dropped unanswered, the kernel
+/// allows them when the group is closed.
+fn work(monitor: &Monitor, fanotify: &Fanotify, events: &Mutex<Receiver<FanotifyEvent>>) {
+    let own_pid = std::process::id() as i32;
+    let mut pending = None;
+
+    loop {
+        let engine = monitor.engine();
+        let mut scanner = FileScanner::new(&engine.rules, &engine.options);
+
+        loop {
+            let event = match pending.take() {
+                Some(event) => event,
+                None => match events.lock().unwrap().recv() {
+                    Ok(event) => event,
+                    Err(_) => return,
+                },
+            };
+            if monitor.stopped() {
+                return;
+            }
+            if monitor.is_stale(&engine) {
+                pending = Some(event);
+                break;
+            }
+            handle(monitor, &engine, &mut scanner, fanotify, &event, own_pid);
+        }
+    }
+}
+
+fn handle(
+    monitor: &Monitor,
+    engine: &Engine,
+    scanner: &mut FileScanner,
+    fanotify: &Fanotify,
+    event: &FanotifyEvent,
+    own_pid: i32,
+) {
+    // Queue overflows carry no file.
+    let Some(fd) = event.fd() else {
+        println!("[REALTIME](WARN) fanotify queue overflow, some events were lost");
+        return;
+    };
+    let mask = event.mask();
+    let path = fs::read_link(format!("/proc/self/fd/{}", fd.as_raw_fd())).unwrap_or_default();
+
+    // The event descriptor is read directly: reopening the path would raise new events.
+    let verdict = match fd.try_clone_to_owned().map(File::from) {
+        Ok(file) if event.pid() != own_pid => match file.metadata() {
+            Ok(metadata) => monitor.check(engine, scanner, &path, &metadata, || Ok(file)),
+            Err(_) => Verdict::Unscanned,
+        },
+        _ => Verdict::Unscanned,
+    };
+
+    let deny =
+        matches!(verdict, Verdict::Infected(_)) && mask.contains(MaskFlags::FAN_OPEN_EXEC_PERM);
+    if mask.intersects(MaskFlags::FAN_OPEN_EXEC_PERM | MaskFlags::FAN_OPEN_PERM) {
+        let response = if deny {
+            Response::FAN_DENY
+        } else {
+            Response::FAN_ALLOW
+        };
+        if let Err(e) = fanotify.write_response(FanotifyResponse::new(fd, response)) {
+            println!(
+                "[REALTIME](ERROR) Cannot answer for {}: {e}",
+                path.display()
+            );
+        }
+    }
+
+    // Reported once the access is answered, quarantine goes through the daemon.
+    if let Verdict::Infected(detection) = verdict {
+        monitor.report(engine, &path, &detection, event_name(mask), deny);
+    }
+}
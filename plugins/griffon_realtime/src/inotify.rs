@@ -0,0 +1,165 @@
+use globset::GlobSet;
+use nix::errno::Errno;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, InotifyEvent, WatchDescriptor};
+use static_analysis::FileScanner;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::os::fd::AsFd;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::cache::Verdict;
+use crate::config::RealtimeConfig;
+use crate::monitor::{Engine, Monitor, wait_readable};
+
+/// Unprivileged fallback: one watch per directory, nothing can be blocked and
+/// executions cannot be told apart from other opens.
+pub struct Watcher {
+    inotify: Inotify,
+    mask: AddWatchFlags,
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+    /// Set once the `max_user_watches` limit is hit, so it is only reported once.
+    exhausted: bool,
+}
+
+pub fn init(config: &RealtimeConfig, exclude: &GlobSet) -> nix::Result<Watcher> {
+    let inotify = Inotify::init(InitFlags::IN_CLOEXEC | InitFlags::IN_NONBLOCK)?;
+
+    // New directories and files moved in are always followed.
+    let mut mask = AddWatchFlags::IN_CREATE | AddWatchFlags::IN_MOVED_TO;
+    if config.scan_on_close_write {
+        mask |= AddWatchFlags::IN_CLOSE_WRITE;
+    }
+    if config.scan_on_open {
+        mask |= AddWatchFlags::IN_OPEN;
+    }
+
+    let mut watcher = Watcher {
+        inotify,
+        mask,
+        dirs: HashMap::new(),
+        exhausted: false,
+    };
+    for mount in &config.mounts {
+        watcher.watch_tree(mount, exclude);
+    }
+    if watcher.dirs.is_empty() {
+        return Err(Errno::ENOENT);
+    }
+    Ok(watcher)
+}
+
+impl Watcher {
+    /// Watches `root` and every directory below it on the same filesystem.
+    fn watch_tree(&mut self, root: &Path, exclude: &GlobSet) {
+        let walk = WalkDir::new(root)
+            .same_file_system(true)
+            .into_iter()
+            .filter_entry(|e| e.file_type().is_dir() && !exclude.is_match(e.path()));
+
+        for dir in walk.filter_map(|e| e.ok()) {
+            if self.exhausted {
+                return;
+            }
+            match self.inotify.add_watch(dir.path(), self.mask) {
+                Ok(wd) => {
+                    self.dirs.insert(wd, dir.into_path());
+                }
+                Err(Errno::ENOSPC) => {
+                    println!(
+                        "[REALTIME](WARN) inotify watch limit reached, {} and below are only partly watched",
+                        root.display()
+                    );
+                    self.exhausted = true;
+                }
+                Err(e) => println!(
+                    "[REALTIME](WARN) Cannot watch {}: {e}",
+                    dir.path().display()
+                ),
+            }
+        }
+    }
+}
+
+/// Handles events until the monitor is stopped.
+pub fn run(monitor: &Monitor, mut watcher: Watcher) {
+    'engine: while !monitor.stopped() {
+        let engine = monitor.engine();
+        let mut scanner = FileScanner::new(&engine.rules, &engine.options);
+
+        while !monitor.stopped() {
+            if monitor.is_stale(&engine) {
+                continue 'engine;
+            }
+            match wait_readable(watcher.inotify.as_fd()) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    println!("[REALTIME](ERROR) inotify poll failed, stopping: {e}");
+                    break 'engine;
+                }
+            }
+            let events = match watcher.inotify.read_events() {
+                Ok(events) => events,
+                Err(Errno::EAGAIN) | Err(Errno::EINTR) => continue,
+                Err(e) => {
+                    println!("[REALTIME](ERROR) inotify read failed, stopping: {e}");
+                    break 'engine;
+                }
+            };
+            for event in events {
+                handle(monitor, &engine, &mut scanner, &mut watcher, event);
+            }
+        }
+    }
+}
+
+fn handle(
+    monitor: &Monitor,
+    engine: &Engine,
+    scanner: &mut FileScanner,
+    watcher: &mut Watcher,
+    event: InotifyEvent,
+) {
+    if event.mask.contains(AddWatchFlags::IN_IGNORED) {
+        watcher.dirs.remove(&event.wd);
+        return;
+    }
+    if event.mask.contains(AddWatchFlags::IN_Q_OVERFLOW) {
+        println!("[REALTIME](WARN) inotify queue overflow, some events were lost");
+        return;
+    }
+    let (Some(dir), Some(name)) = (watcher.dirs.get(&event.wd), event.name) else {
+        return;
+    };
+    let path = dir.join(name);
+
+    if event.mask.contains(AddWatchFlags::IN_ISDIR) {
+        if event
+            .mask
+            .intersects(AddWatchFlags::IN_CREATE | AddWatchFlags::IN_MOVED_TO)
+        {
+            watcher.watch_tree(&path, &engine.exclude);
+        }
+        return;
+    }
+    // Files show up empty on create, they are scanned once written.
+    if event.mask == AddWatchFlags::IN_CREATE {
+        return;
+    }
+
+    // Our own open raises IN_OPEN again, the cached verdict ends the loop
+    // since the metadata is checked without opening the file.
+    let Ok(metadata) = fs::symlink_metadata(&path) else {
+        return;
+    };
+    let verdict = monitor.check(engine, scanner, &path, &metadata, || File::open(&path));
+    if let Verdict::Infected(detection) = verdict {
+        let event_name = if event.mask.contains(AddWatchFlags::IN_OPEN) {
+            "open"
+        } else {
+            "close_write"
+        };
+        monitor.report(engine, &path, &detection, event_name, false);
+    }
+}
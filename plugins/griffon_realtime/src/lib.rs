@@ -0,0 +1,233 @@
+mod cache;
+mod config;
+mod fanotify;
+mod inotify;
+mod monitor;
+
+use abi_stable::{
+    export_root_module,
+    prefix_type::PrefixTypeTrait,
+    sabi_extern_fn,
+    std_types::{RResult, RString, RVec, Tuple2},
+};
+use interface::{PluginI, PluginRoot, PluginRoot_Ref, install_host};
+use serde::Serialize;
+use static_analysis::{RuleLoadReport, list_rules};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use config::Backend;
+use monitor::{Engine, Monitor, Stats};
+
+/// The monitor while it runs, with the thread reading its events.
+static RUNNING: Mutex<Option<Running>> = Mutex::new(None);
+
+struct Running {
+    monitor: Arc<Monitor>,
+    thread: JoinHandle<()>,
+    backend: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct Status {
+    running: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backend: Option<&'static str>,
+    mounts: Vec<String>,
+    blocking: bool,
+    cached_verdicts: usize,
+    #[serde(flatten)]
+    stats: Stats,
+}
+
+#[derive(Debug, Serialize)]
+struct ReloadReport {
+    running: bool,
+    rules: usize,
+    load: RuleLoadReport,
+}
+
+fn status() -> Status {
+    match RUNNING.lock().unwrap().as_ref() {
+        Some(running) => {
+            let engine = running.monitor.engine();
+            Status {
+                running: !running.thread.is_finished(),
+                backend: Some(running.backend),
+                mounts: engine
+                    .config
+                    .mounts
+                    .iter()
+                    .map(|m| m.display().to_string())
+                    .collect(),
+                blocking: running.backend == "fanotify"
+                    && engine.config.scan_on_exec
+                    && engine.config.block_executables,
+                cached_verdicts: running.monitor.cached_verdicts(),
+                stats: running.monitor.stats(),
+            }
+        }
+        None => Status {
+            running: false,
+            backend: None,
+            mounts: Vec::new(),
+            blocking: false,
+            cached_verdicts: 0,
+            stats: Stats::default(),
+        },
+    }
+}
+
+fn start() -> Result<Status, String> {
+    {
+        let mut slot = RUNNING.lock().unwrap();
+        if slot.as_ref().is_some_and(|r| !r.thread.is_finished()) {
+            return Err("already running".to_string());
+        }
+
+        let (engine, _) = Engine::load();
+        let config = engine.config.clone();
+        let exclude = engine.exclude.clone();
+        let monitor = Arc::new(Monitor::new(engine));
+
+        let fanotify = match config.backend {
+            Backend::Inotify => None,
+            Backend::Fanotify => {
+                Some(fanotify::init(&config).map_err(|e| format!("fanotify unavailable: {e}"))?)
+            }
+            Backend::Auto => fanotify::init(&config)
+                .inspect_err(|e| {
+                    println!("[REALTIME](WARN) fanotify unavailable ({e}), falling back to inotify")
+                })
+                .ok(),
+        };
+
+        let worker = Arc::clone(&monitor);
+        let (thread, backend) = match fanotify {
+            Some(fanotify) => (
+                thread::spawn(move || fanotify::run(&worker, fanotify)),
+                "fanotify",
+            ),
+            None => {
+                let watcher = inotify::init(&config, &exclude)
+                    .map_err(|e| format!("inotify unavailable: {e}"))?;
+                (
+                    thread::spawn(move || inotify::run(&worker, watcher)),
+                    "inotify",
+                )
+            }
+        };
+
+        println!(
+            "[REALTIME](INFO) Watching {} with {backend}",
+            config
+                .mounts
+                .iter()
+                .map(|m| m.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        *slot = Some(Running {
+            monitor,
+            thread,
+            backend,
+        });
+    }
+    Ok(status())
+}
+
+fn stop() -> Status {
+    let running = RUNNING.lock().unwrap().take();
+    if let Some(running) = running {
+        running.monitor.stop();
+        let _ = running.thread.join();
+        println!("[REALTIME](INFO) Stopped");
+    }
+    status()
+}
+
+/// Rules, hashes and config are reloaded together; the verdict cache is emptied.
+fn reload_rules() -> ReloadReport {
+    let (engine, load) = Engine::load();
+    let rules = list_rules(&engine.rules).len();
+
+    let slot = RUNNING.lock().unwrap();
+    if let Some(running) = slot.as_ref() {
+        running.monitor.set_engine(engine);
+    }
+    ReloadReport {
+        running: slot.is_some(),
+        rules,
+        load,
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> RString {
+    match serde_json::to_string(value) {
+        Ok(json) => RString::from(json),
+        Err(e) => RString::from(format!("ERR json serialize: {e}")),
+    }
+}
+
+#[sabi_extern_fn]
+pub extern "C" fn init() -> RResult<RVec<Tuple2<RString, RString>>, RString> {
+    let mut info = RVec::new();
+
+    info.push(Tuple2(RString::from("author"), RString::from("Griffon")));
+    info.push(Tuple2(
+        RString::from("name"),
+        RString::from("realtime_protection"),
+    ));
+    info.push(Tuple2(
+        RString::from("description"),
+        RString::from("Scans files as they are opened, executed and written"),
+    ));
+    info.push(Tuple2(
+        RString::from("function"),
+        RString::from("start/stop/status/reload_rules"),
+    ));
+
+    let autostart = config::RealtimeConfig::load().is_ok_and(|c| c.autostart);
+    if autostart && let Err(e) = start() {
+        println!("[REALTIME](ERROR) Cannot start: {e}");
+    }
+
+    RResult::ROk(info)
+}
+
+#[sabi_extern_fn]
+extern "C" fn handle_message(msg: RString) -> RString {
+    println!("[REALTIME](msg) Received message: {}", msg.as_str());
+
+    let function = msg.as_str().split(' ').next().unwrap_or_default();
+    match function {
+        "fn:start" => match start() {
+            Ok(status) => to_json(&status),
+            Err(e) => RString::from(format!("ERR {e}")),
+        },
+        "fn:stop" => to_json(&stop()),
+        "fn:status" => to_json(&status()),
+        "fn:reload_rules" => to_json(&reload_rules()),
+        _ => RString::from(format!("ERR unknown function {}", msg.as_str())),
+    }
+}
+
+#[sabi_extern_fn]
+extern "C" fn shutdown() -> RResult<(), RString> {
+    stop();
+    RResult::ROk(())
+}
+
+#[export_root_module]
+pub fn get_library() -> PluginRoot_Ref {
+    PluginRoot {
+        plugin: PluginI {
+            init,
+            handle_message,
+            set_host: install_host,
+            shutdown,
+        }
+        .leak_into_prefix(),
+    }
+    .leak_into_prefix()
+}
@@ -0,0 +1,323 @@
+use globset::GlobSet;
+use nix::errno::Errno;
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+use serde::Serialize;
+use static_analysis::{
+    FileScanner, HashDatabase, RuleLoadReport, Rules, ScanError, ScanOptions, ScanResult, Severity,
+    load_yara_rules_cached,
+};
+use std::collections::VecDeque;
+use std::fs::{File, Metadata};
+use std::io;
+use std::os::fd::BorrowedFd;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cache::{Verdict, VerdictCache};
+use crate::config::RealtimeConfig;
+
+/// How long backends wait for events before checking for a stop request or new rules.
+const POLL_INTERVAL_MS: u16 = 500;
+/// Detections kept for `status`.
+const RECENT_DETECTIONS: usize = 100;
+
+/// Config and compiled rules, replaced as a whole by `reload_rules`.
+pub struct Engine {
+    pub config: RealtimeConfig,
+    pub options: ScanOptions,
+    pub rules: Rules,
+    pub exclude: GlobSet,
+}
+
+impl Engine {
+    pub fn load() -> (Self, RuleLoadReport) {
+        let config = RealtimeConfig::load().unwrap_or_else(|e| {
+            println!("[REALTIME](ERROR) Bad config, using defaults: {e}");
+            RealtimeConfig::default()
+        });
+        let exclude = config.exclude_set().unwrap_or_else(|e| {
+            println!("[REALTIME](ERROR) Bad exclude pattern, nothing excluded: {e}");
+            GlobSet::empty()
+        });
+
+        let (rules, report) = load_yara_rules_cached(&config.rules_dir, &config.cache_dir);
+        if report.skipped_files() > 0 {
+            println!(
+                "[REALTIME](WARN) {} rule files skipped, reload_rules lists the errors",
+                report.skipped_files()
+            );
+        }
+
+        let hashes = HashDatabase::load_dir(&config.hashes_dir).unwrap_or_else(|e| {
+            println!("[REALTIME](ERROR) Cannot load hash feeds: {e}");
+            HashDatabase::new()
+        });
+        let mut options = config.scan_options();
+        options.hashes = (!hashes.is_empty()).then(|| Arc::new(hashes));
+
+        let engine = Self {
+            options,
+            exclude,
+            config,
+            rules,
+        };
+        (engine, report)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Detection {
+    pub path: String,
+    pub detection: String,
+    /// `open`, `exec` or `close_write`.
+    pub event: &'static str,
+    pub blocked: bool,
+    /// Vault entry id, or the reason the file could not be quarantined.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quarantine: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub at: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Stats {
+    pub scanned: u64,
+    pub cache_hits: u64,
+    pub blocked: u64,
+    /// Most recent last.
+    pub detections: VecDeque<Detection>,
+}
+
+/// State shared by the plugin functions and the backend thread.
+pub struct Monitor {
+    engine: RwLock<Arc<Engine>>,
+    cache: Mutex<VerdictCache>,
+    stats: Mutex<Stats>,
+    stop: AtomicBool,
+}
+
+impl Monitor {
+    pub fn new(engine: Engine) -> Self {
+        let cache = VerdictCache::new(engine.config.cache_entries);
+        Self {
+            engine: RwLock::new(Arc::new(engine)),
+            cache: Mutex::new(cache),
+            stats: Mutex::new(Stats::default()),
+            stop: AtomicBool::new(false),
+        }
+    }
+
+    pub fn engine(&self) -> Arc<Engine> {
+        self.engine.read().unwrap().clone()
+    }
+
+    /// Backends pick the new rules up within `POLL_INTERVAL_MS`.
+    pub fn set_engine(&self, engine: Engine) {
+        *self.engine.write().unwrap() = Arc::new(engine);
+        self.cache.lock().unwrap().clear();
+    }
+
+    /// True once `engine` is no longer the current one and scanners built on it should be dropped.
+    pub fn is_stale(&self, engine: &Arc<Engine>) -> bool {
+        !Arc::ptr_eq(engine, &self.engine.read().unwrap())
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    pub fn cached_verdicts(&self) -> usize {
+        self.cache.lock().unwrap().len()
+    }
+
+    /// Verdict for the file at `path`, from the cache or by scanning what `open` returns.
+    /// Scan failures let the access through: a broken rule must not lock users out of their files.
+    pub fn check(
+        &self,
+        engine: &Engine,
+        scanner: &mut FileScanner,
+        path: &Path,
+        metadata: &Metadata,
+        open: impl FnOnce() -> io::Result<File>,
+    ) -> Verdict {
+        if !metadata.is_file() || engine.exclude.is_match(path) {
+            return Verdict::Unscanned;
+        }
+        if engine
+            .options
+            .max_file_size
+            .is_some_and(|limit| metadata.len() > limit)
+        {
+            return Verdict::Unscanned;
+        }
+        if let Some(verdict) = self.cache.lock().unwrap().get(metadata) {
+            self.stats.lock().unwrap().cache_hits += 1;
+            return verdict;
+        }
+
+        let outcome = open()
+            .map_err(|source| ScanError::Unreadable {
+                path: path.to_path_buf(),
+                source,
+            })
+            .and_then(|file| scanner.scan_open_file(file, path));
+        let verdict = match outcome {
+            Ok(result) if result.is_clean() => Verdict::Clean,
+            Ok(result) => Verdict::Infected(describe(&result)),
+            Err(e) => {
+                println!("[REALTIME](WARN) {e}");
+                Verdict::Unscanned
+            }
+        };
+
+        self.stats.lock().unwrap().scanned += 1;
+        self.cache.lock().unwrap().insert(metadata, verdict.clone());
+        verdict
+    }
+
+    /// Logs a detection, and hands the file to the quarantine when configured.
+    pub fn report(
+        &self,
+        engine: &Engine,
+        path: &Path,
+        detection: &str,
+        event: &'static str,
+        blocked: bool,
+    ) {
+        println!(
+            "[REALTIME](ALERT) {} on {event}: {detection}{}",
+            path.display(),
+            if blocked { " (blocked)" } else { "" }
+        );
+
+        let quarantine = engine.config.quarantine.then(|| {
+            let request = serde_json::json!({
+                "op": "quarantine_file",
+                "path": path.display().to_string(),
+                "detection": detection,
+            });
+            match interface::broker_request(&request.to_string()) {
+                Ok(reply) => serde_json::from_str::<serde_json::Value>(&reply)
+                    .ok()
+                    .and_then(|entry| entry["id"].as_str().map(str::to_string))
+                    .unwrap_or(reply),
+                Err(e) => {
+                    println!(
+                        "[REALTIME](ERROR) Cannot quarantine {}: {e}",
+                        path.display()
+                    );
+                    format!("failed: {e}")
+                }
+            }
+        });
+
+        let mut stats = self.stats.lock().unwrap();
+        if blocked {
+            stats.blocked += 1;
+        }
+        if stats.detections.len() == RECENT_DETECTIONS {
+            stats.detections.pop_front();
+        }
+        stats.detections.push_back(Detection {
+            path: path.display().to_string(),
+            detection: detection.to_string(),
+            event,
+            blocked,
+            quarantine,
+            at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        });
+    }
+}
+
+/// `feed:threat` for hash hits, otherwise `namespace:rule` for each matching rule
+/// and `heuristic:id` for each high-severity finding.
+fn describe(result: &ScanResult) -> String {
+    if let Some(hash) = &result.hash_match {
+        return format!("{}:{}", hash.feed, hash.threat);
+    }
+    result
+        .matches
+        .iter()
+        .map(|m| format!("{}:{}", m.namespace, m.identifier))
+        .chain(
+            result
+                .findings
+                .iter()
+                .filter(|f| f.severity == Severity::High)
+                .map(|f| format!("heuristic:{}", f.id)),
+        )
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Waits up to `POLL_INTERVAL_MS` for `fd` to become readable.
+pub fn wait_readable(fd: BorrowedFd) -> nix::Result<bool> {
+    let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+    match poll(&mut fds, PollTimeout::from(POLL_INTERVAL_MS)) {
+        Ok(n) => Ok(n > 0),
+        Err(Errno::EINTR) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use static_analysis::{Finding, HashAlgorithm, HashMatch, RuleMatch};
+
+    fn rule(namespace: &str, identifier: &str) -> RuleMatch {
+        RuleMatch {
+            namespace: namespace.to_string(),
+            identifier: identifier.to_string(),
+            tags: Vec::new(),
+            metadata: Vec::new(),
+            description: None,
+            severity: None,
+            author: None,
+            strings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn describe_lists_rules_and_high_findings() {
+        let result = ScanResult {
+            matches: vec![rule("malware", "Mirai"), rule("default", "Eicar")],
+            findings: vec![
+                Finding::new("elf.packed", Severity::Medium, String::new()),
+                Finding::new("script.reverse_shell", Severity::High, String::new()),
+            ],
+            ..ScanResult::default()
+        };
+        assert_eq!(
+            describe(&result),
+            "malware:Mirai, default:Eicar, heuristic:script.reverse_shell"
+        );
+    }
+
+    #[test]
+    fn describe_prefers_the_hash_feed() {
+        let result = ScanResult {
+            hash_match: Some(HashMatch {
+                algorithm: HashAlgorithm::Sha256,
+                hash: String::new(),
+                feed: "bazaar".to_string(),
+                threat: "AgentTesla".to_string(),
+            }),
+            matches: vec![rule("malware", "Mirai")],
+            ..ScanResult::default()
+        };
+        assert_eq!(describe(&result), "bazaar:AgentTesla");
+    }
+}
@@ -0,0 +1,626 @@
+use nix::errno::Errno;
+use nix::sys::signal::kill;
+use nix::unistd::{Pid, geteuid, getpgid};
+use serde::Serialize;
+use static_analysis::{Finding, Severity};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::RootkitConfig;
+
+/// Files and directories created by well-known userland and kernel rootkits.
+static ROOTKIT_PATHS: &[(&str, &str)] = &[
+    ("/reptile", "Reptile"),
+    ("/lib/udev/reptile", "Reptile"),
+    ("/XxJynx", "Jynx"),
+    ("/lib/libselinux.so", "BEURK"),
+    ("/lib/libkeyutils.so.1.9", "Ebury"),
+    ("/lib64/libkeyutils.so.1.9", "Ebury"),
+    ("/lib/x86_64-linux-gnu/libkeyutils.so.1.9", "Ebury"),
+    ("/sbin/initsk12", "SucKIT"),
+    ("/sbin/initxrk", "SucKIT"),
+    ("/usr/share/locale/sk/.sk12", "SucKIT"),
+    ("/uNFuNF", "Phalanx"),
+    ("/etc/host.ph1", "Phalanx"),
+    ("/bin/host.ph1", "Phalanx"),
+    ("/proc/knark", "Knark"),
+    ("/dev/.lib", "generic"),
+    ("/usr/bin/sourcemask", "Adore"),
+];
+/// Module names of public LKM rootkits.
+static ROOTKIT_MODULES: &[&str] = &[
+    "diamorphine",
+    "reptile",
+    "reptile_module",
+    "suterusu",
+    "kovid",
+    "adore",
+    "adore_ng",
+    "knark",
+    "rootfoo",
+    "nuk3gh0st",
+];
+/// `/dev` directories that legitimately hold regular files.
+static DEV_FILE_DIRS: [&str; 4] = ["shm", "mqueue", "pts", "hugepages"];
+static TEMP_DIRS: [&str; 3] = ["/tmp/", "/var/tmp/", "/dev/shm/"];
+/// `IFF_PROMISC` in `/sys/class/net/<if>/flags`.
+const IFF_PROMISC: u64 = 0x100;
+/// Taint bits of `kernel.tainted` worth reporting: forced load, out-of-tree, unsigned.
+static TAINTS: [(u32, &str); 3] = [
+    (1, "module force-loaded"),
+    (12, "out-of-tree module loaded"),
+    (13, "unsigned module loaded"),
+];
+/// Pids probed above the highest listed one when `max_pid` is 0.
+const PROBE_MARGIN: u32 = 4096;
+/// Used when `kernel.pid_max` cannot be read.
+const DEFAULT_PID_MAX: u32 = 32768;
+/// Processes listed per `LD_PRELOAD` value.
+const MAX_PIDS_SHOWN: usize = 10;
+
+pub type CheckFn = fn(&RootkitConfig) -> Result<Vec<Finding>, String>;
+
+/// Every check, by the name `check` accepts.
+pub static CHECKS: [(&str, CheckFn); 5] = [
+    ("processes", hidden_processes),
+    ("modules", hidden_modules),
+    ("ld_preload", ld_preload),
+    ("promiscuous", promiscuous_interfaces),
+    ("rootkit_paths", rootkit_paths),
+];
+
+#[derive(Debug, Serialize)]
+pub struct CheckSummary {
+    pub name: &'static str,
+    pub findings: usize,
+    /// The check could not run, its findings are missing from the report.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RootkitReport {
+    /// No finding at `Severity::High`, the threshold of `ScanResult::is_clean`.
+    pub clean: bool,
+    pub checks: Vec<CheckSummary>,
+    pub findings: Vec<Finding>,
+}
+
+/// Runs the named checks, every check when `names` is empty.
+pub fn run(config: &RootkitConfig, names: &[&str]) -> Result<RootkitReport, String> {
+    if let Some(unknown) = names
+        .iter()
+        .find(|name| !CHECKS.iter().any(|(check, _)| check == *name))
+    {
+        return Err(format!("unknown check {unknown}"));
+    }
+
+    let mut checks = Vec::new();
+    let mut findings = Vec::new();
+    for (name, check) in &CHECKS {
+        if !names.is_empty() && !names.contains(name) {
+            continue;
+        }
+        let (found, error) = match check(config) {
+            Ok(found) => (found, None),
+            Err(e) => (Vec::new(), Some(e)),
+        };
+        checks.push(CheckSummary {
+            name,
+            findings: found.len(),
+            error,
+        });
+        findings.extend(found);
+    }
+
+    Ok(RootkitReport {
+        clean: findings.iter().all(|f| f.severity < Severity::High),
+        checks,
+        findings,
+    })
+}
+
+fn listed_pids() -> Result<HashSet<u32>, String> {
+    let dir = fs::read_dir("/proc").map_err(|e| format!("/proc: {e}"))?;
+    Ok(dir
+        .filter_map(|e| e.ok()?.file_name().to_str()?.parse().ok())
+        .collect())
+}
+
+/// A field of `/proc/<pid>/status`, `None` once the process is gone.
+fn status_field(pid: u32, field: &str) -> Option<String> {
+    let status = fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+    status.lines().find_map(|line| {
+        line.strip_prefix(field)?
+            .strip_prefix(':')
+            .map(|v| v.trim().to_string())
+    })
+}
+
+fn describe_pid(pid: u32) -> String {
+    let cmdline = fs::read(format!("/proc/{pid}/cmdline"))
+        .map(|raw| {
+            String::from_utf8_lossy(&raw)
+                .trim_end_matches('\0')
+                .replace('\0', " ")
+        })
+        .unwrap_or_default();
+    if cmdline.is_empty() {
+        let comm = status_field(pid, "Name").unwrap_or_default();
+        format!("{pid} [{comm}]")
+    } else {
+        format!("{pid} ({cmdline})")
+    }
+}
+
+/// What the kernel says about a pid when asked directly rather than through `/proc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Probe {
+    Gone,
+    Alive,
+    /// `kill` answered `EPERM`: some process exists, but one of another user,
+    /// which a non-root runner may legitimately not see under `/proc`.
+    Denied,
+}
+
+/// Why the `/proc` listing may legitimately miss processes, in which case
+/// hidden ones cannot be told apart. Root sees every process whatever the
+/// `hidepid` mount option.
+fn partial_proc_view() -> Option<String> {
+    let euid = geteuid();
+    if euid.is_root() {
+        return None;
+    }
+    let mounts = fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+    hides_pids(&mounts)
+        .then(|| format!("/proc is mounted with hidepid and the plugin runs as uid {euid}"))
+}
+
+/// True when the `/proc` mount in `mounts` hides other users' processes.
+fn hides_pids(mounts: &str) -> bool {
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (_, target, kind) = (fields.next()?, fields.next()?, fields.next()?);
+            (target == "/proc" && kind == "proc").then(|| fields.next())?
+        })
+        .flat_map(|options| options.split(','))
+        .filter_map(|option| option.strip_prefix("hidepid="))
+        .any(|value| !matches!(value, "0" | "off"))
+}
+
+/// A process whose `/proc/<pid>` can be opened but is missing from the directory
+/// listing is hidden by a hooked `getdents`. Parents missing from the listing
+/// give the same answer without probing. Probing also asks the kernel directly
+/// through `kill(pid, 0)` and `getpgid`, which finds processes hidden from
+/// `/proc` altogether.
+///
+/// Skipped when `/proc` hides other users' processes from the runner. Pids a
+/// non-root runner may not signal could be other users' processes: they are
+/// only reported at medium severity.
+fn hidden_processes(config: &RootkitConfig) -> Result<Vec<Finding>, String> {
+    if let Some(reason) = partial_proc_view() {
+        return Err(format!("skipped, {reason}: run it as root"));
+    }
+    let listed = listed_pids()?;
+    let mut hidden: BTreeMap<u32, (&str, Severity)> = BTreeMap::new();
+
+    for &pid in &listed {
+        let parent = status_field(pid, "PPid").and_then(|p| p.parse::<u32>().ok());
+        if let Some(parent) = parent
+            && parent != 0
+            && !listed.contains(&parent)
+            && Path::new(&format!("/proc/{parent}")).exists()
+        {
+            hidden.insert(parent, ("parent of a listed process", Severity::High));
+        }
+    }
+
+    if config.probe_pids {
+        let pid_max = fs::read_to_string("/proc/sys/kernel/pid_max")
+            .ok()
+            .and_then(|m| m.trim().parse().ok())
+            .unwrap_or(DEFAULT_PID_MAX);
+        for pid in (1..=probe_limit(config, &listed, pid_max)).filter(|pid| !listed.contains(pid)) {
+            if Path::new(&format!("/proc/{pid}")).exists() {
+                hidden
+                    .entry(pid)
+                    .or_insert(("found by probing /proc", Severity::High));
+                continue;
+            }
+            match probe(pid) {
+                Probe::Gone => {}
+                Probe::Alive => {
+                    hidden
+                        .entry(pid)
+                        .or_insert(("answers signals but has no /proc entry", Severity::High));
+                }
+                Probe::Denied => {
+                    hidden.entry(pid).or_insert((
+                        "exists for kill but has no /proc entry, \
+                         inconclusive as it may belong to another user",
+                        Severity::Medium,
+                    ));
+                }
+            }
+        }
+    }
+
+    // Threads resolve under /proc without being listed, and processes may have
+    // started since the listing: only leaders still missing from a new listing count.
+    // Without a /proc entry the leader cannot be told, the process only has to be alive.
+    let relisted = listed_pids()?;
+    hidden.retain(|&pid, _| {
+        !relisted.contains(&pid)
+            && match status_field(pid, "Tgid") {
+                Some(tgid) => tgid == pid.to_string(),
+                None => !Path::new(&format!("/proc/{pid}")).exists() && probe(pid) != Probe::Gone,
+            }
+    });
+
+    Ok(hidden
+        .into_iter()
+        .map(|(pid, (how, severity))| {
+            Finding::new(
+                "rootkit.hidden_process",
+                severity,
+                format!(
+                    "process {} is hidden from the /proc listing ({how})",
+                    describe_pid(pid)
+                ),
+            )
+        })
+        .collect())
+}
+
+/// Highest pid probed: `max_pid`, or by default the highest listed pid plus
+/// `PROBE_MARGIN`, within `pid_max`.
+fn probe_limit(config: &RootkitConfig, listed: &HashSet<u32>, pid_max: u32) -> u32 {
+    if config.max_pid != 0 {
+        return config.max_pid;
+    }
+    let highest = listed.iter().copied().max().unwrap_or(0);
+    highest.saturating_add(PROBE_MARGIN).min(pid_max)
+}
+
+/// Asks the kernel rather than `/proc`. Root is never denied, so `Denied`
+/// only comes up for non-root runners.
+fn probe(pid: u32) -> Probe {
+    let Ok(raw) = i32::try_from(pid) else {
+        return Probe::Gone;
+    };
+    let pid = Pid::from_raw(raw);
+    match kill(pid, None) {
+        Ok(()) => Probe::Alive,
+        Err(Errno::EPERM) => Probe::Denied,
+        Err(_) if getpgid(Some(pid)).is_ok() => Probe::Alive,
+        Err(_) => Probe::Gone,
+    }
+}
+
+/// Loadable modules appear both in `/proc/modules` and as `/sys/module/<name>`
+/// with an `initstate`; rootkits usually unlink themselves from one only.
+fn hidden_modules(config: &RootkitConfig) -> Result<Vec<Finding>, String> {
+    let proc_modules = match fs::read_to_string("/proc/modules") {
+        Ok(text) => text,
+        // Kernel built without loadable module support.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("/proc/modules: {e}")),
+    };
+    let listed: HashSet<String> = proc_modules
+        .lines()
+        .filter_map(|l| l.split_whitespace().next())
+        .map(str::to_string)
+        .collect();
+    let sysfs: HashSet<String> = fs::read_dir("/sys/module")
+        .map_err(|e| format!("/sys/module: {e}"))?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().join("initstate").exists())
+        .filter_map(|e| e.file_name().into_string().ok())
+        .collect();
+
+    let mut findings = Vec::new();
+    let sorted = |names: &HashSet<String>, other: &HashSet<String>| {
+        let mut missing: Vec<String> = names.difference(other).cloned().collect();
+        missing.sort();
+        missing
+    };
+    for name in sorted(&sysfs, &listed) {
+        findings.push(Finding::new(
+            "rootkit.hidden_module",
+            Severity::High,
+            format!("module {name} is loaded (/sys/module) but hidden from /proc/modules"),
+        ));
+    }
+    for name in sorted(&listed, &sysfs) {
+        findings.push(Finding::new(
+            "rootkit.hidden_module",
+            Severity::Medium,
+            format!("module {name} is in /proc/modules but missing from /sys/module"),
+        ));
+    }
+
+    let known: Vec<&String> = listed
+        .union(&sysfs)
+        .filter(|name| {
+            ROOTKIT_MODULES.contains(&name.as_str())
+                || config.rootkit_modules.iter().any(|m| m == *name)
+        })
+        .collect();
+    for name in known {
+        findings.push(Finding::new(
+            "rootkit.known_module",
+            Severity::High,
+            format!("module {name} has the name of a known rootkit"),
+        ));
+    }
+
+    let tainted: u64 = fs::read_to_string("/proc/sys/kernel/tainted")
+        .ok()
+        .and_then(|t| t.trim().parse().ok())
+        .unwrap_or(0);
+    let reasons: Vec<&str> = TAINTS
+        .iter()
+        .filter(|(bit, _)| tainted & (1 << bit) != 0)
+        .map(|(_, reason)| *reason)
+        .collect();
+    if !reasons.is_empty() {
+        findings.push(Finding::new(
+            "rootkit.tainted_kernel",
+            Severity::Low,
+            format!("kernel tainted: {}", reasons.join(", ")),
+        ));
+    }
+    Ok(findings)
+}
+
+/// `/etc/ld.so.preload` and `LD_PRELOAD` in the environment of running processes.
+fn ld_preload(_: &RootkitConfig) -> Result<Vec<Finding>, String> {
+    let mut findings = Vec::new();
+    let preload = Path::new("/etc/ld.so.preload");
+
+    match fs::read_to_string(preload) {
+        Ok(text) => {
+            let libraries: Vec<&str> = text
+                .lines()
+                .map(|l| l.split('#').next().unwrap_or_default().trim())
+                .filter(|l| !l.is_empty())
+                .collect();
+            if !libraries.is_empty() {
+                findings.push(Finding::new(
+                    "rootkit.ld_so_preload",
+                    Severity::High,
+                    format!(
+                        "/etc/ld.so.preload injects {} into every program",
+                        libraries.join(", ")
+                    ),
+                ));
+            }
+            let listed = fs::read_dir("/etc").is_ok_and(|dir| {
+                dir.filter_map(|e| e.ok())
+                    .any(|e| e.file_name() == "ld.so.preload")
+            });
+            if !listed {
+                findings.push(Finding::new(
+                    "rootkit.hidden_file",
+                    Severity::High,
+                    "/etc/ld.so.preload exists but is hidden from the /etc listing".to_string(),
+                ));
+            }
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(format!("{}: {e}", preload.display())),
+    }
+
+    // Same value across processes is reported once.
+    let mut preloads: BTreeMap<String, Vec<u32>> = BTreeMap::new();
+    for pid in listed_pids()? {
+        let Ok(environ) = fs::read(format!("/proc/{pid}/environ")) else {
+            continue;
+        };
+        for var in environ.split(|&b| b == 0) {
+            if let Some(value) = var.strip_prefix(b"LD_PRELOAD=")
+                && !value.is_empty()
+            {
+                preloads
+                    .entry(String::from_utf8_lossy(value).into_owned())
+                    .or_default()
+                    .push(pid);
+            }
+        }
+    }
+    for (value, mut pids) in preloads {
+        pids.sort_unstable();
+        let suspicious = value
+            .split([' ', ':'])
+            .filter(|l| !l.is_empty())
+            .any(|lib| TEMP_DIRS.iter().any(|d| lib.starts_with(d)) || !Path::new(lib).exists());
+        let shown: Vec<String> = pids
+            .iter()
+            .take(MAX_PIDS_SHOWN)
+            .map(u32::to_string)
+            .collect();
+        findings.push(Finding::new(
+            "rootkit.ld_preload_env",
+            if suspicious {
+                Severity::High
+            } else {
+                Severity::Medium
+            },
+            format!(
+                "LD_PRELOAD={value} set for {} process(es): {}{}",
+                pids.len(),
+                shown.join(", "),
+                if pids.len() > MAX_PIDS_SHOWN {
+                    ", ..."
+                } else {
+                    ""
+                }
+            ),
+        ));
+    }
+    Ok(findings)
+}
+
+/// Interfaces capturing all traffic, as sniffers and some backdoors do.
+fn promiscuous_interfaces(config: &RootkitConfig) -> Result<Vec<Finding>, String> {
+    let dir = fs::read_dir("/sys/class/net").map_err(|e| format!("/sys/class/net: {e}"))?;
+    let mut findings = Vec::new();
+    let mut interfaces: Vec<PathBuf> = dir.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    interfaces.sort();
+
+    for interface in interfaces {
+        let name = interface
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let flags = fs::read_to_string(interface.join("flags"))
+            .ok()
+            .and_then(|f| u64::from_str_radix(f.trim().trim_start_matches("0x"), 16).ok())
+            .unwrap_or(0);
+        // Bridge ports are put in promiscuous mode by the bridge itself.
+        if flags & IFF_PROMISC == 0
+            || interface.join("brport").exists()
+            || config.promiscuous_allowed.contains(&name)
+        {
+            continue;
+        }
+        findings.push(Finding::new(
+            "rootkit.promiscuous_interface",
+            Severity::Medium,
+            format!("{name} is in promiscuous mode, traffic for other hosts is captured"),
+        ));
+    }
+    Ok(findings)
+}
+
+/// Known rootkit files, and regular files hidden among the device nodes of `/dev`.
+fn rootkit_paths(config: &RootkitConfig) -> Result<Vec<Finding>, String> {
+    let mut findings = Vec::new();
+    let known = ROOTKIT_PATHS
+        .iter()
+        .map(|(path, name)| (PathBuf::from(path), format!("left by the {name} rootkit")))
+        .chain(
+            config
+                .rootkit_paths
+                .iter()
+                .map(|p| (p.clone(), "listed in rootkit_paths".to_string())),
+        );
+    for (path, why) in known {
+        if fs::symlink_metadata(&path).is_ok() {
+            findings.push(Finding::new(
+                "rootkit.known_path",
+                Severity::High,
+                format!("{} exists, {why}", path.display()),
+            ));
+        }
+    }
+
+    let mut dev_files = Vec::new();
+    dev_regular_files(Path::new("/dev"), 0, &mut dev_files);
+    for path in dev_files {
+        findings.push(Finding::new(
+            "rootkit.dev_file",
+            Severity::Medium,
+            format!(
+                "{} is a regular file among device nodes, a common hiding place",
+                path.display()
+            ),
+        ));
+    }
+    Ok(findings)
+}
+
+/// Regular files of `/dev` up to three levels deep, outside `DEV_FILE_DIRS`.
+fn dev_regular_files(dir: &Path, depth: usize, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<_> = entries.filter_map(|e| e.ok()).collect();
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let name = entry.file_name();
+        if file_type.is_file() {
+            found.push(entry.path());
+        } else if file_type.is_dir()
+            && depth < 2
+            && !(depth == 0 && DEV_FILE_DIRS.iter().any(|d| name == *d))
+        {
+            dev_regular_files(&entry.path(), depth + 1, found);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("griffon-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn probe_limit_follows_the_listing_within_pid_max() {
+        let mut config = RootkitConfig::default();
+        let listed: HashSet<u32> = [1, 200, 5000].into_iter().collect();
+        assert_eq!(
+            probe_limit(&config, &listed, 4_194_304),
+            5000 + PROBE_MARGIN
+        );
+        assert_eq!(probe_limit(&config, &listed, 6000), 6000);
+        assert_eq!(probe_limit(&config, &HashSet::new(), 32768), PROBE_MARGIN);
+
+        config.max_pid = 100;
+        assert_eq!(probe_limit(&config, &listed, 6000), 100);
+    }
+
+    #[test]
+    fn hidepid_is_read_from_the_proc_mount() {
+        let mounts =
+            |options: &str| format!("sysfs /sys sysfs rw 0 0\nproc /proc proc {options} 0 0\n");
+        assert!(!hides_pids(&mounts("rw,nosuid,nodev,noexec,relatime")));
+        assert!(!hides_pids(&mounts("rw,relatime,hidepid=0")));
+        assert!(!hides_pids(&mounts("rw,relatime,hidepid=off")));
+        assert!(hides_pids(&mounts("rw,relatime,hidepid=2")));
+        assert!(hides_pids(&mounts("rw,relatime,hidepid=invisible,gid=10")));
+        // Another proc mount, e.g. in a container root, does not count.
+        assert!(!hides_pids("proc /srv/jail/proc proc rw,hidepid=2 0 0\n"));
+    }
+
+    #[test]
+    fn dev_walk_finds_regular_files_outside_the_usual_dirs() {
+        let dev = temp_dir("dev");
+        for dir in ["shm", "a/b/c", "a/shm"] {
+            fs::create_dir_all(dev.join(dir)).unwrap();
+        }
+        for file in [
+            ".hidden",
+            "shm/segment",
+            "a/x",
+            "a/b/y",
+            "a/b/c/z",
+            "a/shm/w",
+        ] {
+            fs::write(dev.join(file), "").unwrap();
+        }
+        std::os::unix::fs::symlink("/etc/passwd", dev.join("link")).unwrap();
+
+        let mut found = Vec::new();
+        dev_regular_files(&dev, 0, &mut found);
+        let found: Vec<_> = found
+            .iter()
+            .map(|p| p.strip_prefix(&dev).unwrap().to_str().unwrap())
+            .collect();
+        // shm is only skipped at the top, three levels are walked.
+        assert_eq!(found, [".hidden", "a/b/y", "a/shm/w", "a/x"]);
+        fs::remove_dir_all(dev).unwrap();
+    }
+}
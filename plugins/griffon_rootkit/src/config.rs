@@ -0,0 +1,61 @@
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Looked up in the working directory when `GRIFFON_ROOTKIT_CONFIG` is not set.
+static DEFAULT_CONFIG_PATH: &str = "./griffon_rootkit.toml";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RootkitConfig {
+    /// Look for processes hidden from the `/proc` listing by probing every pid.
+    /// The check needs root when `/proc` is mounted with `hidepid`.
+    pub probe_pids: bool,
+    /// Highest pid probed, 0 probes a margin above the highest listed pid,
+    /// within `kernel.pid_max`.
+    pub max_pid: u32,
+    /// Added to the built-in list of files and directories left by known rootkits.
+    pub rootkit_paths: Vec<PathBuf>,
+    /// Added to the built-in list of known rootkit module names.
+    pub rootkit_modules: Vec<String>,
+    /// Interfaces expected in promiscuous mode, such as a capture interface.
+    pub promiscuous_allowed: Vec<String>,
+}
+
+impl Default for RootkitConfig {
+    fn default() -> Self {
+        Self {
+            probe_pids: true,
+            max_pid: 0,
+            rootkit_paths: Vec::new(),
+            rootkit_modules: Vec::new(),
+            promiscuous_allowed: Vec::new(),
+        }
+    }
+}
+
+impl RootkitConfig {
+    /// Loads the config from `GRIFFON_ROOTKIT_CONFIG` or `./griffon_rootkit.toml`.
+    /// A missing file is not an error: the defaults are used instead.
+    pub fn load() -> io::Result<Self> {
+        let path = std::env::var_os("GRIFFON_ROOTKIT_CONFIG")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH));
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::from_file(&path)
+    }
+
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let raw = fs::read_to_string(path)?;
+        toml::from_str(&raw).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {e}", path.display()),
+            )
+        })
+    }
+}
@@ -0,0 +1,87 @@
+mod checks;
+mod config;
+
+use abi_stable::{
+    export_root_module,
+    prefix_type::PrefixTypeTrait,
+    sabi_extern_fn,
+    std_types::{RResult, RString, RVec, Tuple2},
+};
+use interface::{PluginI, PluginRoot, PluginRoot_Ref, install_host, noop_shutdown};
+use serde::Serialize;
+
+use config::RootkitConfig;
+
+fn to_json<T: Serialize>(value: &T) -> RString {
+    match serde_json::to_string(value) {
+        Ok(json) => RString::from(json),
+        Err(e) => RString::from(format!("ERR json serialize: {e}")),
+    }
+}
+
+/// The config is read on each call, like the persistence audit.
+fn run_checks(names: &[&str]) -> RString {
+    let config = RootkitConfig::load().unwrap_or_else(|e| {
+        println!("[ROOTKIT](ERROR) Bad config, using defaults: {e}");
+        RootkitConfig::default()
+    });
+    match checks::run(&config, names) {
+        Ok(report) => {
+            println!(
+                "[ROOTKIT](INFO) {} checks run, {} findings",
+                report.checks.len(),
+                report.findings.len()
+            );
+            to_json(&report)
+        }
+        Err(e) => RString::from(format!("ERR {e}")),
+    }
+}
+
+#[sabi_extern_fn]
+pub extern "C" fn init() -> RResult<RVec<Tuple2<RString, RString>>, RString> {
+    let mut info = RVec::new();
+
+    info.push(Tuple2(RString::from("author"), RString::from("Griffon")));
+    info.push(Tuple2(
+        RString::from("name"),
+        RString::from("rootkit_checks"),
+    ));
+    info.push(Tuple2(
+        RString::from("description"),
+        RString::from(
+            "Looks for hidden processes and modules, preloads, sniffing interfaces and rootkit files",
+        ),
+    ));
+    info.push(Tuple2(RString::from("function"), RString::from("check")));
+
+    RResult::ROk(info)
+}
+
+#[sabi_extern_fn]
+extern "C" fn handle_message(msg: RString) -> RString {
+    println!("[ROOTKIT](msg) Received message: {}", msg.as_str());
+
+    let (function, arg) = match msg.as_str().split_once(' ') {
+        Some((function, arg)) => (function, arg.trim()),
+        None => (msg.as_str(), ""),
+    };
+    match function {
+        "fn:check" => run_checks(&arg.split_whitespace().collect::<Vec<_>>()),
+        _ => RString::from(format!("ERR unknown function {}", msg.as_str())),
+    }
+}
+
+#[export_root_module]
+pub fn get_library() -> PluginRoot_Ref {
+    PluginRoot {
+        plugin: PluginI {
+            init,
+            handle_message,
+            set_host: install_host,
+            shutdown: noop_shutdown,
+        }
+        .leak_into_prefix(),
+    }
+    .leak_into_prefix()
+}
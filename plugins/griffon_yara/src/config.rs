@@ -0,0 +1,164 @@
+use serde::Deserialize;
+use static_analysis::{ArchiveLimits, ProcessScanOptions, RuleFilter, SCAN_TIMEOUT, ScanOptions};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Looked up in the working directory when `GRIFFON_YARA_CONFIG` is not set.
+static DEFAULT_CONFIG_PATH: &str = "./griffon_yara.toml";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct YaraConfig {
+    pub rules_dir: PathBuf,
+    /// Where compiled rules are kept between starts.
+    pub cache_dir: PathBuf,
+    /// SHA-256/MD5 feed files (`.txt`, `.csv`, `.hashes`), checked before the rules.
+    pub hashes_dir: PathBuf,
+    /// Worker threads for `scan_dir`, 0 uses one per CPU.
+    pub threads: usize,
+    pub max_file_size_mb: Option<u64>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub follow_symlinks: bool,
+    pub one_file_system: bool,
+    pub skip_pseudo_fs: bool,
+    pub timeout_secs: u64,
+    /// Remember clean files in `cache_dir` and skip them while unchanged.
+    pub scan_cache: bool,
+    /// Run the heuristic stages (ELF analysis, script patterns) and report their findings.
+    pub heuristics: bool,
+    pub archives: ArchiveConfig,
+    pub processes: ProcessConfig,
+    /// `[categories]` table: namespaces and tags whose matches are reported.
+    pub categories: RuleFilter,
+}
+
+/// `[archives]` table: unpacking of zip, tar, gzip, bzip2 and xz files.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ArchiveConfig {
+    pub enabled: bool,
+    pub max_depth: u32,
+    pub max_total_mb: u64,
+    pub max_ratio: u64,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        let limits = ArchiveLimits::default();
+        Self {
+            enabled: true,
+            max_depth: limits.max_depth,
+            max_total_mb: limits.max_total_bytes / (1024 * 1024),
+            max_ratio: limits.max_ratio,
+        }
+    }
+}
+
+/// `[processes]` table: what `scan_processes` reads besides the executables.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ProcessConfig {
+    pub memory: bool,
+    pub all_regions: bool,
+    pub max_region_mb: u64,
+}
+
+impl Default for ProcessConfig {
+    fn default() -> Self {
+        let options = ProcessScanOptions::default();
+        Self {
+            memory: options.memory,
+            all_regions: options.all_regions,
+            max_region_mb: options.max_region_size / (1024 * 1024),
+        }
+    }
+}
+
+impl Default for YaraConfig {
+    fn default() -> Self {
+        Self {
+            rules_dir: interface::data_dir().join("rules/current/rules"),
+            cache_dir: interface::data_dir().join("yara-cache"),
+            hashes_dir: interface::data_dir().join("rules/current/hashes"),
+            threads: 0,
+            max_file_size_mb: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            follow_symlinks: false,
+            one_file_system: false,
+            skip_pseudo_fs: true,
+            timeout_secs: SCAN_TIMEOUT.as_secs(),
+            scan_cache: true,
+            heuristics: true,
+            archives: ArchiveConfig::default(),
+            processes: ProcessConfig::default(),
+            categories: RuleFilter::default(),
+        }
+    }
+}
+
+impl YaraConfig {
+    /// Loads the config from `GRIFFON_YARA_CONFIG` or `./griffon_yara.toml`.
+    /// A missing file is not an error: the defaults are used instead.
+    pub fn load() -> io::Result<Self> {
+        let path = std::env::var_os("GRIFFON_YARA_CONFIG")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH));
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::from_file(&path)
+    }
+
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let raw = fs::read_to_string(path)?;
+        toml::from_str(&raw).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {e}", path.display()),
+            )
+        })
+    }
+
+    pub fn scan_options(&self) -> ScanOptions {
+        ScanOptions {
+            threads: self.threads,
+            max_file_size: self.max_file_size_mb.map(|mb| mb * 1024 * 1024),
+            include: self.include.clone(),
+            exclude: self.exclude.clone(),
+            follow_symlinks: self.follow_symlinks,
+            one_file_system: self.one_file_system,
+            skip_pseudo_fs: self.skip_pseudo_fs,
+            timeout: Duration::from_secs(self.timeout_secs),
+            archives: self.archives.enabled.then(|| ArchiveLimits {
+                max_depth: self.archives.max_depth,
+                max_total_bytes: self.archives.max_total_mb * 1024 * 1024,
+                max_ratio: self.archives.max_ratio,
+            }),
+            hashes: None,
+            filter: self.categories.clone(),
+            cache: None,
+            heuristics: self.heuristics,
+        }
+    }
+
+    /// Every process when `pids` is empty.
+    pub fn process_options(&self, pids: Vec<u32>) -> ProcessScanOptions {
+        ProcessScanOptions {
+            pids,
+            memory: self.processes.memory,
+            all_regions: self.processes.all_regions,
+            max_region_size: self.processes.max_region_mb * 1024 * 1024,
+            // The runner is started by the daemon: skip it and every other runner.
+            skip_tree: Some(std::os::unix::process::parent_id()),
+        }
+    }
+
+    pub fn scan_cache_path(&self) -> PathBuf {
+        self.cache_dir.join("scan-cache.bin")
+    }
+}
@@ -0,0 +1,330 @@
+mod config;
+
+use abi_stable::{
+    export_root_module,
+    prefix_type::PrefixTypeTrait,
+    sabi_extern_fn,
+    std_types::{RResult, RString, RVec, Tuple2},
+};
+use interface::{PluginI, PluginRoot, PluginRoot_Ref, install_host, noop_shutdown};
+use serde::Serialize;
+use static_analysis::{
+    HashDatabase, ProcessScan, RuleLoadReport, Rules, ScanCache, ScanError, ScanOptions,
+    ScanResult, cache_version, list_categories, list_rules, load_yara_rules_cached, scan_path,
+    scan_processes,
+};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use config::YaraConfig;
+
+/// Config and compiled rules, loaded on first use and swapped by `reload_rules`.
+/// Scans keep their own `Arc`, so a reload never waits for them.
+static ANALYZER: RwLock<Option<Arc<Analyzer>>> = RwLock::new(None);
+
+struct Analyzer {
+    config: YaraConfig,
+    options: ScanOptions,
+    rules: Rules,
+}
+
+#[derive(Debug, Serialize)]
+struct FileReport {
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<ScanResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct DirReport {
+    root: String,
+    files_scanned: u64,
+    /// Skipped because found clean earlier and unchanged since, included in `files_scanned`.
+    files_cached: u64,
+    /// Only files with matches, heuristic findings or errors are listed.
+    files: Vec<FileReport>,
+}
+
+#[derive(Debug, Serialize)]
+struct ProcessReport {
+    processes_scanned: u64,
+    /// Processes whose executable or memory could not be read, usually for lack of privileges.
+    processes_unreadable: u64,
+    /// Only processes with matches or heuristic findings are listed.
+    processes: Vec<ProcessScan>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReloadReport {
+    rules_dir: String,
+    rules: usize,
+    hashes: usize,
+    load: RuleLoadReport,
+}
+
+impl Analyzer {
+    fn load() -> (Self, RuleLoadReport) {
+        let config = YaraConfig::load().unwrap_or_else(|e| {
+            println!("[LIBYARA](ERROR) Bad config, using defaults: {e}");
+            YaraConfig::default()
+        });
+
+        let (rules, report) = load_yara_rules_cached(&config.rules_dir, &config.cache_dir);
+        if report.skipped_files() > 0 {
+            println!(
+                "[LIBYARA](WARN) {} rule files skipped, reload_rules lists the errors",
+                report.skipped_files()
+            );
+        }
+
+        let hashes = HashDatabase::load_dir(&config.hashes_dir).unwrap_or_else(|e| {
+            println!("[LIBYARA](ERROR) Cannot load hash feeds: {e}");
+            HashDatabase::new()
+        });
+
+        let mut options = config.scan_options();
+        options.hashes = (!hashes.is_empty()).then(|| Arc::new(hashes));
+        // Opened last: its version covers the rules, the hashes and the options.
+        if config.scan_cache {
+            let version = cache_version(&report, &options);
+            options.cache = Some(Arc::new(ScanCache::open(
+                config.scan_cache_path(),
+                &version,
+            )));
+        }
+
+        let analyzer = Self {
+            options,
+            config,
+            rules,
+        };
+        (analyzer, report)
+    }
+}
+
+fn analyzer() -> Arc<Analyzer> {
+    if let Some(analyzer) = ANALYZER.read().unwrap().as_ref() {
+        return analyzer.clone();
+    }
+    let mut slot = ANALYZER.write().unwrap();
+    slot.get_or_insert_with(|| Arc::new(Analyzer::load().0))
+        .clone()
+}
+
+fn reload_rules() -> ReloadReport {
+    let (analyzer, load) = Analyzer::load();
+    let report = ReloadReport {
+        rules_dir: analyzer.config.rules_dir.display().to_string(),
+        rules: list_rules(&analyzer.rules).len(),
+        hashes: analyzer.options.hashes.as_ref().map_or(0, |db| db.len()),
+        load,
+    };
+    *ANALYZER.write().unwrap() = Some(Arc::new(analyzer));
+    report
+}
+
+impl FileReport {
+    fn new(path: &Path, outcome: Result<ScanResult, ScanError>) -> Self {
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        Self {
+            path: path.display().to_string(),
+            result,
+            error,
+        }
+    }
+}
+
+fn scan_dir(root: &Path) -> Result<DirReport, ScanError> {
+    let analyzer = analyzer();
+    let scans = scan_path(&analyzer.rules, root, &analyzer.options)?;
+
+    Ok(DirReport {
+        root: root.display().to_string(),
+        files_scanned: scans.len() as u64,
+        files_cached: scans
+            .iter()
+            .filter(|s| s.outcome.as_ref().is_ok_and(|r| r.cached))
+            .count() as u64,
+        files: scans
+            .into_iter()
+            .filter(|s| {
+                s.outcome
+                    .as_ref()
+                    .map_or(true, |r| !r.is_clean() || !r.findings.is_empty())
+            })
+            .map(|s| FileReport::new(&s.path, s.outcome))
+            .collect(),
+    })
+}
+
+/// Goes through `scan_path` so size limits, exclusions and the timeout apply as well.
+/// Directories and special files are refused: `scan_path` would walk or skip them.
+fn scan_one(path: &Path) -> Result<FileReport, ScanError> {
+    let metadata = fs::metadata(path).map_err(|source| ScanError::Unreadable {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    if metadata.is_dir() {
+        return Err(ScanError::InvalidOptions(format!(
+            "{} is a directory, use scan_dir",
+            path.display()
+        )));
+    }
+    if !metadata.is_file() {
+        return Err(ScanError::InvalidOptions(format!(
+            "{} is not a regular file",
+            path.display()
+        )));
+    }
+    let analyzer = analyzer();
+    let scan = scan_path(&analyzer.rules, path, &analyzer.options)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| ScanError::InvalidOptions(format!("{} is excluded", path.display())))?;
+    Ok(FileReport::new(&scan.path, scan.outcome))
+}
+
+/// All processes when `pids` is empty, the plugin's own process excepted.
+fn scan_procs(pids: &str) -> Result<ProcessReport, String> {
+    let pids = pids
+        .split_whitespace()
+        .map(|pid| pid.parse().map_err(|_| format!("bad pid {pid}")))
+        .collect::<Result<Vec<u32>, String>>()?;
+    let analyzer = analyzer();
+    let options = analyzer.config.process_options(pids);
+    let scans = scan_processes(&analyzer.rules, &analyzer.options, &options)
+        .map_err(|e| format!("cannot list processes: {e}"))?;
+
+    Ok(ProcessReport {
+        processes_scanned: scans.len() as u64,
+        processes_unreadable: scans.iter().filter(|s| !s.errors.is_empty()).count() as u64,
+        processes: scans
+            .into_iter()
+            .filter(|s| !s.is_clean() || !s.findings.is_empty())
+            .collect(),
+    })
+}
+
+fn to_json<T: Serialize>(value: &T) -> RString {
+    match serde_json::to_string(value) {
+        Ok(json) => RString::from(json),
+        Err(e) => RString::from(format!("ERR json serialize: {e}")),
+    }
+}
+
+fn reply<T: Serialize>(result: Result<T, ScanError>) -> RString {
+    match result {
+        Ok(value) => to_json(&value),
+        Err(e) => RString::from(format!("ERR {e}")),
+    }
+}
+
+#[sabi_extern_fn]
+pub extern "C" fn init() -> RResult<RVec<Tuple2<RString, RString>>, RString> {
+    let mut info = RVec::new();
+
+    info.push(Tuple2(RString::from("author"), RString::from("Griffon")));
+    info.push(Tuple2(
+        RString::from("name"),
+        RString::from("static_analysis"),
+    ));
+    info.push(Tuple2(
+        RString::from("description"),
+        RString::from("YARA static analysis of files and folders"),
+    ));
+    info.push(Tuple2(
+        RString::from("function"),
+        RString::from("scan_file/scan_dir/scan_processes/list_rules/list_categories/reload_rules"),
+    ));
+
+    RResult::ROk(info)
+}
+
+#[sabi_extern_fn]
+extern "C" fn handle_message(msg: RString) -> RString {
+    println!("[LIBYARA](msg) Received message: {}", msg.as_str());
+
+    // Arguments are joined with spaces on the wire, so a path is the whole remainder.
+    let (function, arg) = match msg.as_str().split_once(' ') {
+        Some((function, arg)) => (function, arg.trim()),
+        None => (msg.as_str(), ""),
+    };
+
+    match (function, arg) {
+        ("fn:scan_file", "") | ("fn:scan_dir", "") => {
+            RString::from(format!("ERR {function} expects a path"))
+        }
+        ("fn:scan_file", path) => reply(scan_one(Path::new(path))),
+        ("fn:scan_dir", path) => reply(scan_dir(Path::new(path))),
+        ("fn:scan_processes", pids) => match scan_procs(pids) {
+            Ok(report) => to_json(&report),
+            Err(e) => RString::from(format!("ERR {e}")),
+        },
+        ("fn:list_rules", _) => to_json(&list_rules(&analyzer().rules)),
+        ("fn:list_categories", _) => to_json(&list_categories(&analyzer().rules)),
+        ("fn:reload_rules", _) => to_json(&reload_rules()),
+        _ => RString::from(format!("ERR unknown function {}", msg.as_str())),
+    }
+}
+
+#[export_root_module]
+pub fn get_library() -> PluginRoot_Ref {
+    PluginRoot {
+        plugin: PluginI {
+            init,
+            handle_message,
+            set_host: install_host,
+            shutdown: noop_shutdown,
+        }
+        .leak_into_prefix(),
+    }
+    .leak_into_prefix()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn default_config_loads_the_installed_bundle() {
+        let data = std::env::temp_dir().join(format!("griffon-bundle-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&data);
+        // The layout `rules update` leaves behind: versions unpacked side by side,
+        // `current` pointing at the active one.
+        let version = data.join("rules/versions/2026.10.19");
+        fs::create_dir_all(version.join("rules")).unwrap();
+        fs::create_dir_all(version.join("hashes")).unwrap();
+        fs::write(
+            version.join("rules/bundle.yar"),
+            "rule Bundled { strings: $a = \"griffon\" condition: $a }",
+        )
+        .unwrap();
+        fs::write(
+            version.join("hashes/feed.txt"),
+            format!("{},Bundled\n", "ab".repeat(32)),
+        )
+        .unwrap();
+        symlink("versions/2026.10.19", data.join("rules/current")).unwrap();
+
+        // SAFETY: no other test reads or writes the environment.
+        unsafe {
+            std::env::set_var(interface::DATA_DIR_ENV, &data);
+            std::env::set_var("GRIFFON_YARA_CONFIG", data.join("missing.toml"));
+        }
+        let (analyzer, report) = Analyzer::load();
+
+        assert_eq!(analyzer.config.rules_dir, data.join("rules/current/rules"));
+        assert_eq!(report.loaded_files, 1);
+        assert_eq!(analyzer.options.hashes.as_ref().map(|db| db.len()), Some(1));
+        assert!(analyzer.config.cache_dir.starts_with(&data));
+        fs::remove_dir_all(data).unwrap();
+    }
+}
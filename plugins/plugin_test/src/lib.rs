@@ -4,7 +4,7 @@ use abi_stable::{
     sabi_extern_fn,
     std_types::{RResult, RString, RVec, Tuple2},
 };
-use interface::{PluginI, PluginRoot, PluginRoot_Ref};
+use interface::{PluginI, PluginRoot, PluginRoot_Ref, install_host};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread::{JoinHandle, sleep, spawn};
 use std::time::Duration;
@@ -42,7 +42,7 @@ pub extern "C" fn init() -> RResult<RVec<Tuple2<RString, RString>>, RString> {
 extern "C" fn handle_message(msg: RString) -> RString {
     print!("[LIB1](msg) Received message: {}", msg.as_str());
 
-    let res = match msg.as_str() {
+    match msg.as_str() {
         "fn:start" => {
             start_thread();
             RString::from(format!("ACK LIB1 {}\n", msg.as_str()))
@@ -52,8 +52,12 @@ extern "C" fn handle_message(msg: RString) -> RString {
             RString::from(format!("ACK LIB1 {}\n", msg.as_str()))
         }
         _ => RString::from(format!("ACK LIB1 {}\n", msg.as_str())),
-    };
-    res
+    }
+}
+
+#[sabi_extern_fn]
+extern "C" fn shutdown() -> RResult<(), RString> {
+    stop()
 }
 
 #[export_root_module]
@@ -62,6 +66,8 @@ pub fn get_library() -> PluginRoot_Ref {
         plugin: PluginI {
             init,
             handle_message,
+            set_host: install_host,
+            shutdown,
         }
         .leak_into_prefix(),
     }
@@ -5,7 +5,7 @@ use abi_stable::{
     sabi_extern_fn,
     std_types::{RResult, RString},
 };
-use interface::{PluginI, PluginRoot, PluginRoot_Ref};
+use interface::{PluginI, PluginRoot, PluginRoot_Ref, install_host, noop_shutdown};
 
 #[sabi_extern_fn]
 pub extern "C" fn init() -> RResult<RVec<Tuple2<RString, RString>>, RString> {
@@ -28,11 +28,10 @@ pub extern "C" fn init() -> RResult<RVec<Tuple2<RString, RString>>, RString> {
 #[sabi_extern_fn]
 extern "C" fn handle_message(msg: RString) -> RString {
     print!("[LIB2](msg) Received message: {}", msg.as_str());
-    let res = match msg.as_str() {
+    match msg.as_str() {
         "fn:ping" => ping(),
         _ => RString::from(format!("ACK LIB2 {}\n", msg.as_str())),
-    };
-    res
+    }
 }
 
 #[export_root_module]
@@ -41,6 +40,8 @@ pub fn get_library() -> PluginRoot_Ref {
         plugin: PluginI {
             init,
             handle_message,
+            set_host: install_host,
+            shutdown: noop_shutdown,
         }
         .leak_into_prefix(),
     }
@@ -1,5 +1,11 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
-use static_analysis::{load_yara_rules, scan_bytes};
+use static_analysis::{
+    FileScanner, ScanOptions, TIMEOUT_SPAN, load_yara_rules, load_yara_rules_cached, scan_bytes,
+    scan_file, scan_path, scan_reader,
+};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::time::Duration;
 
 fn criterion_benchmark(c: &mut Criterion) {
     // ------------------------------------------------------------------
@@ -19,6 +25,12 @@ fn criterion_benchmark(c: &mut Criterion) {
             black_box(load_yara_rules(black_box("rules")))
         })
     });
+
+    // Startup path once the cache is warm: hash the sources and deserialize.
+    load_yara_rules_cached("rules", "rules-cache");
+    group_lifecycle.bench_function("load_rules_cached", |b| {
+        b.iter(|| black_box(load_yara_rules_cached(black_box("rules"), "rules-cache")))
+    });
     group_lifecycle.finish();
 
     // ------------------------------------------------------------------
@@ -26,7 +38,7 @@ fn criterion_benchmark(c: &mut Criterion) {
     // ------------------------------------------------------------------
     
     // 1. Setup (Run once, outside the measurement loop)
-    let rules = load_yara_rules("rules");
+    let (rules, _) = load_yara_rules("rules");
     
     // CRITICAL FIX: Don't use zeros. Use a repeating pattern or random noise.
     // Scanners often have "fast paths" for zero-buffers.
@@ -50,6 +62,87 @@ fn criterion_benchmark(c: &mut Criterion) {
     });
 
     group_scan.finish();
+
+    // ------------------------------------------------------------------
+    // GROUP 3: Directory scanning, one worker vs every CPU
+    // ------------------------------------------------------------------
+    let mut group_dir = c.benchmark_group("scan_path");
+    group_dir.sample_size(10);
+
+    for (name, threads) in [("samples_1_thread", 1), ("samples_all_cpus", 0)] {
+        let options = ScanOptions {
+            threads,
+            ..ScanOptions::default()
+        };
+        group_dir.bench_function(name, |b| {
+            b.iter(|| black_box(scan_path(&rules, "samples", &options)))
+        });
+    }
+    group_dir.finish();
+
+    // ------------------------------------------------------------------
+    // GROUP 4: Large file, whole read vs mmap vs bounded-memory streaming
+    // ------------------------------------------------------------------
+    let big_file = std::env::temp_dir().join("static_analysis_bench_64mb.bin");
+    fs::write(&big_file, vec![0xAAu8; 64 * 1024 * 1024]).expect("cannot write bench file");
+
+    let mut group_file = c.benchmark_group("file_scanning");
+    group_file.sample_size(10);
+    group_file.throughput(Throughput::Bytes(64 * 1024 * 1024));
+
+    group_file.bench_function("read_whole_file", |b| {
+        b.iter(|| {
+            let input = fs::read(black_box(&big_file)).unwrap();
+            black_box(scan_bytes(&rules, &input))
+        })
+    });
+    group_file.bench_function("mmap", |b| {
+        b.iter(|| black_box(scan_file(&rules, black_box(&big_file))))
+    });
+    group_file.bench_function("streamed", |b| {
+        b.iter(|| {
+            let file = File::open(black_box(&big_file)).unwrap();
+            black_box(scan_reader(&rules, file))
+        })
+    });
+    group_file.finish();
+
+    let _ = fs::remove_file(&big_file);
+
+    // ------------------------------------------------------------------
+    // GROUP 5: Disk-image sized input, the timeout grows with the size
+    // ------------------------------------------------------------------
+    // Sparse, so it costs no disk space. A timeout that fits one span must
+    // still let the whole image through, mapped or streamed.
+    let image = std::env::temp_dir().join("static_analysis_bench_image.bin");
+    let image_size = 4 * TIMEOUT_SPAN;
+    File::create(&image)
+        .and_then(|f| f.set_len(image_size))
+        .expect("cannot write bench image");
+    let options = ScanOptions {
+        timeout: Duration::from_secs(10),
+        ..ScanOptions::default()
+    };
+
+    let mut group_image = c.benchmark_group("large_input");
+    group_image.sample_size(10);
+    group_image.throughput(Throughput::Bytes(image_size));
+    group_image.bench_function("mmap_1gb", |b| {
+        let mut scanner = FileScanner::new(&rules, &options);
+        b.iter(|| {
+            let file = File::open(&image).unwrap();
+            black_box(scanner.scan_open_file(file, &image).expect("image timed out"))
+        })
+    });
+    group_image.bench_function("streamed_1gb", |b| {
+        b.iter(|| {
+            let stream = io::repeat(0xAA).take(image_size);
+            black_box(scan_reader(&rules, stream).expect("stream timed out"))
+        })
+    });
+    group_image.finish();
+
+    let _ = fs::remove_file(&image);
 }
 
 criterion_group!(benches, criterion_benchmark);
@@ -0,0 +1,53 @@
+//! Records the locked yara-x version so the compiled rule cache is dropped on upgrades.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+fn main() {
+    let version = lockfile_version().unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=YARA_X_VERSION={version}");
+    println!("cargo:rerun-if-changed=build.rs");
+}
+
+/// The yara-x version locked by the workspace being built. Its lockfile is
+/// looked up from `OUT_DIR` first, which sits in that workspace's target
+/// directory: when this crate is a path dependency, the lockfiles next to it
+/// belong to another workspace. Lockfiles without yara-x are skipped.
+fn lockfile_version() -> Option<String> {
+    ["OUT_DIR", "CARGO_MANIFEST_DIR"]
+        .iter()
+        .filter_map(|var| env::var_os(var).map(PathBuf::from))
+        .flat_map(|dir| {
+            dir.ancestors()
+                .map(|a| a.join("Cargo.lock"))
+                .collect::<Vec<_>>()
+        })
+        .find_map(|lock| {
+            let version = yara_x_version(&fs::read_to_string(&lock).ok()?)?;
+            println!("cargo:rerun-if-changed={}", lock.display());
+            Some(version)
+        })
+}
+
+/// `version+source` of the yara-x package, the git revision included.
+fn yara_x_version(lock: &str) -> Option<String> {
+    let package = lock
+        .split("[[package]]")
+        .find(|p| p.lines().any(|l| l.trim() == "name = \"yara-x\""))?;
+
+    let field = |name: &str| {
+        package.lines().find_map(|l| {
+            l.trim()
+                .strip_prefix(name)
+                .and_then(|v| v.trim().strip_prefix('='))
+                .map(|v| v.trim().trim_matches('"').to_string())
+        })
+    };
+    let version = field("version")?;
+    Some(match field("source") {
+        Some(source) => format!("{version}+{source}"),
+        None => version,
+    })
+}
@@ -0,0 +1,383 @@
+//! In-memory unpacking of zip, tar, gzip, bzip2 and xz containers, so that
+//! every member is scanned as if it were a file of its own.
+
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use crate::{ArchiveEntryScan, ScanError, ScanResult};
+
+/// Members smaller than this are never refused for their compression ratio:
+/// tiny, highly repetitive files legitimately compress very well.
+const RATIO_FLOOR_BYTES: u64 = 1024 * 1024;
+
+/// Bounds that keep a crafted archive (zip bomb, deeply nested archives)
+/// from exhausting memory or time.
+#[derive(Debug, Clone, Copy)]
+pub struct ArchiveLimits {
+    /// Nesting levels unpacked, 1 only looks at the members of the scanned file.
+    pub max_depth: u32,
+    /// Decompressed bytes allowed for one scanned file, all levels included.
+    pub max_total_bytes: u64,
+    /// Largest decompressed / compressed size ratio accepted for a member.
+    pub max_ratio: u64,
+}
+
+impl Default for ArchiveLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 5,
+            max_total_bytes: 512 * 1024 * 1024,
+            max_ratio: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Zip,
+    Tar,
+    Gzip,
+    Bzip2,
+    Xz,
+}
+
+fn detect(data: &[u8]) -> Option<Kind> {
+    if data.starts_with(b"PK\x03\x04") || data.starts_with(b"PK\x05\x06") {
+        Some(Kind::Zip)
+    } else if data.starts_with(&[0x1f, 0x8b]) {
+        Some(Kind::Gzip)
+    } else if data.starts_with(b"BZh") {
+        Some(Kind::Bzip2)
+    } else if data.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+        Some(Kind::Xz)
+    } else if data.get(257..262) == Some(b"ustar") {
+        Some(Kind::Tar)
+    } else {
+        None
+    }
+}
+
+/// Name of the single member of a compressed stream: `a.tar.gz` holds `a.tar`.
+fn stream_member_name(container: &str) -> String {
+    let name = container.rsplit("!/").next().unwrap_or(container);
+    let name = Path::new(name)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    for (suffix, replacement) in [
+        (".tgz", ".tar"),
+        (".tbz2", ".tar"),
+        (".txz", ".tar"),
+        (".gz", ""),
+        (".bz2", ""),
+        (".xz", ""),
+    ] {
+        if let Some(stem) = name.strip_suffix(suffix) {
+            return format!("{stem}{replacement}");
+        }
+    }
+    name
+}
+
+/// Unpacks `data` if it is a supported archive and scans every member with `scan`.
+///
+/// `name` labels the container in the nested paths (`a.zip!/inner.tar!/evil.sh`).
+/// Returns `None` when `data` is not an archive. Only members with matches or
+/// errors are returned, limits that stopped the unpacking are reported as errors.
+pub(crate) fn scan_archive(
+    data: &[u8],
+    name: &str,
+    limits: ArchiveLimits,
+    scan: &mut dyn FnMut(&[u8]) -> Result<ScanResult, ScanError>,
+) -> Option<Vec<ArchiveEntryScan>> {
+    let kind = detect(data)?;
+    let mut walk = Walk {
+        limits,
+        remaining: limits.max_total_bytes,
+        scan,
+        entries: Vec::new(),
+    };
+    walk.container(data, name, kind, 1);
+    Some(walk.entries)
+}
+
+struct Walk<'s> {
+    limits: ArchiveLimits,
+    /// Decompressed bytes still allowed by `max_total_bytes`.
+    remaining: u64,
+    scan: &'s mut dyn FnMut(&[u8]) -> Result<ScanResult, ScanError>,
+    entries: Vec<ArchiveEntryScan>,
+}
+
+impl Walk<'_> {
+    /// Scans the members of a container, which are at nesting level `depth`.
+    fn container(&mut self, data: &[u8], path: &str, kind: Kind, depth: u32) {
+        let unpacked = match kind {
+            Kind::Zip => self.zip(data, path, depth),
+            Kind::Tar => self.tar(data, path, depth),
+            Kind::Gzip => self.stream(flate2::read::MultiGzDecoder::new(data), data, path, depth),
+            Kind::Bzip2 => self.stream(bzip2::read::MultiBzDecoder::new(data), data, path, depth),
+            Kind::Xz => self.stream(
+                xz2::read::XzDecoder::new_multi_decoder(data),
+                data,
+                path,
+                depth,
+            ),
+        };
+        if let Err(e) = unpacked {
+            self.entries.push(ArchiveEntryScan::failed(path, e));
+        }
+    }
+
+    fn zip(&mut self, data: &[u8], path: &str, depth: u32) -> Result<(), String> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+
+        for index in 0..archive.len() {
+            if self.remaining == 0 {
+                return Err("total size limit reached, remaining members not scanned".into());
+            }
+            let mut file = match archive.by_index(index) {
+                Ok(file) => file,
+                Err(e) => {
+                    self.entries.push(ArchiveEntryScan::failed(
+                        &format!("{path}!/#{index}"),
+                        e.to_string(),
+                    ));
+                    continue;
+                }
+            };
+            if file.is_dir() {
+                continue;
+            }
+
+            let member_path = format!("{path}!/{}", file.name());
+            let compressed = file.compressed_size();
+            match self.read_member(&mut file, compressed) {
+                Ok(member) => self.member(&member, member_path, depth),
+                Err(e) => self.entries.push(ArchiveEntryScan::failed(&member_path, e)),
+            }
+        }
+        Ok(())
+    }
+
+    fn tar(&mut self, data: &[u8], path: &str, depth: u32) -> Result<(), String> {
+        let mut archive = tar::Archive::new(Cursor::new(data));
+
+        for entry in archive.entries().map_err(|e| e.to_string())? {
+            if self.remaining == 0 {
+                return Err("total size limit reached, remaining members not scanned".into());
+            }
+            let mut entry = entry.map_err(|e| e.to_string())?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+
+            let member_path = match entry.path() {
+                Ok(name) => format!("{path}!/{}", name.display()),
+                Err(_) => format!("{path}!/?"),
+            };
+            // Tar does not compress, the ratio limit does not apply.
+            let size = entry.size();
+            match self.read_member(&mut entry, size) {
+                Ok(member) => self.member(&member, member_path, depth),
+                Err(e) => self.entries.push(ArchiveEntryScan::failed(&member_path, e)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Compressed streams hold a single member.
+    fn stream<R: Read>(
+        &mut self,
+        mut decoder: R,
+        compressed: &[u8],
+        path: &str,
+        depth: u32,
+    ) -> Result<(), String> {
+        let member = self.read_member(&mut decoder, compressed.len() as u64)?;
+        let member_path = format!("{path}!/{}", stream_member_name(path));
+        self.member(&member, member_path, depth);
+        Ok(())
+    }
+
+    /// Reads a member while enforcing the total size and ratio limits.
+    fn read_member<R: Read>(&mut self, reader: &mut R, compressed: u64) -> Result<Vec<u8>, String> {
+        let ratio_cap = compressed
+            .saturating_mul(self.limits.max_ratio)
+            .max(RATIO_FLOOR_BYTES);
+        let cap = self.remaining.min(ratio_cap);
+
+        let mut member = Vec::new();
+        reader
+            .take(cap + 1)
+            .read_to_end(&mut member)
+            .map_err(|e| e.to_string())?;
+
+        if member.len() as u64 > cap {
+            return Err(if cap == self.remaining {
+                self.remaining = 0;
+                "total size limit reached".to_string()
+            } else {
+                format!("compression ratio over {}", self.limits.max_ratio)
+            });
+        }
+        self.remaining -= member.len() as u64;
+        Ok(member)
+    }
+
+    fn member(&mut self, data: &[u8], path: String, depth: u32) {
+        match (self.scan)(data) {
+            Ok(result) if !result.is_clean() => {
+                self.entries.push(ArchiveEntryScan {
+                    path: path.clone(),
+                    result: Some(result),
+                    error: None,
+                });
+            }
+            Ok(_) => {}
+            Err(e) => self
+                .entries
+                .push(ArchiveEntryScan::failed(&path, e.to_string())),
+        }
+
+        if let Some(kind) = detect(data) {
+            if depth >= self.limits.max_depth {
+                self.entries.push(ArchiveEntryScan::failed(
+                    &path,
+                    "nesting limit reached, not unpacked".to_string(),
+                ));
+            } else {
+                self.container(data, &path, kind, depth + 1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Finding, Severity};
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    fn tar(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, data) in members {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zip(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in members {
+            writer
+                .start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    /// Members containing `EVIL` are flagged, every scanned member is recorded.
+    fn walk(data: &[u8], name: &str, limits: ArchiveLimits) -> (Vec<ArchiveEntryScan>, usize) {
+        let mut scanned = 0;
+        let entries = scan_archive(data, name, limits, &mut |member| {
+            scanned += 1;
+            let mut result = ScanResult::default();
+            if member.windows(4).any(|w| w == b"EVIL") {
+                result
+                    .findings
+                    .push(Finding::new("test.evil", Severity::High, String::new()));
+            }
+            Ok(result)
+        })
+        .expect("not an archive");
+        (entries, scanned)
+    }
+
+    fn errors(entries: &[ArchiveEntryScan]) -> Vec<(&str, &str)> {
+        entries
+            .iter()
+            .filter_map(|e| Some((e.path.as_str(), e.error.as_deref()?)))
+            .collect()
+    }
+
+    #[test]
+    fn nested_members_are_scanned_and_named() {
+        let inner = zip(&[("readme.txt", b"hello"), ("evil.sh", b"EVIL")]);
+        let bundle = gzip(&tar(&[("inner.zip", &inner)]));
+
+        let (entries, scanned) = walk(&bundle, "a.tar.gz", ArchiveLimits::default());
+        assert_eq!(scanned, 4);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, "a.tar.gz!/a.tar!/inner.zip!/evil.sh");
+        assert!(!entries[0].result.as_ref().unwrap().is_clean());
+    }
+
+    #[test]
+    fn nesting_stops_at_the_depth_limit() {
+        let bundle = gzip(&tar(&[("evil.sh", b"EVIL")]));
+        let limits = ArchiveLimits {
+            max_depth: 1,
+            ..ArchiveLimits::default()
+        };
+
+        let (entries, scanned) = walk(&bundle, "a.tgz", limits);
+        assert_eq!(scanned, 1);
+        assert_eq!(
+            errors(&entries),
+            [("a.tgz!/a.tar", "nesting limit reached, not unpacked")]
+        );
+    }
+
+    #[test]
+    fn highly_compressed_members_are_refused() {
+        let bomb = gzip(&vec![0u8; 4 * RATIO_FLOOR_BYTES as usize]);
+
+        let (entries, scanned) = walk(&bomb, "bomb.gz", ArchiveLimits::default());
+        assert_eq!(scanned, 0);
+        assert_eq!(
+            errors(&entries),
+            [("bomb.gz", "compression ratio over 100")]
+        );
+
+        // Below the floor, a high ratio is accepted.
+        let small = gzip(&[0u8; 4096]);
+        let (entries, scanned) = walk(&small, "small.gz", ArchiveLimits::default());
+        assert_eq!((entries.len(), scanned), (0, 1));
+    }
+
+    #[test]
+    fn unpacking_stops_at_the_total_size() {
+        let bundle = tar(&[("a", &[1; 60]), ("b", &[2; 60]), ("c", &[3; 10])]);
+        let limits = ArchiveLimits {
+            max_total_bytes: 100,
+            ..ArchiveLimits::default()
+        };
+
+        let (entries, scanned) = walk(&bundle, "a.tar", limits);
+        assert_eq!(scanned, 1);
+        assert_eq!(
+            errors(&entries),
+            [
+                ("a.tar!/b", "total size limit reached"),
+                (
+                    "a.tar",
+                    "total size limit reached, remaining members not scanned"
+                ),
+            ]
+        );
+    }
+}
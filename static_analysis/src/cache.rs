@@ -0,0 +1,314 @@
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File, Metadata};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{RuleLoadReport, ScanOptions};
+
+/// First bytes of a cache file, bumped whenever the record layout changes.
+static MAGIC: &[u8; 8] = b"GRFSC002";
+/// dev, ino, size, mtime, mtime_nsec, ctime, ctime_nsec, seen.
+const RECORD_FIELDS: usize = 8;
+/// Entries no scan has come across for this many days are dropped on save:
+/// the file was most likely deleted or replaced.
+const STALE_DAYS: u64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct FileId {
+    dev: u64,
+    ino: u64,
+}
+
+/// Anything that changes when the contents do. `ctime` catches writes that restore `mtime`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    size: u64,
+    mtime: i64,
+    mtime_nsec: i64,
+    ctime: i64,
+    ctime_nsec: i64,
+}
+
+fn key(metadata: &Metadata) -> (FileId, Stamp) {
+    let id = FileId {
+        dev: metadata.dev(),
+        ino: metadata.ino(),
+    };
+    let stamp = Stamp {
+        size: metadata.size(),
+        mtime: metadata.mtime(),
+        mtime_nsec: metadata.mtime_nsec(),
+        ctime: metadata.ctime(),
+        ctime_nsec: metadata.ctime_nsec(),
+    };
+    (id, stamp)
+}
+
+/// Days since the Unix epoch, the resolution at which entries are aged.
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() / 86_400)
+}
+
+/// Identifies everything a clean verdict depends on: the rule set, the hash
+/// feeds, the category filter, the archive limits and the heuristics. Pass it to `ScanCache::open`.
+pub fn cache_version(report: &RuleLoadReport, options: &ScanOptions) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(&report.rules_version);
+    hasher.update([0]);
+    if let Some(hashes) = &options.hashes {
+        hasher.update(hashes.fingerprint());
+    }
+    hasher.update([0]);
+    hasher.update(format!("{:?}", options.filter));
+    hasher.update([0]);
+    hasher.update(format!("{:?}", options.archives));
+    hasher.update([options.heuristics as u8]);
+    hex::encode(hasher.finalize())
+}
+
+/// Files found clean by earlier scans, so `scan_path` can skip them while
+/// they stay unchanged. Only valid for the `version` it was opened with: a
+/// file written for another version is discarded.
+///
+/// Each entry remembers the day a scan last came across its file. Entries
+/// not seen for `STALE_DAYS` are dropped on save, so the cache of a recurring
+/// scan follows the files that still exist.
+pub struct ScanCache {
+    path: PathBuf,
+    version: [u8; 32],
+    /// Stamp of the clean file and the day it was last seen.
+    clean: Mutex<HashMap<FileId, (Stamp, u64)>>,
+}
+
+impl fmt::Debug for ScanCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScanCache")
+            .field("path", &self.path)
+            .field("entries", &self.len())
+            .finish()
+    }
+}
+
+impl ScanCache {
+    /// Loads the cache stored at `path`. A missing, unreadable or outdated file gives an empty cache.
+    pub fn open<P: AsRef<Path>>(path: P, version: &str) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let version: [u8; 32] = Sha256::digest(version).into();
+
+        let clean = match Self::read(&path, &version) {
+            Ok(Some(clean)) => {
+                log::info!("Scan cache {}: {} clean files", path.display(), clean.len());
+                clean
+            }
+            Ok(None) => {
+                log::info!("Scan cache {} reset for new rules", path.display());
+                HashMap::new()
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                log::warn!("Ignoring unusable scan cache {}: {e}", path.display());
+                HashMap::new()
+            }
+        };
+
+        Self {
+            path,
+            version,
+            clean: Mutex::new(clean),
+        }
+    }
+
+    /// `Ok(None)` when the file belongs to another version.
+    fn read(path: &Path, version: &[u8; 32]) -> io::Result<Option<HashMap<FileId, (Stamp, u64)>>> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut header = [0u8; 8 + 32];
+        reader.read_exact(&mut header)?;
+        if &header[..8] != MAGIC || &header[8..] != version {
+            return Ok(None);
+        }
+
+        let mut clean = HashMap::new();
+        let mut record = [0u8; RECORD_FIELDS * 8];
+        loop {
+            match reader.read_exact(&mut record) {
+                Ok(()) => {}
+                // A torn last record is dropped, the rest is still good.
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+            let field =
+                |i: usize| u64::from_le_bytes(record[i * 8..(i + 1) * 8].try_into().unwrap());
+            clean.insert(
+                FileId {
+                    dev: field(0),
+                    ino: field(1),
+                },
+                (
+                    Stamp {
+                        size: field(2),
+                        mtime: field(3) as i64,
+                        mtime_nsec: field(4) as i64,
+                        ctime: field(5) as i64,
+                        ctime_nsec: field(6) as i64,
+                    },
+                    field(7),
+                ),
+            );
+        }
+        Ok(Some(clean))
+    }
+
+    /// Drops stale entries, then writes the cache through a temporary file next to it.
+    pub fn save(&self) -> io::Result<()> {
+        let mut clean = self.clean.lock().unwrap();
+        let oldest = today().saturating_sub(STALE_DAYS);
+        clean.retain(|_, (_, seen)| *seen >= oldest);
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = self.path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&self.version)?;
+
+        for (id, (stamp, seen)) in clean.iter() {
+            let fields: [u64; RECORD_FIELDS] = [
+                id.dev,
+                id.ino,
+                stamp.size,
+                stamp.mtime as u64,
+                stamp.mtime_nsec as u64,
+                stamp.ctime as u64,
+                stamp.ctime_nsec as u64,
+                *seen,
+            ];
+            for field in fields {
+                writer.write_all(&field.to_le_bytes())?;
+            }
+        }
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        fs::rename(tmp, &self.path)
+    }
+
+    /// True when the file was found clean and has not changed since.
+    /// The entry then counts as seen today.
+    pub fn is_clean(&self, metadata: &Metadata) -> bool {
+        let (id, stamp) = key(metadata);
+        match self.clean.lock().unwrap().get_mut(&id) {
+            Some((cached, seen)) if *cached == stamp => {
+                *seen = today();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// `metadata` must have been taken before the scan, so a file modified
+    /// meanwhile no longer matches its entry.
+    pub fn mark_clean(&self, metadata: &Metadata) {
+        let (id, stamp) = key(metadata);
+        self.clean.lock().unwrap().insert(id, (stamp, today()));
+    }
+
+    /// Drops the entry of a file found infected, unreadable or with findings.
+    pub fn forget(&self, metadata: &Metadata) {
+        self.clean.lock().unwrap().remove(&key(metadata).0);
+    }
+
+    pub fn len(&self) -> usize {
+        self.clean.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("griffon-{name}-{}", std::process::id()))
+    }
+
+    #[test]
+    fn changed_files_are_no_longer_clean() {
+        let file = temp_path("cache-file");
+        fs::write(&file, "a").unwrap();
+        let cache = ScanCache::open(temp_path("cache-unsaved"), "v1");
+
+        cache.mark_clean(&fs::metadata(&file).unwrap());
+        assert!(cache.is_clean(&fs::metadata(&file).unwrap()));
+
+        fs::write(&file, "ab").unwrap();
+        assert!(!cache.is_clean(&fs::metadata(&file).unwrap()));
+        cache.forget(&fs::metadata(&file).unwrap());
+        assert!(cache.is_empty());
+        fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn new_rules_start_a_new_cache() {
+        let file = temp_path("cache-rules-file");
+        let path = temp_path("cache-rules");
+        fs::write(&file, "a").unwrap();
+        let metadata = fs::metadata(&file).unwrap();
+
+        let options = ScanOptions::default();
+        let report = |rules_version: &str| RuleLoadReport {
+            rules_version: rules_version.to_string(),
+            ..RuleLoadReport::default()
+        };
+        let old = cache_version(&report("old"), &options);
+        let new = cache_version(&report("new"), &options);
+        assert_ne!(old, new);
+
+        let cache = ScanCache::open(&path, &old);
+        cache.mark_clean(&metadata);
+        cache.save().unwrap();
+        assert!(ScanCache::open(&path, &old).is_clean(&metadata));
+        assert!(ScanCache::open(&path, &new).is_empty());
+
+        fs::remove_file(file).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn entries_not_seen_for_long_are_dropped_on_save() {
+        let path = temp_path("cache-stale");
+        let cache = ScanCache::open(&path, "v1");
+        let stamp = Stamp {
+            size: 1,
+            mtime: 0,
+            mtime_nsec: 0,
+            ctime: 0,
+            ctime_nsec: 0,
+        };
+        {
+            let mut clean = cache.clean.lock().unwrap();
+            clean.insert(FileId { dev: 1, ino: 1 }, (stamp, today()));
+            clean.insert(FileId { dev: 1, ino: 2 }, (stamp, today() - STALE_DAYS - 1));
+        }
+        cache.save().unwrap();
+        assert_eq!(cache.len(), 1);
+
+        let reopened = ScanCache::open(&path, "v1");
+        let clean = reopened.clean.lock().unwrap();
+        assert!(clean.contains_key(&FileId { dev: 1, ino: 1 }));
+        assert!(!clean.contains_key(&FileId { dev: 1, ino: 2 }));
+        drop(clean);
+        fs::remove_file(path).unwrap();
+    }
+}
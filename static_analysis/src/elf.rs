@@ -0,0 +1,396 @@
+use goblin::elf::Elf;
+use goblin::elf::header::{ET_DYN, ET_EXEC, et_to_str, machine_to_str};
+use goblin::elf::program_header::{PT_DYNAMIC, PT_GNU_STACK, PT_INTERP, PT_LOAD};
+use goblin::elf::section_header::SHT_NOBITS;
+use serde::Serialize;
+use std::path::Path;
+
+use crate::{Finding, Severity};
+
+/// Imported and exported symbols kept per binary, the rest is only counted.
+const MAX_SYMBOLS: usize = 256;
+/// Bits per byte above which code is taken to be compressed or encrypted.
+const PACKED_ENTROPY: f64 = 7.2;
+/// UPX writes its `UPX!` marker within the first page.
+const UPX_MARKER_WINDOW: usize = 4096;
+
+/// Directories where users drop their own binaries, checked for stripped static executables.
+static HOME_DIRS: [&str; 2] = ["/home/", "/root/"];
+
+/// What the ELF stage learnt about a binary. The same facts are available to
+/// rules through yara-x's own module, with `import "elf"`, as the builtin
+/// `heuristics` rules do.
+#[derive(Debug, Clone, Serialize)]
+pub struct ElfInfo {
+    /// `ELF32` or `ELF64`.
+    pub class: &'static str,
+    pub machine: &'static str,
+    /// `EXEC`, `DYN`, `REL`, `CORE`...
+    pub kind: &'static str,
+    pub entry: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interpreter: Option<String>,
+    pub libraries: Vec<String>,
+    pub static_linked: bool,
+    /// No `.symtab`.
+    pub stripped: bool,
+    pub sections: Vec<ElfSection>,
+    pub imports: Vec<String>,
+    pub exports: Vec<String>,
+    /// Symbols left out of `imports` and `exports` past `MAX_SYMBOLS`.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub symbols_truncated: usize,
+    /// `UPX`, or `unknown` when the code looks packed without a known signature.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub packer: Option<String>,
+    /// Loadable segments both writable and executable.
+    pub rwx_segments: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ElfSection {
+    pub name: String,
+    pub size: u64,
+    /// Shannon entropy of the contents in bits per byte, 0 for sections without any (`.bss`).
+    pub entropy: f64,
+    /// `r`, `w` and `x` as in `readelf`, `-` when unset.
+    pub flags: String,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+/// True when `data` starts with the ELF magic.
+pub(crate) fn is_elf(data: &[u8]) -> bool {
+    data.starts_with(b"\x7fELF")
+}
+
+fn entropy(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0u64; 256];
+    for &byte in data {
+        counts[byte as usize] += 1;
+    }
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Parses `data` as an ELF binary and reports its suspicious traits.
+/// `None` when it is not one, or too malformed for goblin to read.
+///
+/// `path` is where the binary was found, used by the location-based checks.
+pub fn analyze_elf(data: &[u8], path: Option<&Path>) -> Option<(ElfInfo, Vec<Finding>)> {
+    if !is_elf(data) {
+        return None;
+    }
+    let elf = match Elf::parse(data) {
+        Ok(elf) => elf,
+        Err(e) => {
+            log::debug!("Not analyzing malformed ELF: {e}");
+            return None;
+        }
+    };
+    let mut findings = Vec::new();
+
+    let sections: Vec<ElfSection> = elf
+        .section_headers
+        .iter()
+        .map(|sh| {
+            let contents = (sh.sh_type != SHT_NOBITS)
+                .then(|| {
+                    let start = usize::try_from(sh.sh_offset).ok()?;
+                    let end = start.checked_add(usize::try_from(sh.sh_size).ok()?)?;
+                    data.get(start..end)
+                })
+                .flatten()
+                .unwrap_or_default();
+            let flag = |set: bool, c: char| if set { c } else { '-' };
+            ElfSection {
+                name: elf
+                    .shdr_strtab
+                    .get_at(sh.sh_name)
+                    .unwrap_or_default()
+                    .to_string(),
+                size: sh.sh_size,
+                entropy: entropy(contents),
+                flags: [
+                    flag(sh.is_alloc(), 'r'),
+                    flag(sh.is_writable(), 'w'),
+                    flag(sh.is_executable(), 'x'),
+                ]
+                .iter()
+                .collect(),
+            }
+        })
+        .collect();
+
+    let mut imports = Vec::new();
+    let mut exports = Vec::new();
+    let mut symbols_truncated = 0;
+    for sym in elf.dynsyms.iter() {
+        let Some(name) = elf.dynstrtab.get_at(sym.st_name).filter(|n| !n.is_empty()) else {
+            continue;
+        };
+        let list = if sym.is_import() {
+            &mut imports
+        } else if sym.st_bind() != 0 && sym.st_shndx != 0 {
+            // Global or weak, and defined here.
+            &mut exports
+        } else {
+            continue;
+        };
+        if list.len() < MAX_SYMBOLS {
+            list.push(name.to_string());
+        } else {
+            symbols_truncated += 1;
+        }
+    }
+
+    let has_segment = |kind| elf.program_headers.iter().any(|ph| ph.p_type == kind);
+    let executable = matches!(elf.header.e_type, ET_EXEC | ET_DYN);
+    let static_linked = executable && !has_segment(PT_INTERP) && !has_segment(PT_DYNAMIC);
+    let stripped = elf.syms.is_empty();
+
+    let rwx_segments = elf
+        .program_headers
+        .iter()
+        .filter(|ph| ph.p_type == PT_LOAD && ph.is_write() && ph.is_executable())
+        .count();
+    if elf
+        .program_headers
+        .iter()
+        .any(|ph| ph.p_type == PT_GNU_STACK && ph.is_executable())
+    {
+        findings.push(Finding::new(
+            "elf.executable_stack",
+            Severity::Medium,
+            "the stack is mapped executable, which eases shellcode execution".to_string(),
+        ));
+    }
+
+    let upx_marker = data[..data.len().min(UPX_MARKER_WINDOW)]
+        .windows(4)
+        .any(|w| w == b"UPX!");
+    let upx_sections = sections
+        .iter()
+        .any(|s| s.name.starts_with("UPX") || s.name == ".upx");
+    // Packed binaries often drop their section headers, the whole file is then measured.
+    let packed_code = if sections.is_empty() {
+        entropy(data) > PACKED_ENTROPY
+    } else {
+        sections
+            .iter()
+            .any(|s| s.flags.ends_with('x') && s.entropy > PACKED_ENTROPY)
+    };
+    let packer = if upx_marker || upx_sections {
+        Some("UPX")
+    } else if packed_code {
+        Some("unknown")
+    } else {
+        None
+    };
+    if let Some(packer) = packer {
+        findings.push(Finding::new(
+            "elf.packed",
+            Severity::Medium,
+            match packer {
+                "UPX" => "packed with UPX, the real code is only visible once unpacked".to_string(),
+                _ => format!(
+                    "code entropy above {PACKED_ENTROPY} bits per byte, likely packed or encrypted"
+                ),
+            },
+        ));
+    }
+
+    let in_home = path.is_some_and(|p| HOME_DIRS.iter().any(|dir| p.starts_with(dir)));
+    let dropped_shape = static_linked && stripped && in_home;
+    if dropped_shape {
+        findings.push(Finding::new(
+            "elf.stripped_static_in_home",
+            Severity::Medium,
+            "stripped statically linked executable in a home directory, \
+             a common shape for dropped implants and miners"
+                .to_string(),
+        ));
+    }
+
+    // Old toolchains and some JIT runtimes emit RWX segments too: on its own the
+    // trait is informative, it only counts as a detection next to another one.
+    if rwx_segments > 0 {
+        let corroborated = packer.is_some() || dropped_shape;
+        findings.push(Finding::new(
+            "elf.rwx_segment",
+            if corroborated {
+                Severity::High
+            } else {
+                Severity::Medium
+            },
+            format!(
+                "{rwx_segments} loadable segment(s) both writable and executable, \
+                 typical of self-modifying or injected code{}",
+                if corroborated {
+                    ", in a packed or dropped-looking binary"
+                } else {
+                    ""
+                }
+            ),
+        ));
+    }
+
+    let info = ElfInfo {
+        class: if elf.is_64 { "ELF64" } else { "ELF32" },
+        machine: machine_to_str(elf.header.e_machine),
+        kind: et_to_str(elf.header.e_type),
+        entry: elf.entry,
+        interpreter: elf.interpreter.map(str::to_string),
+        libraries: elf.libraries.iter().map(|l| l.to_string()).collect(),
+        static_linked,
+        stripped,
+        sections,
+        imports,
+        exports,
+        symbols_truncated,
+        packer: packer.map(str::to_string),
+        rwx_segments,
+    };
+    Some((info, findings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ScanResult, load_yara_rules, scan_bytes};
+    use goblin::elf::program_header::{PF_R, PF_W, PF_X};
+
+    const BASE: u64 = 0x40_0000;
+
+    /// A section-less ELF64 x86-64 executable: one loadable segment with
+    /// `load_flags` mapping the whole file, then the other `segments`, then `code`.
+    fn elf(load_flags: u32, segments: &[(u32, u32)], code: &[u8]) -> Vec<u8> {
+        let phnum = 1 + segments.len();
+        let code_offset = (64 + 56 * phnum) as u64;
+        let size = code_offset + code.len() as u64;
+
+        let mut data = Vec::new();
+        data.extend_from_slice(b"\x7fELF\x02\x01\x01");
+        data.resize(16, 0);
+        data.extend_from_slice(&2u16.to_le_bytes()); // ET_EXEC
+        data.extend_from_slice(&62u16.to_le_bytes()); // EM_X86_64
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&(BASE + code_offset).to_le_bytes());
+        data.extend_from_slice(&64u64.to_le_bytes()); // e_phoff
+        data.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+        data.extend_from_slice(&0u32.to_le_bytes());
+        for half in [64u16, 56, phnum as u16, 64, 0, 0] {
+            data.extend_from_slice(&half.to_le_bytes());
+        }
+
+        let load = (PT_LOAD, load_flags, 0, BASE, size);
+        let others = segments.iter().map(|&(kind, flags)| (kind, flags, 0, 0, 0));
+        for (kind, flags, offset, vaddr, len) in std::iter::once(load).chain(others) {
+            data.extend_from_slice(&kind.to_le_bytes());
+            data.extend_from_slice(&flags.to_le_bytes());
+            for field in [offset, vaddr, vaddr, len, len, 0x1000] {
+                data.extend_from_slice(&field.to_le_bytes());
+            }
+        }
+        data.extend_from_slice(code);
+        data
+    }
+
+    fn finding(findings: &[Finding], id: &str) -> Option<Severity> {
+        findings.iter().find(|f| f.id == id).map(|f| f.severity)
+    }
+
+    fn is_clean(findings: Vec<Finding>) -> bool {
+        ScanResult {
+            findings,
+            ..ScanResult::default()
+        }
+        .is_clean()
+    }
+
+    #[test]
+    fn headers_are_parsed() {
+        let data = elf(PF_R | PF_X, &[], &[0xc3]);
+        let (info, findings) = analyze_elf(&data, None).unwrap();
+        assert_eq!(info.class, "ELF64");
+        assert_eq!(info.machine, "X86_64");
+        assert_eq!(info.kind, "EXEC");
+        assert_eq!(info.entry, BASE + 64 + 56);
+        assert!(info.static_linked && info.stripped);
+        assert_eq!(info.rwx_segments, 0);
+        assert!(findings.is_empty());
+
+        assert!(analyze_elf(b"#!/bin/sh\n", None).is_none());
+        assert!(analyze_elf(b"\x7fELF\x02", None).is_none());
+    }
+
+    #[test]
+    fn rwx_alone_is_not_a_detection() {
+        let data = elf(PF_R | PF_W | PF_X, &[], &[0xc3]);
+        let (info, findings) = analyze_elf(&data, Some(Path::new("/usr/bin/legacy"))).unwrap();
+        assert_eq!(info.rwx_segments, 1);
+        assert_eq!(
+            finding(&findings, "elf.rwx_segment"),
+            Some(Severity::Medium)
+        );
+        assert!(is_clean(findings));
+    }
+
+    #[test]
+    fn rwx_counts_next_to_packing_or_a_dropped_shape() {
+        let packed = elf(PF_R | PF_W | PF_X, &[], b"UPX!");
+        let (info, findings) = analyze_elf(&packed, None).unwrap();
+        assert_eq!(info.packer.as_deref(), Some("UPX"));
+        assert_eq!(finding(&findings, "elf.packed"), Some(Severity::Medium));
+        assert_eq!(finding(&findings, "elf.rwx_segment"), Some(Severity::High));
+        assert!(!is_clean(findings));
+
+        let dropped = elf(PF_R | PF_W | PF_X, &[], &[0xc3]);
+        let (_, findings) =
+            analyze_elf(&dropped, Some(Path::new("/home/user/.x/kworker"))).unwrap();
+        assert_eq!(
+            finding(&findings, "elf.stripped_static_in_home"),
+            Some(Severity::Medium)
+        );
+        assert_eq!(finding(&findings, "elf.rwx_segment"), Some(Severity::High));
+    }
+
+    #[test]
+    fn executable_stack_is_reported() {
+        let data = elf(PF_R | PF_X, &[(PT_GNU_STACK, PF_R | PF_W | PF_X)], &[0xc3]);
+        let (_, findings) = analyze_elf(&data, None).unwrap();
+        assert_eq!(
+            finding(&findings, "elf.executable_stack"),
+            Some(Severity::Medium)
+        );
+    }
+
+    #[test]
+    fn builtin_rules_use_the_elf_module() {
+        let dir = std::env::temp_dir().join(format!("griffon-no-rules-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (rules, report) = load_yara_rules(&dir);
+        assert!(report.failures.is_empty());
+
+        let rule = |data: &[u8]| {
+            scan_bytes(&rules, data).unwrap().matches.iter().any(|m| {
+                m.namespace == "heuristics" && m.identifier == "ELF_RWX_Entry_Without_Sections"
+            })
+        };
+        assert!(rule(&elf(PF_R | PF_W | PF_X, &[], &[0xc3])));
+        assert!(!rule(&elf(PF_R | PF_X, &[], &[0xc3])));
+        std::fs::remove_dir(dir).unwrap();
+    }
+}
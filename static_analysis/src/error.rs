@@ -0,0 +1,42 @@
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Why a scan could not produce a result.
+#[derive(Debug, thiserror::Error)]
+pub enum ScanError {
+    #[error("cannot read {path}: {source}")]
+    Unreadable {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("{path} is {size} bytes, over the {limit} bytes limit")]
+    TooLarge {
+        path: PathBuf,
+        size: u64,
+        limit: u64,
+    },
+
+    #[error("scan timed out after {0:?}")]
+    Timeout(Duration),
+
+    #[error("yara-x error: {0}")]
+    Engine(String),
+
+    #[error("invalid scan options: {0}")]
+    InvalidOptions(String),
+}
+
+impl ScanError {
+    pub(crate) fn from_yara(err: yara_x::ScanError, timeout: Duration) -> Self {
+        match err {
+            yara_x::ScanError::Timeout => ScanError::Timeout(timeout),
+            yara_x::ScanError::OpenError { path, err } => {
+                ScanError::Unreadable { path, source: err }
+            }
+            other => ScanError::Engine(other.to_string()),
+        }
+    }
+}
@@ -0,0 +1,384 @@
+use md5::Md5;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use walkdir::WalkDir;
+
+/// Extensions of the feed files picked up by `HashDatabase::load_dir`.
+static FEED_EXTENSIONS: [&str; 3] = ["txt", "csv", "hashes"];
+/// CSV header names of the hash column, preferred in this order.
+static HASH_COLUMNS: [&str; 5] = ["sha256_hash", "sha256", "md5_hash", "md5", "hash"];
+/// CSV header names of the column holding the threat label.
+static THREAT_COLUMNS: [&str; 5] = ["signature", "threat", "label", "malware", "family"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    Sha256,
+    Md5,
+}
+
+/// A scanned input whose hash is listed in a feed.
+#[derive(Debug, Clone, Serialize)]
+pub struct HashMatch {
+    pub algorithm: HashAlgorithm,
+    pub hash: String,
+    /// Name of the feed file the hash comes from, without extension.
+    pub feed: String,
+    pub threat: String,
+}
+
+#[derive(Debug, Clone)]
+struct Signature {
+    feed: Arc<str>,
+    threat: Arc<str>,
+}
+
+/// Known-bad SHA-256 and MD5 hashes, loaded from threat feed files.
+///
+/// Each line of a feed holds a hex hash, optionally followed by a threat label,
+/// separated by a comma, semicolon, tab or spaces. The label runs to the end
+/// of the line or to the next comma, semicolon or tab. `#` starts a comment.
+///
+/// CSV feeds with a header, commented out or not, are read by column: the hash
+/// from `sha256_hash`, `sha256`, `md5_hash`, `md5` or `hash`, the label from
+/// `signature`, `threat`, `label`, `malware` or `family`. Without a header the
+/// first 32 or 64 digit hex field of a line is taken, labelled with the feed name.
+#[derive(Default)]
+pub struct HashDatabase {
+    sha256: HashMap<[u8; 32], Signature>,
+    md5: HashMap<[u8; 16], Signature>,
+}
+
+impl fmt::Debug for HashDatabase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HashDatabase")
+            .field("sha256", &self.sha256.len())
+            .field("md5", &self.md5.len())
+            .finish()
+    }
+}
+
+impl HashDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads every feed file under `dir`. A missing directory gives an empty database.
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let mut db = Self::new();
+        let dir = dir.as_ref();
+        if !dir.exists() {
+            return Ok(db);
+        }
+
+        for entry in WalkDir::new(dir).sort_by_file_name() {
+            let entry = entry.map_err(io::Error::from)?;
+            let is_feed = entry.file_type().is_file()
+                && entry
+                    .path()
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .is_some_and(|e| FEED_EXTENSIONS.contains(&e));
+            if is_feed {
+                db.load_file(entry.path())?;
+            }
+        }
+        Ok(db)
+    }
+
+    /// Adds the hashes of one feed file, named after the file stem. Returns how many were added.
+    pub fn load_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<usize> {
+        let path = path.as_ref();
+        let feed: Arc<str> = path
+            .file_stem()
+            .map(|s| s.to_string_lossy())
+            .unwrap_or_default()
+            .into();
+        let contents = fs::read_to_string(path)?;
+
+        let mut added = 0;
+        let mut skipped = 0;
+        let mut columns = None;
+        for line in contents.lines() {
+            let (line, comment) = split_comment(line);
+            let line = line.trim();
+            if columns.is_none() {
+                // Some exports, MalwareBazaar's among them, comment their header out.
+                let header = if line.is_empty() { comment } else { line };
+                if let Some(header) = Columns::from_header(header) {
+                    columns = Some(header);
+                    continue;
+                }
+            }
+            if line.is_empty() {
+                continue;
+            }
+            if self.add_line(line, &feed, columns.as_ref()) {
+                added += 1;
+            } else {
+                skipped += 1;
+            }
+        }
+
+        log::info!("Hash feed {feed}: {added} hashes, {skipped} lines skipped");
+        Ok(added)
+    }
+
+    fn add_line(&mut self, line: &str, feed: &Arc<str>, columns: Option<&Columns>) -> bool {
+        let (hash, label) = match columns {
+            Some(columns) => {
+                let fields: Vec<&str> = csv_fields(line).collect();
+                let Some(hash) = fields.get(columns.hash) else {
+                    return false;
+                };
+                let label = columns.threat.and_then(|i| fields.get(i).copied());
+                (*hash, label)
+            }
+            None => {
+                let (first, rest) = line
+                    .split_once(|c: char| c == ',' || c == ';' || c.is_whitespace())
+                    .unwrap_or((line, ""));
+                let first = unquote(first);
+                if is_hash(first) {
+                    (first, csv_fields(rest).next())
+                } else {
+                    match csv_fields(line).find(|f| is_hash(f)) {
+                        Some(hash) => (hash, None),
+                        None => return false,
+                    }
+                }
+            }
+        };
+        let threat: Arc<str> = match label.filter(|l| !l.is_empty()) {
+            Some(label) => label.into(),
+            None => feed.clone(),
+        };
+        let signature = Signature {
+            feed: feed.clone(),
+            threat,
+        };
+
+        match hash.len() {
+            64 => parse_hex::<32>(hash).map(|h| self.sha256.insert(h, signature)),
+            32 => parse_hex::<16>(hash).map(|h| self.md5.insert(h, signature)),
+            _ => None,
+        }
+        .is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.sha256.len() + self.md5.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Digest of every listed hash, to tell whether two databases flag the same inputs.
+    pub fn fingerprint(&self) -> String {
+        let mut sha256: Vec<&[u8; 32]> = self.sha256.keys().collect();
+        let mut md5: Vec<&[u8; 16]> = self.md5.keys().collect();
+        sha256.sort_unstable();
+        md5.sort_unstable();
+
+        let mut hasher = Sha256::new();
+        sha256.iter().for_each(|h| hasher.update(h));
+        md5.iter().for_each(|h| hasher.update(h));
+        hex::encode(hasher.finalize())
+    }
+
+    /// Looks `data` up, hashing it only with the algorithms some feed uses.
+    pub fn lookup(&self, data: &[u8]) -> Option<HashMatch> {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    pub(crate) fn hasher(&self) -> FeedHasher<'_> {
+        FeedHasher {
+            db: self,
+            sha256: (!self.sha256.is_empty()).then(Sha256::new),
+            md5: (!self.md5.is_empty()).then(Md5::new),
+        }
+    }
+}
+
+/// Incremental hashing for inputs that are scanned in several windows.
+pub(crate) struct FeedHasher<'a> {
+    db: &'a HashDatabase,
+    sha256: Option<Sha256>,
+    md5: Option<Md5>,
+}
+
+impl FeedHasher<'_> {
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        if let Some(h) = &mut self.sha256 {
+            h.update(data);
+        }
+        if let Some(h) = &mut self.md5 {
+            h.update(data);
+        }
+    }
+
+    pub(crate) fn finish(self) -> Option<HashMatch> {
+        let found = |algorithm, hash: &[u8], signature: &Signature| HashMatch {
+            algorithm,
+            hash: hex::encode(hash),
+            feed: signature.feed.to_string(),
+            threat: signature.threat.to_string(),
+        };
+
+        if let Some(h) = self.sha256 {
+            let hash: [u8; 32] = h.finalize().into();
+            if let Some(signature) = self.db.sha256.get(&hash) {
+                return Some(found(HashAlgorithm::Sha256, &hash, signature));
+            }
+        }
+        if let Some(h) = self.md5 {
+            let hash: [u8; 16] = h.finalize().into();
+            if let Some(signature) = self.db.md5.get(&hash) {
+                return Some(found(HashAlgorithm::Md5, &hash, signature));
+            }
+        }
+        None
+    }
+}
+
+/// Where the hash and the label are in the rows of a CSV feed.
+#[derive(Debug, PartialEq)]
+struct Columns {
+    hash: usize,
+    threat: Option<usize>,
+}
+
+impl Columns {
+    /// `None` unless `line` names a hash column and holds no hash itself.
+    fn from_header(line: &str) -> Option<Self> {
+        let names: Vec<String> = csv_fields(line).map(str::to_ascii_lowercase).collect();
+        if names.iter().any(|n| is_hash(n)) {
+            return None;
+        }
+        let position = |candidates: &[&str]| {
+            candidates
+                .iter()
+                .find_map(|c| names.iter().position(|n| n == c))
+        };
+        Some(Self {
+            hash: position(&HASH_COLUMNS)?,
+            threat: position(&THREAT_COLUMNS),
+        })
+    }
+}
+
+/// The comma, semicolon or tab separated fields of a line, unquoted.
+fn csv_fields(line: &str) -> impl Iterator<Item = &str> {
+    line.split([',', ';', '\t']).map(unquote)
+}
+
+fn unquote(field: &str) -> &str {
+    field.trim().trim_matches('"').trim()
+}
+
+fn is_hash(field: &str) -> bool {
+    matches!(field.len(), 32 | 64) && field.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn parse_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    let mut out = [0u8; N];
+    hex::decode_to_slice(hex, &mut out).ok()?;
+    Some(out)
+}
+
+/// Splits off a `#` comment. Only a `#` at line start or after whitespace opens
+/// one, so labels such as `Win.Trojan#2` stay whole.
+fn split_comment(line: &str) -> (&str, &str) {
+    let start = line
+        .match_indices('#')
+        .find(|&(i, _)| i == 0 || line[..i].ends_with(char::is_whitespace));
+    match start {
+        Some((i, _)) => (&line[..i], &line[i + 1..]),
+        None => (line, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static SHA256: &str = "275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f";
+    static MD5: &str = "44d88612fea8a8f36de82e1278abb02f";
+
+    fn threat(db: &HashDatabase, hash: &str) -> Option<String> {
+        let signature = match hash.len() {
+            64 => db.sha256.get(&parse_hex::<32>(hash)?),
+            _ => db.md5.get(&parse_hex::<16>(hash)?),
+        };
+        signature.map(|s| s.threat.to_string())
+    }
+
+    fn load(contents: &str) -> HashDatabase {
+        let path = std::env::temp_dir().join(format!("griffon-feed-{}.csv", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let mut db = HashDatabase::new();
+        db.load_file(&path).unwrap();
+        fs::remove_file(path).unwrap();
+        db
+    }
+
+    #[test]
+    fn plain_lines_keep_the_whole_label() {
+        let mut db = HashDatabase::new();
+        let feed: Arc<str> = "feed".into();
+        assert!(db.add_line(&format!("{SHA256}  Trojan Foo"), &feed, None));
+        assert!(db.add_line(&format!("{MD5};\"EICAR test\";extra"), &feed, None));
+        assert_eq!(threat(&db, SHA256).as_deref(), Some("Trojan Foo"));
+        assert_eq!(threat(&db, MD5).as_deref(), Some("EICAR test"));
+    }
+
+    #[test]
+    fn unlabelled_lines_are_named_after_the_feed() {
+        let mut db = HashDatabase::new();
+        let feed: Arc<str> = "feed".into();
+        assert!(db.add_line(SHA256, &feed, None));
+        assert!(!db.add_line("not a hash", &feed, None));
+        assert!(!db.add_line(&SHA256[1..], &feed, None));
+        assert_eq!(threat(&db, SHA256).as_deref(), Some("feed"));
+    }
+
+    #[test]
+    fn headerless_csv_rows_use_the_first_hash_field() {
+        let mut db = HashDatabase::new();
+        let feed: Arc<str> = "feed".into();
+        assert!(db.add_line(&format!("2024-01-01,{SHA256},x"), &feed, None));
+        assert_eq!(threat(&db, SHA256).as_deref(), Some("feed"));
+    }
+
+    #[test]
+    fn hashes_inside_labels_are_not_comments() {
+        let db = load(&format!(
+            "# feed\n{SHA256}  Win.Trojan#2  # added 2026-10-01\n{MD5} Eicar#Test\n"
+        ));
+        assert_eq!(threat(&db, SHA256).as_deref(), Some("Win.Trojan#2"));
+        assert_eq!(threat(&db, MD5).as_deref(), Some("Eicar#Test"));
+    }
+
+    #[test]
+    fn csv_headers_locate_the_columns() {
+        let db = load(&format!(
+            "# \"first_seen_utc\",\"sha256_hash\",\"md5_hash\",\"signature\"\n\
+             \"2024-01-01 00:00:00\",\"{SHA256}\",\"{MD5}\",\"AgentTesla\"\n"
+        ));
+        assert_eq!(threat(&db, SHA256).as_deref(), Some("AgentTesla"));
+        assert_eq!(threat(&db, MD5), None);
+
+        // Without a label column, rows are named after the feed.
+        let db = load(&format!("md5;name\n{MD5};x\n"));
+        assert!(threat(&db, MD5).unwrap().starts_with("griffon-feed-"));
+    }
+}
@@ -1,30 +1,70 @@
-use static_analysis::{load_yara_rules, scan_file};
-use walkdir::WalkDir;
+use static_analysis::{HashDatabase, ScanOptions, load_yara_rules_cached, scan_path};
+use std::sync::Arc;
 use std::time::Instant;
 
 fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
     println!("Loading rules...");
     let load_start = Instant::now();
-    let rules = load_yara_rules("rules");
-    println!("Rules loaded in {:.2?}", load_start.elapsed());
+    let (rules, report) = load_yara_rules_cached("rules", "rules-cache");
+    println!(
+        "Rules loaded in {:.2?} ({} files, {} skipped)",
+        load_start.elapsed(),
+        report.loaded_files,
+        report.skipped_files()
+    );
+
+    // Usage: static_analysis [threads], 0 or nothing uses every CPU.
+    let options = ScanOptions {
+        threads: std::env::args()
+            .nth(1)
+            .and_then(|t| t.parse().ok())
+            .unwrap_or(0),
+        hashes: HashDatabase::load_dir("hashes")
+            .ok()
+            .filter(|db| !db.is_empty())
+            .map(Arc::new),
+        ..ScanOptions::default()
+    };
 
     println!("Scanning samples...");
     let scan_start = Instant::now();
     let mut total_hits = 0;
-    let mut files_scanned = 0;
+    let scans = match scan_path(&rules, "samples", &options) {
+        Ok(scans) => scans,
+        Err(e) => {
+            println!("[ERROR] {e}");
+            return;
+        }
+    };
 
-    for entry in WalkDir::new("samples").into_iter().filter_map(|e| e.ok()) {
-        if entry.path().is_file() {
-            // Optional: Skip hidden files or huge files if needed
-            let hits = scan_file(&rules, entry.path());
-            if hits > 0 {
-                println!("[ALERT] {:?} matched {} rules", entry.path(), hits);
-                total_hits += hits;
+    for scan in &scans {
+        match &scan.outcome {
+            Ok(result) if !result.is_clean() => {
+                let names: Vec<String> = result
+                    .hash_match
+                    .iter()
+                    .map(|h| format!("{}:{}", h.feed, h.threat))
+                    .chain(
+                        result
+                            .matches
+                            .iter()
+                            .map(|m| format!("{}:{}", m.namespace, m.identifier)),
+                    )
+                    .collect();
+                println!("[ALERT] {:?} matched {}", scan.path, names.join(", "));
+                total_hits += result.matches.len();
             }
-            files_scanned += 1;
+            Ok(_) => {}
+            Err(e) => println!("[ERROR] {e}"),
         }
     }
 
-    println!("Finished. Scanned {} files. Total matches: {} in {:.2?}", 
-        files_scanned, total_hits, scan_start.elapsed());
-}
\ No newline at end of file
+    println!(
+        "Finished. Scanned {} files. Total matches: {} in {:.2?}",
+        scans.len(),
+        total_hits,
+        scan_start.elapsed()
+    );
+}
@@ -0,0 +1,440 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io;
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use yara_x::Rules;
+
+use crate::scan::TimedScanner;
+use crate::{Finding, ScanOptions, ScanResult, Severity};
+
+/// Regions are read in chunks of this size, so one unreadable page only loses its chunk.
+const READ_CHUNK: usize = 1024 * 1024;
+/// Kernel-provided mappings that cannot be read through `mem`, by prefix (`[vvar_vclock]`).
+static UNREADABLE_REGIONS: [&str; 2] = ["[vvar", "[vsyscall]"];
+
+/// Settings for `scan_processes`, next to the per-file `ScanOptions`.
+#[derive(Debug, Clone)]
+pub struct ProcessScanOptions {
+    /// Processes to scan, all of them when empty.
+    pub pids: Vec<u32>,
+    /// Scan the memory regions as well as the executables.
+    pub memory: bool,
+    /// Also scan regions mapped from files still on disk (libraries, the
+    /// executable itself), which a file scan already covers. Off by default:
+    /// only anonymous, deleted-file and memfd regions are read.
+    pub all_regions: bool,
+    /// Larger regions are only scanned up to this size.
+    pub max_region_size: u64,
+    /// This process and all its descendants are left out, e.g. the daemon and
+    /// the plugin runners, whose memory holds rules of their own.
+    pub skip_tree: Option<u32>,
+}
+
+impl Default for ProcessScanOptions {
+    fn default() -> Self {
+        Self {
+            pids: Vec::new(),
+            memory: true,
+            all_regions: false,
+            max_region_size: 64 * 1024 * 1024,
+            skip_tree: None,
+        }
+    }
+}
+
+/// What was found in one running process.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessScan {
+    pub pid: u32,
+    /// Arguments joined with spaces, `[comm]` for kernel threads.
+    pub cmdline: String,
+    /// Target of `/proc/<pid>/exe`, without the ` (deleted)` suffix.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exe: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub exe_deleted: bool,
+    /// Shared by processes running the same binary, it is only scanned once.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exe_result: Option<ScanResult>,
+    /// Memory regions with matches.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub regions: Vec<RegionMatch>,
+    pub regions_scanned: usize,
+    /// Regions that could not be read, usually guard pages or a process exiting.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub regions_unreadable: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub findings: Vec<Finding>,
+    /// Why the executable or the memory could not be scanned, e.g. missing privileges.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+impl ProcessScan {
+    /// No match in the executable nor in memory, and no high severity finding.
+    pub fn is_clean(&self) -> bool {
+        self.exe_result.as_ref().is_none_or(ScanResult::is_clean)
+            && self.regions.is_empty()
+            && self.findings.iter().all(|f| f.severity < Severity::High)
+    }
+}
+
+/// A memory region that matched, string offsets being relative to `start`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RegionMatch {
+    pub start: u64,
+    pub end: u64,
+    /// As in `maps`, e.g. `rwxp`.
+    pub perms: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pathname: Option<String>,
+    pub result: ScanResult,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+/// One line of `/proc/<pid>/maps`.
+struct Region {
+    start: u64,
+    end: u64,
+    perms: String,
+    pathname: Option<String>,
+}
+
+impl Region {
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(6, ' ');
+        let (start, end) = fields.next()?.split_once('-')?;
+        let perms = fields.next()?.to_string();
+        // Offset, device and inode are not needed.
+        let pathname = fields.nth(3).map(str::trim).filter(|p| !p.is_empty());
+        Some(Self {
+            start: u64::from_str_radix(start, 16).ok()?,
+            end: u64::from_str_radix(end, 16).ok()?,
+            perms,
+            pathname: pathname.map(str::to_string),
+        })
+    }
+
+    /// Anonymous memory and mappings of files no longer on disk: where
+    /// injected or unpacked code lives.
+    fn is_suspicious(&self) -> bool {
+        match &self.pathname {
+            None => true,
+            Some(p) => {
+                p.starts_with("[heap]")
+                    || p.starts_with("[stack")
+                    || p.starts_with("[anon:")
+                    || p.starts_with("/memfd:")
+                    || p.ends_with(" (deleted)")
+            }
+        }
+    }
+
+    fn wanted(&self, options: &ProcessScanOptions) -> bool {
+        self.perms.starts_with('r')
+            && !self
+                .pathname
+                .as_deref()
+                .is_some_and(|p| UNREADABLE_REGIONS.iter().any(|r| p.starts_with(r)))
+            && (options.all_regions || self.is_suspicious())
+    }
+}
+
+fn cmdline(pid: u32) -> String {
+    match fs::read(format!("/proc/{pid}/cmdline")) {
+        Ok(raw) if !raw.is_empty() => String::from_utf8_lossy(&raw)
+            .trim_end_matches('\0')
+            .replace('\0', " "),
+        _ => fs::read_to_string(format!("/proc/{pid}/comm"))
+            .map(|comm| format!("[{}]", comm.trim_end()))
+            .unwrap_or_default(),
+    }
+}
+
+/// Parent of `pid`, from `/proc/<pid>/stat`.
+fn parent_pid(pid: u32) -> Option<u32> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // The command name may hold spaces and parentheses, the fields after it do not.
+    stat.rsplit_once(')')?
+        .1
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
+/// `root` and every process descending from it.
+fn process_tree(root: u32, pids: &[u32]) -> HashSet<u32> {
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    for &pid in pids {
+        if let Some(parent) = parent_pid(pid) {
+            children.entry(parent).or_default().push(pid);
+        }
+    }
+    let mut tree = HashSet::from([root]);
+    let mut pending = vec![root];
+    while let Some(pid) = pending.pop() {
+        for &child in children.get(&pid).into_iter().flatten() {
+            if tree.insert(child) {
+                pending.push(child);
+            }
+        }
+    }
+    tree
+}
+
+fn all_pids() -> io::Result<Vec<u32>> {
+    let mut pids: Vec<u32> = fs::read_dir("/proc")?
+        .filter_map(|e| e.ok()?.file_name().to_str()?.parse().ok())
+        .collect();
+    pids.sort_unstable();
+    Ok(pids)
+}
+
+/// Executable results by device and inode, shared by the workers.
+type ExeCache = Mutex<HashMap<(u64, u64), ScanResult>>;
+
+struct ProcessScanner<'r, 'o> {
+    scanner: TimedScanner<'r>,
+    options: &'o ProcessScanOptions,
+    exes: &'o ExeCache,
+}
+
+impl ProcessScanner<'_, '_> {
+    /// `None` when the process is gone, or is a kernel thread with nothing to scan.
+    fn scan(&mut self, pid: u32) -> Option<ProcessScan> {
+        let proc_dir = PathBuf::from(format!("/proc/{pid}"));
+        if !proc_dir.exists() {
+            return None;
+        }
+        let mut scan = ProcessScan {
+            pid,
+            cmdline: cmdline(pid),
+            exe: None,
+            exe_deleted: false,
+            exe_result: None,
+            regions: Vec::new(),
+            regions_scanned: 0,
+            regions_unreadable: 0,
+            findings: Vec::new(),
+            errors: Vec::new(),
+        };
+
+        match fs::read_link(proc_dir.join("exe")) {
+            Ok(target) => self.scan_exe(&mut scan, &proc_dir, &target),
+            // Kernel threads have no executable and no user memory.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => scan.errors.push(format!("exe: {e}")),
+        }
+        if self.options.memory {
+            self.scan_memory(&mut scan, &proc_dir);
+        }
+        Some(scan)
+    }
+
+    fn scan_exe(&mut self, scan: &mut ProcessScan, proc_dir: &Path, target: &Path) {
+        let target = target.to_string_lossy();
+        let (path, deleted) = match target.strip_suffix(" (deleted)") {
+            Some(path) => (path, true),
+            None => (target.as_ref(), false),
+        };
+        scan.exe = Some(path.to_string());
+        scan.exe_deleted = deleted;
+        if path.starts_with("/memfd:") {
+            scan.findings.push(Finding::new(
+                "process.memfd_exe",
+                Severity::High,
+                format!("runs from an in-memory file ({path}), nothing of it is on disk"),
+            ));
+        } else if deleted {
+            scan.findings.push(Finding::new(
+                "process.deleted_exe",
+                Severity::Medium,
+                format!("{path} was deleted after being started, or replaced by an upgrade"),
+            ));
+        }
+
+        // `exe` opens the binary itself, even when it is no longer on disk.
+        let file = match File::open(proc_dir.join("exe")) {
+            Ok(file) => file,
+            Err(e) => {
+                scan.errors.push(format!("exe: {e}"));
+                return;
+            }
+        };
+        let key = match file.metadata() {
+            Ok(metadata) => (metadata.dev(), metadata.ino()),
+            Err(e) => {
+                scan.errors.push(format!("exe: {e}"));
+                return;
+            }
+        };
+        if let Some(result) = self.exes.lock().unwrap().get(&key) {
+            scan.exe_result = Some(result.clone());
+            return;
+        }
+        match self.scanner.scan_open_file(file, Path::new(path)) {
+            Ok(result) => {
+                self.exes.lock().unwrap().insert(key, result.clone());
+                scan.exe_result = Some(result);
+            }
+            Err(e) => scan.errors.push(format!("exe: {e}")),
+        }
+    }
+
+    fn scan_memory(&mut self, scan: &mut ProcessScan, proc_dir: &Path) {
+        let maps = match fs::read_to_string(proc_dir.join("maps")) {
+            Ok(maps) => maps,
+            Err(e) => {
+                scan.errors.push(format!("maps: {e}"));
+                return;
+            }
+        };
+        // Needs ptrace access to the process: root, or the same user without Yama restrictions.
+        let mem = match File::open(proc_dir.join("mem")) {
+            Ok(mem) => mem,
+            Err(e) => {
+                scan.errors.push(format!("mem: {e}"));
+                return;
+            }
+        };
+
+        // The regions of a process share one time budget, like the windows of a file,
+        // grown as regions are read.
+        self.scanner.start_input(0);
+        let mut read = 0u64;
+        let mut buffer = Vec::new();
+        for region in maps.lines().filter_map(Region::parse) {
+            if !region.wanted(self.options) {
+                continue;
+            }
+            let size = (region.end - region.start).min(self.options.max_region_size);
+            if read_region(&mem, region.start, size as usize, &mut buffer).is_err() {
+                scan.regions_unreadable += 1;
+                continue;
+            }
+            scan.regions_scanned += 1;
+            read += buffer.len() as u64;
+            self.scanner.grow_input(read);
+            match self.scanner.scan_bytes(&buffer) {
+                Ok(result) if !result.matches.is_empty() => scan.regions.push(RegionMatch {
+                    start: region.start,
+                    end: region.end,
+                    perms: region.perms,
+                    pathname: region.pathname,
+                    result,
+                }),
+                Ok(_) => {}
+                Err(e) => scan
+                    .errors
+                    .push(format!("region {:x}-{:x}: {e}", region.start, region.end)),
+            }
+        }
+    }
+}
+
+/// Reads `size` bytes at `start` into `buffer`. Chunks that fail, such as
+/// guard pages in the middle of a region, are left zeroed; the read only fails
+/// when nothing could be read.
+fn read_region(mem: &File, start: u64, size: usize, buffer: &mut Vec<u8>) -> io::Result<()> {
+    buffer.clear();
+    buffer.resize(size, 0);
+    let mut read_any = false;
+    let mut last_error = None;
+    for (index, chunk) in buffer.chunks_mut(READ_CHUNK).enumerate() {
+        match mem.read_exact_at(chunk, start + (index * READ_CHUNK) as u64) {
+            Ok(()) => read_any = true,
+            Err(e) => {
+                chunk.fill(0);
+                last_error = Some(e);
+            }
+        }
+    }
+    match last_error {
+        Some(e) if !read_any => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Scans the executable and, unless disabled, the memory of running processes
+/// with the rules, on the worker threads of `options`. Binaries shared by
+/// several processes are scanned once. Results are sorted by pid; processes
+/// that exited meanwhile and kernel threads are left out, as are the calling
+/// process, other instances of its executable and the `skip_tree` processes.
+///
+/// Reading other users' processes needs root (`CAP_SYS_PTRACE`), failures
+/// are reported in `ProcessScan::errors` rather than stopping the scan.
+pub fn scan_processes(
+    rules: &Rules,
+    options: &ScanOptions,
+    process_options: &ProcessScanOptions,
+) -> io::Result<Vec<ProcessScan>> {
+    let running = all_pids()?;
+    let mut pids = if process_options.pids.is_empty() {
+        running.clone()
+    } else {
+        process_options.pids.clone()
+    };
+    // Our own memory holds the rules themselves, so does that of every
+    // process running the same executable, such as the other plugin runners.
+    let mut skipped = match process_options.skip_tree {
+        Some(root) => process_tree(root, &running),
+        None => HashSet::new(),
+    };
+    skipped.insert(std::process::id());
+    let own_exe = fs::read_link("/proc/self/exe").ok();
+    pids.retain(|pid| {
+        !skipped.contains(pid)
+            && (own_exe.is_none() || fs::read_link(format!("/proc/{pid}/exe")).ok() != own_exe)
+    });
+    let queue = Mutex::new(pids.into_iter());
+    let exes = ExeCache::default();
+    let done = Mutex::new(Vec::new());
+
+    thread::scope(|scope| {
+        for _ in 0..options.worker_count() {
+            scope.spawn(|| {
+                let mut scanner = ProcessScanner {
+                    scanner: TimedScanner::new(rules, options),
+                    options: process_options,
+                    exes: &exes,
+                };
+                loop {
+                    let Some(pid) = queue.lock().unwrap().next() else {
+                        break;
+                    };
+                    if let Some(scan) = scanner.scan(pid) {
+                        done.lock().unwrap().push(scan);
+                    }
+                }
+            });
+        }
+    });
+
+    let mut scans = done.into_inner().unwrap();
+    scans.sort_by_key(|s| s.pid);
+    Ok(scans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn process_tree_holds_the_descendants() {
+        let me = std::process::id();
+        let parent = std::os::unix::process::parent_id();
+        assert_eq!(parent_pid(me), Some(parent));
+
+        let pids = all_pids().unwrap();
+        assert!(process_tree(parent, &pids).contains(&me));
+        assert!(!process_tree(me, &pids).contains(&parent));
+    }
+}
@@ -0,0 +1,236 @@
+use serde::Serialize;
+use std::fmt;
+
+use crate::{ElfInfo, HashMatch};
+
+/// Everything a scan found in one input.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ScanResult {
+    /// Set when the input is listed in a hash feed, the rules are then not run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash_match: Option<HashMatch>,
+    pub matches: Vec<RuleMatch>,
+    pub scanned_bytes: u64,
+    /// Archive members, at any depth, that matched or could not be scanned.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub entries: Vec<ArchiveEntryScan>,
+    /// Not read again: the file was clean and is unchanged since, see `ScanCache`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+    /// Suspicious traits found by the heuristics, independently of the rules.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub findings: Vec<Finding>,
+    /// Set when the input is an ELF binary and the heuristics are enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elf: Option<ElfInfo>,
+}
+
+impl ScanResult {
+    /// Nothing matched, neither the input itself nor any archive member.
+    /// Findings below `Severity::High` are informative and do not count.
+    pub fn is_clean(&self) -> bool {
+        self.hash_match.is_none()
+            && self.matches.is_empty()
+            && self.findings.iter().all(|f| f.severity < Severity::High)
+            && self
+                .entries
+                .iter()
+                .all(|e| e.result.as_ref().is_none_or(ScanResult::is_clean))
+    }
+
+    /// Nothing at all to report: clean, without even informative findings, and
+    /// every archive member scanned. Only such files are skipped by `ScanCache`,
+    /// which could not give their findings back.
+    pub(crate) fn is_unremarkable(&self) -> bool {
+        self.is_clean() && self.findings.is_empty() && self.entries.is_empty()
+    }
+
+    /// Adds the matches of a window that starts at `base` in the scanned stream.
+    /// Matches found again in the overlap with the previous window are dropped.
+    pub(crate) fn merge_window(&mut self, window: ScanResult, base: u64) {
+        for mut rule in window.matches {
+            for string in &mut rule.strings {
+                string.offset += base as usize;
+            }
+            match self
+                .matches
+                .iter_mut()
+                .find(|m| m.namespace == rule.namespace && m.identifier == rule.identifier)
+            {
+                Some(existing) => {
+                    for string in rule.strings {
+                        let seen = existing.strings.iter().any(|s| {
+                            s.identifier == string.identifier && s.offset == string.offset
+                        });
+                        if !seen {
+                            existing.strings.push(string);
+                        }
+                    }
+                }
+                None => self.matches.push(rule),
+            }
+        }
+    }
+
+    pub(crate) fn from_yara(results: &yara_x::ScanResults, scanned_bytes: u64) -> Self {
+        Self {
+            hash_match: None,
+            matches: results.matching_rules().map(RuleMatch::from_yara).collect(),
+            scanned_bytes,
+            entries: Vec::new(),
+            cached: false,
+            findings: Vec::new(),
+            elf: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+}
+
+/// A suspicious trait reported by a heuristic, e.g. `elf.rwx_segment`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    /// `<stage>.<trait>`, stable so it can be filtered or allow-listed.
+    pub id: String,
+    pub severity: Severity,
+    pub explanation: String,
+}
+
+impl Finding {
+    pub fn new(id: &str, severity: Severity, explanation: String) -> Self {
+        Self {
+            id: id.to_string(),
+            severity,
+            explanation,
+        }
+    }
+}
+
+/// A member of a scanned archive, `path` being nested like `a.zip!/inner.tar!/evil.sh`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchiveEntryScan {
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<ScanResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ArchiveEntryScan {
+    pub(crate) fn failed(path: &str, error: String) -> Self {
+        Self {
+            path: path.to_string(),
+            result: None,
+            error: Some(error),
+        }
+    }
+}
+
+/// A rule that matched, with what the rule author documented about it.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleMatch {
+    pub namespace: String,
+    pub identifier: String,
+    pub tags: Vec<String>,
+    /// Every `meta:` entry of the rule, in declaration order.
+    pub metadata: Vec<(String, MetaValue)>,
+    pub description: Option<String>,
+    pub severity: Option<String>,
+    pub author: Option<String>,
+    pub strings: Vec<StringMatch>,
+}
+
+impl RuleMatch {
+    fn from_yara(rule: yara_x::Rule) -> Self {
+        let metadata: Vec<(String, MetaValue)> = rule
+            .metadata()
+            .map(|(key, value)| (key.to_string(), MetaValue::from(value)))
+            .collect();
+
+        // Rule sets disagree on naming, take the first of the usual spellings.
+        let lookup = |keys: &[&str]| {
+            metadata
+                .iter()
+                .find(|(k, _)| keys.iter().any(|key| k.eq_ignore_ascii_case(key)))
+                .map(|(_, v)| v.to_string())
+        };
+        let description = lookup(&["description", "desc"]);
+        let severity = lookup(&["severity", "score", "threat_level"]);
+        let author = lookup(&["author"]);
+
+        let strings = rule
+            .patterns()
+            .flat_map(|pattern| {
+                let identifier = pattern.identifier();
+                pattern.matches().map(move |m| {
+                    let range = m.range();
+                    StringMatch {
+                        identifier: identifier.to_string(),
+                        offset: range.start,
+                        length: range.len(),
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            namespace: rule.namespace().to_string(),
+            identifier: rule.identifier().to_string(),
+            tags: rule.tags().map(|t| t.identifier().to_string()).collect(),
+            metadata,
+            description,
+            severity,
+            author,
+            strings,
+        }
+    }
+}
+
+/// One occurrence of a rule string (`$a`, `$hex`, ...) in the input.
+#[derive(Debug, Clone, Serialize)]
+pub struct StringMatch {
+    pub identifier: String,
+    pub offset: usize,
+    pub length: usize,
+}
+
+/// Owned copy of a `meta:` value.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum MetaValue {
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Bytes(Vec<u8>),
+}
+
+impl From<yara_x::MetaValue<'_>> for MetaValue {
+    fn from(value: yara_x::MetaValue<'_>) -> Self {
+        match value {
+            yara_x::MetaValue::Integer(i) => MetaValue::Integer(i),
+            yara_x::MetaValue::Float(f) => MetaValue::Float(f),
+            yara_x::MetaValue::Bool(b) => MetaValue::Bool(b),
+            yara_x::MetaValue::String(s) => MetaValue::String(s.to_string()),
+            yara_x::MetaValue::Bytes(b) => MetaValue::Bytes(b.to_vec()),
+        }
+    }
+}
+
+impl fmt::Display for MetaValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetaValue::Integer(i) => write!(f, "{i}"),
+            MetaValue::Float(x) => write!(f, "{x}"),
+            MetaValue::Bool(b) => write!(f, "{b}"),
+            MetaValue::String(s) => f.write_str(s),
+            MetaValue::Bytes(b) => write!(f, "{}", String::from_utf8_lossy(b)),
+        }
+    }
+}
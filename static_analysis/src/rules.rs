@@ -0,0 +1,416 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+use yara_x::{Compiler, Rules, SourceCode};
+
+use crate::RuleMatch;
+
+/// Injected in every rule set so the "infected" samples always have something to match.
+static BENCHMARK_RULE: &str = r#"
+    rule Benchmark_Test {
+        strings:
+            $a = "RUST_AV_BENCHMARK_PAYLOAD_SIGNATURE"
+        condition:
+            $a
+    }
+"#;
+
+/// Rules shipped with the engine, built on yara-x modules such as `elf`.
+/// Compiled into `BUILTIN_NAMESPACE` of every rule set.
+static BUILTIN_RULES: &str = include_str!("../builtin/heuristics.yar");
+
+/// Extension of the compiled rule files written by `load_yara_rules_cached`.
+static CACHE_EXTENSION: &str = "yarc";
+/// Extension of the `RuleLoadReport` saved next to each compiled rule file.
+static REPORT_EXTENSION: &str = "json";
+/// Start of every file name written by `load_yara_rules_cached`, followed by
+/// the rules directory key and the rule set fingerprint.
+static CACHE_PREFIX: &str = "rules";
+
+/// Namespace of the benchmark rule and of the files at the top of the rules directory.
+pub static DEFAULT_NAMESPACE: &str = "default";
+/// Namespace of the rules shipped with the engine, see `BUILTIN_RULES`.
+pub static BUILTIN_NAMESPACE: &str = "heuristics";
+
+/// A rule file found on disk, read before compiling so it can also be hashed.
+struct RuleSource {
+    path: PathBuf,
+    /// Top-level directory of the file under the rules directory.
+    namespace: String,
+    contents: io::Result<String>,
+}
+
+/// `<dir>/malware/linux/x.yar` goes to the `malware` namespace, `<dir>/x.yar` to the default one.
+fn namespace_for(dir: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(dir).unwrap_or(path);
+    let mut components = relative.components();
+    match (components.next(), components.next()) {
+        (Some(first), Some(_)) => first.as_os_str().to_string_lossy().into_owned(),
+        _ => DEFAULT_NAMESPACE.to_string(),
+    }
+}
+
+/// Rule files under `dir`, grouped by namespace (default first) and sorted by path
+/// so neither the compiled rules nor the fingerprint depend on readdir order.
+fn collect_sources(dir: &Path) -> Vec<RuleSource> {
+    let mut sources: Vec<RuleSource> = WalkDir::new(dir)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        // Strict extension check
+        .filter(|e| {
+            e.path()
+                .extension()
+                .is_some_and(|ext| ext == "yar" || ext == "yara")
+        })
+        .map(|e| RuleSource {
+            namespace: namespace_for(dir, e.path()),
+            contents: fs::read_to_string(e.path()),
+            path: e.into_path(),
+        })
+        .collect();
+
+    sources.sort_by(|a, b| {
+        let key = |s: &RuleSource| (s.namespace != DEFAULT_NAMESPACE, s.namespace.clone());
+        key(a).cmp(&key(b)).then_with(|| a.path.cmp(&b.path))
+    });
+    sources
+}
+
+/// Hash of everything that affects the compiled rules: the sources and the engine version.
+fn fingerprint(sources: &[RuleSource]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("YARA_X_VERSION"));
+    hasher.update([0]);
+    hasher.update(BENCHMARK_RULE);
+    hasher.update([0]);
+    hasher.update(BUILTIN_RULES);
+    for source in sources {
+        hasher.update([0]);
+        hasher.update(&source.namespace);
+        hasher.update([0]);
+        hasher.update(source.path.as_os_str().as_encoded_bytes());
+        hasher.update([0]);
+        match &source.contents {
+            Ok(contents) => hasher.update(contents),
+            Err(_) => hasher.update("<unreadable>"),
+        }
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Problem found in one rule file while compiling.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleFailure {
+    pub path: PathBuf,
+    /// Full yara-x diagnostic, or the I/O error for unreadable files.
+    pub message: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    /// True when the file was left out of the rule set, false for warnings.
+    pub skipped: bool,
+}
+
+/// Outcome of loading a rules directory, so broken rules can be found and fixed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RuleLoadReport {
+    pub loaded_files: usize,
+    pub failures: Vec<RuleFailure>,
+    /// The rules came from the compiled cache, the report is the one saved with it.
+    pub from_cache: bool,
+    /// Changes whenever the rule files or the yara-x version do, see `cache_version`.
+    #[serde(default)]
+    pub rules_version: String,
+}
+
+impl RuleLoadReport {
+    pub fn skipped_files(&self) -> usize {
+        self.failures.iter().filter(|f| f.skipped).count()
+    }
+
+    fn log_summary(&self) {
+        log::info!(
+            "Rules loaded: {} files, {} skipped, {} with warnings{}",
+            self.loaded_files,
+            self.skipped_files(),
+            self.failures.iter().filter(|f| !f.skipped).count(),
+            if self.from_cache { " (from cache)" } else { "" }
+        );
+    }
+}
+
+impl RuleFailure {
+    fn new(path: &Path, message: String, skipped: bool) -> Self {
+        let (line, column) = diagnostic_position(&message).unzip();
+        Self {
+            path: path.to_path_buf(),
+            message,
+            line,
+            column,
+            skipped,
+        }
+    }
+
+    fn log(&self) {
+        let at = match (self.line, self.column) {
+            (Some(line), Some(column)) => format!(":{line}:{column}"),
+            _ => String::new(),
+        };
+        if self.skipped {
+            log::warn!("Skipped {}{at}: {}", self.path.display(), self.message);
+        } else {
+            log::info!("Warning in {}{at}: {}", self.path.display(), self.message);
+        }
+    }
+}
+
+/// Reads `line:column` from the ` --> origin:line:column` marker of a yara-x diagnostic.
+fn diagnostic_position(message: &str) -> Option<(usize, usize)> {
+    let location = message
+        .lines()
+        .find_map(|l| l.trim_start().strip_prefix("-->"))?;
+    let mut parts = location.trim().rsplitn(3, ':');
+    let column = parts.next()?.parse().ok()?;
+    let line = parts.next()?.parse().ok()?;
+    Some((line, column))
+}
+
+fn compile(sources: &[RuleSource]) -> (Rules, RuleLoadReport) {
+    let mut compiler = Compiler::new();
+    let mut report = RuleLoadReport {
+        rules_version: fingerprint(sources),
+        ..RuleLoadReport::default()
+    };
+
+    compiler
+        .new_namespace(BUILTIN_NAMESPACE)
+        .add_source(SourceCode::from(BUILTIN_RULES).with_origin("builtin/heuristics.yar"))
+        .expect("Failed to add the builtin rules");
+    compiler
+        .new_namespace(DEFAULT_NAMESPACE)
+        .add_source(BENCHMARK_RULE)
+        .expect("Failed to add internal benchmark rule");
+    let mut namespace = DEFAULT_NAMESPACE;
+
+    for source in sources {
+        // Sources are grouped by namespace, each one is opened once.
+        if source.namespace != namespace {
+            namespace = &source.namespace;
+            compiler.new_namespace(namespace);
+        }
+
+        let contents = match &source.contents {
+            Ok(contents) => contents,
+            Err(e) => {
+                let failure = RuleFailure::new(&source.path, e.to_string(), true);
+                failure.log();
+                report.failures.push(failure);
+                continue;
+            }
+        };
+
+        let warnings_before = compiler.warnings().len();
+        let error = compiler
+            .add_source(
+                SourceCode::from(contents.as_str()).with_origin(source.path.display().to_string()),
+            )
+            .err()
+            .map(|e| e.to_string());
+
+        let mut failures: Vec<RuleFailure> = compiler.warnings()[warnings_before..]
+            .iter()
+            .map(|w| RuleFailure::new(&source.path, w.to_string(), false))
+            .collect();
+        match error {
+            None => report.loaded_files += 1,
+            Some(e) => failures.push(RuleFailure::new(&source.path, e, true)),
+        }
+
+        for failure in failures {
+            failure.log();
+            report.failures.push(failure);
+        }
+    }
+
+    report.log_summary();
+    (compiler.build(), report)
+}
+
+/// Recursively loads and compiles the rules under `dir`.
+/// Also injects a synthetic rule for benchmarking and the builtin rules.
+pub fn load_yara_rules<P: AsRef<Path>>(dir: P) -> (Rules, RuleLoadReport) {
+    log::info!("Loading rules from {}", dir.as_ref().display());
+    compile(&collect_sources(dir.as_ref()))
+}
+
+/// Like `load_yara_rules`, but reuses the compiled rules stored in `cache_dir`
+/// as long as neither the rule files nor the yara-x version changed.
+///
+/// Cache failures are never fatal: the rules are compiled from source instead.
+pub fn load_yara_rules_cached<P: AsRef<Path>, Q: AsRef<Path>>(
+    dir: P,
+    cache_dir: Q,
+) -> (Rules, RuleLoadReport) {
+    let cache_dir = cache_dir.as_ref();
+    let sources = collect_sources(dir.as_ref());
+    let version = fingerprint(&sources);
+    let prefix = cache_prefix(dir.as_ref());
+    let cache_file = cache_dir.join(format!("{prefix}{version}.{CACHE_EXTENSION}"));
+
+    if let Ok(bytes) = fs::read(&cache_file) {
+        match Rules::deserialize(bytes) {
+            Ok(rules) => {
+                log::info!("Loaded compiled rules from {}", cache_file.display());
+                let mut report: RuleLoadReport =
+                    fs::read(cache_file.with_extension(REPORT_EXTENSION))
+                        .ok()
+                        .and_then(|raw| serde_json::from_slice(&raw).ok())
+                        .unwrap_or_default();
+                report.from_cache = true;
+                report.rules_version = version;
+                report.log_summary();
+                return (rules, report);
+            }
+            Err(e) => log::warn!("Ignoring unusable rule cache {}: {e}", cache_file.display()),
+        }
+    }
+
+    let (rules, report) = compile(&sources);
+    if let Err(e) = store_cache(&rules, &report, &cache_file, &prefix) {
+        log::warn!("Could not write rule cache {}: {e}", cache_file.display());
+    }
+    (rules, report)
+}
+
+/// Names the cache files of one rules directory, so several rule sets can share
+/// a cache directory without pruning each other.
+fn cache_prefix(dir: &Path) -> String {
+    let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    let digest = Sha256::digest(dir.as_os_str().as_encoded_bytes());
+    format!("{CACHE_PREFIX}-{}-", hex::encode(&digest[..8]))
+}
+
+/// Writes the new cache atomically, then drops the ones left by older rule
+/// sets of the same directory. Other files in the cache directory are kept.
+fn store_cache(
+    rules: &Rules,
+    report: &RuleLoadReport,
+    cache_file: &Path,
+    prefix: &str,
+) -> io::Result<()> {
+    let bytes = rules.serialize().map_err(io::Error::other)?;
+    let dir = cache_file.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+
+    // The report goes first: a cache file without one only loses the diagnostics.
+    let report_file = cache_file.with_extension(REPORT_EXTENSION);
+    fs::write(
+        &report_file,
+        serde_json::to_vec(report).map_err(io::Error::other)?,
+    )?;
+
+    let tmp = cache_file.with_extension("tmp");
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, cache_file)?;
+
+    for entry in fs::read_dir(dir)?.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let current = path == cache_file || path == report_file;
+        let older = entry.file_name().to_string_lossy().starts_with(prefix)
+            && path
+                .extension()
+                .is_some_and(|ext| ext == CACHE_EXTENSION || ext == REPORT_EXTENSION);
+        if older && !current {
+            let _ = fs::remove_file(path);
+        }
+    }
+    Ok(())
+}
+
+/// Identity of a compiled rule, as listed to users.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleInfo {
+    pub namespace: String,
+    pub identifier: String,
+    pub tags: Vec<String>,
+}
+
+pub fn list_rules(rules: &Rules) -> Vec<RuleInfo> {
+    rules
+        .iter()
+        .map(|rule| RuleInfo {
+            namespace: rule.namespace().to_string(),
+            identifier: rule.identifier().to_string(),
+            tags: rule.tags().map(|t| t.identifier().to_string()).collect(),
+        })
+        .collect()
+}
+
+/// Rules of one namespace, as offered to users choosing what to detect.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleCategory {
+    pub namespace: String,
+    pub rules: usize,
+    /// Rule count per tag within the namespace.
+    pub tags: BTreeMap<String, usize>,
+}
+
+pub fn list_categories(rules: &Rules) -> Vec<RuleCategory> {
+    let mut categories: BTreeMap<String, RuleCategory> = BTreeMap::new();
+    for rule in rules.iter() {
+        let category = categories
+            .entry(rule.namespace().to_string())
+            .or_insert_with(|| RuleCategory {
+                namespace: rule.namespace().to_string(),
+                rules: 0,
+                tags: BTreeMap::new(),
+            });
+        category.rules += 1;
+        for tag in rule.tags() {
+            *category
+                .tags
+                .entry(tag.identifier().to_string())
+                .or_default() += 1;
+        }
+    }
+    categories.into_values().collect()
+}
+
+/// Which matches are reported, by namespace and tag. Empty lists impose nothing,
+/// and a disabled namespace or tag wins over an enabled one.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RuleFilter {
+    pub enabled_namespaces: Vec<String>,
+    pub disabled_namespaces: Vec<String>,
+    /// When not empty, a rule needs at least one of these tags.
+    pub enabled_tags: Vec<String>,
+    pub disabled_tags: Vec<String>,
+}
+
+impl RuleFilter {
+    pub fn is_empty(&self) -> bool {
+        self.enabled_namespaces.is_empty()
+            && self.disabled_namespaces.is_empty()
+            && self.enabled_tags.is_empty()
+            && self.disabled_tags.is_empty()
+    }
+
+    pub fn keeps(&self, rule: &RuleMatch) -> bool {
+        let listed = |list: &[String], value: &str| list.iter().any(|v| v == value);
+
+        let namespace_ok = (self.enabled_namespaces.is_empty()
+            || listed(&self.enabled_namespaces, &rule.namespace))
+            && !listed(&self.disabled_namespaces, &rule.namespace);
+        let tags_ok = (self.enabled_tags.is_empty()
+            || rule.tags.iter().any(|t| listed(&self.enabled_tags, t)))
+            && !rule.tags.iter().any(|t| listed(&self.disabled_tags, t));
+
+        namespace_ok && tags_ok
+    }
+}
@@ -0,0 +1,512 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use memmap2::Mmap;
+use std::fs::{File, Metadata};
+use std::io::Read;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+use walkdir::{DirEntry, WalkDir};
+use yara_x::{Rules, Scanner};
+
+use crate::archive::{ArchiveLimits, scan_archive};
+use crate::elf::{analyze_elf, is_elf};
+use crate::hashdb::HashDatabase;
+use crate::script::analyze_script;
+use crate::{RuleFilter, ScanCache, ScanError, ScanResult};
+
+/// Longest time yara-x may spend on each `TIMEOUT_SPAN` of a single input, all
+/// of its stream windows and archive members together.
+pub const SCAN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Files that cannot be memory-mapped are read whole up to this size, streamed above it.
+pub const MAX_IN_MEMORY: u64 = 64 * 1024 * 1024;
+/// Window size of the streaming scan.
+pub const STREAM_CHUNK: usize = 16 * 1024 * 1024;
+/// Bytes shared by consecutive windows, so matches across a boundary are still found.
+pub const STREAM_OVERLAP: usize = 64 * 1024;
+/// Inputs get the timeout once per started span of this many bytes, so a
+/// multi-GB image is not held to the time given to a small file.
+pub const TIMEOUT_SPAN: u64 = 256 * 1024 * 1024;
+
+/// Kernel pseudo filesystems, whose files are not worth scanning and may block on read.
+static PSEUDO_FS_ROOTS: [&str; 3] = ["/proc", "/sys", "/dev"];
+
+/// Settings for `scan_path`.
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    /// Worker threads, 0 uses one per available CPU.
+    pub threads: usize,
+    /// Larger files are reported as `ScanError::TooLarge` instead of being scanned.
+    pub max_file_size: Option<u64>,
+    /// When not empty, only files whose path matches one of these globs are scanned.
+    pub include: Vec<String>,
+    /// Files and directories matching one of these globs are left out.
+    pub exclude: Vec<String>,
+    pub follow_symlinks: bool,
+    /// Do not descend into directories on another filesystem than the root.
+    pub one_file_system: bool,
+    /// Do not descend into `/proc`, `/sys` and `/dev`.
+    pub skip_pseudo_fs: bool,
+    /// Limit given to yara-x per `TIMEOUT_SPAN` of a file, shared by the
+    /// windows and members of the file.
+    pub timeout: Duration,
+    /// Unpack and scan the members of archives, `None` scans them as raw bytes.
+    pub archives: Option<ArchiveLimits>,
+    /// Known-bad hashes, checked before the rules.
+    pub hashes: Option<Arc<HashDatabase>>,
+    /// Threat categories to report, matches of other rules are dropped.
+    pub filter: RuleFilter,
+    /// Skips files found clean by an earlier `scan_path` and unchanged since.
+    /// Saved at the end of each `scan_path`.
+    pub cache: Option<Arc<ScanCache>>,
+    /// Run the heuristic stages (ELF analysis, script patterns) alongside the rules.
+    /// They see files only, not archive members.
+    pub heuristics: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            threads: 0,
+            max_file_size: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            follow_symlinks: false,
+            one_file_system: false,
+            skip_pseudo_fs: true,
+            timeout: SCAN_TIMEOUT,
+            archives: Some(ArchiveLimits::default()),
+            hashes: None,
+            filter: RuleFilter::default(),
+            cache: None,
+            heuristics: true,
+        }
+    }
+}
+
+impl ScanOptions {
+    pub(crate) fn worker_count(&self) -> usize {
+        match self.threads {
+            0 => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            n => n,
+        }
+    }
+}
+
+fn glob_set(patterns: &[String]) -> Result<GlobSet, ScanError> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern)
+            .map_err(|e| ScanError::InvalidOptions(format!("bad pattern {pattern}: {e}")))?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|e| ScanError::InvalidOptions(e.to_string()))
+}
+
+/// The path filters of `ScanOptions`, compiled once per scan.
+struct PathFilter {
+    include: GlobSet,
+    exclude: GlobSet,
+    skip_pseudo_fs: bool,
+}
+
+impl PathFilter {
+    fn new(options: &ScanOptions) -> Result<Self, ScanError> {
+        Ok(Self {
+            include: glob_set(&options.include)?,
+            exclude: glob_set(&options.exclude)?,
+            skip_pseudo_fs: options.skip_pseudo_fs,
+        })
+    }
+
+    /// Entries for which this is false are not descended into.
+    fn walks(&self, entry: &DirEntry) -> bool {
+        let path = entry.path();
+        // The root is always walked, even when it is one of the pseudo filesystems.
+        let pseudo = entry.depth() > 0
+            && self.skip_pseudo_fs
+            && PSEUDO_FS_ROOTS.iter().any(|root| path == Path::new(root));
+        !pseudo && !self.exclude.is_match(path)
+    }
+
+    fn includes(&self, path: &Path) -> bool {
+        self.include.is_empty() || self.include.is_match(path)
+    }
+}
+
+/// Outcome of scanning one file under the root given to `scan_path`.
+#[derive(Debug)]
+pub struct FileScan {
+    pub path: PathBuf,
+    pub outcome: Result<ScanResult, ScanError>,
+}
+
+/// A yara-x scanner and the options it was configured with.
+pub(crate) struct TimedScanner<'r> {
+    scanner: Scanner<'r>,
+    timeout: Duration,
+    /// When the current input was started, see `start_input`.
+    started: Instant,
+    /// Time given to the current input, `timeout` per `TIMEOUT_SPAN`.
+    budget: Duration,
+    archives: Option<ArchiveLimits>,
+    hashes: Option<Arc<HashDatabase>>,
+    filter: RuleFilter,
+    heuristics: bool,
+}
+
+impl<'r> TimedScanner<'r> {
+    pub(crate) fn new(rules: &'r Rules, options: &ScanOptions) -> Self {
+        Self {
+            scanner: Scanner::new(rules),
+            timeout: options.timeout,
+            started: Instant::now(),
+            budget: options.timeout,
+            archives: options.archives,
+            hashes: options.hashes.clone(),
+            filter: options.filter.clone(),
+            heuristics: options.heuristics,
+        }
+    }
+
+    /// Gives the next input, `size` bytes long, its time. Every yara-x call
+    /// until the next one only gets what is left of it.
+    pub(crate) fn start_input(&mut self, size: u64) {
+        self.started = Instant::now();
+        self.budget = budget_for(self.timeout, size);
+    }
+
+    /// Extends the time of the current input once `size` bytes of it are
+    /// known, for inputs whose size is only learnt while reading them.
+    pub(crate) fn grow_input(&mut self, size: u64) {
+        self.budget = self.budget.max(budget_for(self.timeout, size));
+    }
+
+    /// Checks the hash database, then the rules if the hash is not known.
+    fn scan_data(&mut self, data: &[u8]) -> Result<ScanResult, ScanError> {
+        if let Some(hash_match) = self.hashes.as_ref().and_then(|db| db.lookup(data)) {
+            return Ok(ScanResult {
+                hash_match: Some(hash_match),
+                scanned_bytes: data.len() as u64,
+                ..ScanResult::default()
+            });
+        }
+        self.scan_bytes(data)
+    }
+
+    /// Scans the contents of a file, and its members if it is an archive.
+    /// A file known by its hash is neither unpacked nor analyzed.
+    fn scan_contents(&mut self, data: &[u8], path: &Path) -> Result<ScanResult, ScanError> {
+        let mut result = self.scan_data(data)?;
+        if result.hash_match.is_some() {
+            return Ok(result);
+        }
+        if self.heuristics {
+            if is_elf(data) {
+                if let Some((info, findings)) = analyze_elf(data, Some(path)) {
+                    result.elf = Some(info);
+                    result.findings.extend(findings);
+                }
+            } else {
+                result.findings.extend(analyze_script(data));
+            }
+        }
+        if let Some(limits) = self.archives {
+            let name = path.file_name().map_or_else(
+                || path.display().to_string(),
+                |n| n.to_string_lossy().into_owned(),
+            );
+            if let Some(entries) =
+                scan_archive(data, &name, limits, &mut |member| self.scan_data(member))
+            {
+                result.entries = entries;
+            }
+        }
+        Ok(result)
+    }
+
+    /// Scans one buffer of the current input within its remaining time.
+    pub(crate) fn scan_bytes(&mut self, input: &[u8]) -> Result<ScanResult, ScanError> {
+        let remaining = self.budget.saturating_sub(self.started.elapsed());
+        if remaining.is_zero() {
+            return Err(ScanError::Timeout(self.budget));
+        }
+        self.scanner.set_timeout(remaining);
+        let results = self
+            .scanner
+            .scan(input)
+            .map_err(|e| ScanError::from_yara(e, self.budget))?;
+        let mut result = ScanResult::from_yara(&results, input.len() as u64);
+        if !self.filter.is_empty() {
+            result.matches.retain(|m| self.filter.keeps(m));
+        }
+        Ok(result)
+    }
+
+    /// Regular files are memory-mapped. Files that cannot be mapped are read
+    /// into memory, or streamed when larger than `MAX_IN_MEMORY`.
+    /// Streamed inputs are not unpacked even if they are archives.
+    fn scan_file(&mut self, path: &Path) -> Result<ScanResult, ScanError> {
+        let file = File::open(path).map_err(|source| ScanError::Unreadable {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        self.scan_open_file(file, path)
+    }
+
+    /// `path` is only used in errors, to name archive members and by the heuristics.
+    pub(crate) fn scan_open_file(
+        &mut self,
+        mut file: File,
+        path: &Path,
+    ) -> Result<ScanResult, ScanError> {
+        let unreadable = |source| ScanError::Unreadable {
+            path: path.to_path_buf(),
+            source,
+        };
+        let metadata = file.metadata().map_err(unreadable)?;
+        self.start_input(metadata.len());
+
+        // Pipes and character devices report a size of 0 but may still have content.
+        if !metadata.is_file() {
+            return self.scan_stream(file, path);
+        }
+        // Mapping an empty file fails, there is nothing to map anyway.
+        if metadata.len() == 0 {
+            return self.scan_data(&[]);
+        }
+
+        // SAFETY: the mapping is only read while scanning. A file truncated meanwhile
+        // by another process can still fault, the same trade-off yara-x makes.
+        match unsafe { Mmap::map(&file) } {
+            Ok(map) => self.scan_contents(&map, path),
+            Err(_) if metadata.len() > MAX_IN_MEMORY => self.scan_stream(file, path),
+            Err(_) => {
+                let mut input = Vec::with_capacity(metadata.len() as usize);
+                file.read_to_end(&mut input).map_err(unreadable)?;
+                self.scan_contents(&input, path)
+            }
+        }
+    }
+
+    /// Scans `reader` in `STREAM_CHUNK`-sized windows that overlap by `STREAM_OVERLAP`
+    /// bytes, so memory stays bounded whatever the input size.
+    ///
+    /// Strings longer than the overlap can be missed across a window boundary, and
+    /// conditions on absolute offsets or `filesize` only see the current window.
+    /// The hash is computed along the way and checked once the stream is read.
+    fn scan_stream<R: Read>(&mut self, reader: R, path: &Path) -> Result<ScanResult, ScanError> {
+        let unreadable = |source| ScanError::Unreadable {
+            path: path.to_path_buf(),
+            source,
+        };
+        let hashes = self.hashes.clone();
+        let mut hasher = hashes.as_deref().map(HashDatabase::hasher);
+        let mut reader = reader;
+        let mut buffer = Vec::with_capacity(STREAM_CHUNK);
+        let mut result = ScanResult::default();
+        // Offset of `buffer[0]` in the stream.
+        let mut base = 0u64;
+
+        loop {
+            let kept = buffer.len();
+            let read = (&mut reader)
+                .take((STREAM_CHUNK - buffer.len()) as u64)
+                .read_to_end(&mut buffer)
+                .map_err(unreadable)?;
+            if let Some(hasher) = &mut hasher {
+                hasher.update(&buffer[kept..]);
+            }
+            // Nothing new after a full window: its tail was already scanned.
+            if read == 0 && base > 0 {
+                break;
+            }
+
+            self.grow_input(base + buffer.len() as u64);
+            result.merge_window(self.scan_bytes(&buffer)?, base);
+
+            if buffer.len() < STREAM_CHUNK {
+                break;
+            }
+            let keep_from = buffer.len() - STREAM_OVERLAP;
+            buffer.drain(..keep_from);
+            base += keep_from as u64;
+        }
+
+        result.scanned_bytes = base + buffer.len() as u64;
+        result.hash_match = hasher.and_then(|h| h.finish());
+        Ok(result)
+    }
+}
+
+/// `timeout` once per started `TIMEOUT_SPAN` of `size`, at least once.
+fn budget_for(timeout: Duration, size: u64) -> Duration {
+    let spans = size.div_ceil(TIMEOUT_SPAN).max(1);
+    timeout.saturating_mul(u32::try_from(spans).unwrap_or(u32::MAX))
+}
+
+/// A reusable scanner for callers handling files one at a time, e.g. on file events.
+/// Creating one has a cost, keep it around rather than building one per file.
+///
+/// Only the per-file options apply: timeout, archives, hashes, filter and heuristics.
+pub struct FileScanner<'r>(TimedScanner<'r>);
+
+impl<'r> FileScanner<'r> {
+    pub fn new(rules: &'r Rules, options: &ScanOptions) -> Self {
+        Self(TimedScanner::new(rules, options))
+    }
+
+    pub fn scan_file<P: AsRef<Path>>(&mut self, path: P) -> Result<ScanResult, ScanError> {
+        self.0.scan_file(path.as_ref())
+    }
+
+    /// Scans a file that is already open, such as a descriptor handed over by
+    /// fanotify. `path` is only used in errors and to name archive members.
+    pub fn scan_open_file(&mut self, file: File, path: &Path) -> Result<ScanResult, ScanError> {
+        self.0.scan_open_file(file, path)
+    }
+}
+
+/// Scans `input` as raw bytes, archives are not unpacked.
+pub fn scan_bytes(rules: &Rules, input: &[u8]) -> Result<ScanResult, ScanError> {
+    let mut scanner = TimedScanner::new(rules, &ScanOptions::default());
+    scanner.start_input(input.len() as u64);
+    scanner.scan_bytes(input)
+}
+
+/// Scans a stream of unknown size with bounded memory, see `STREAM_CHUNK`.
+pub fn scan_reader<R: Read>(rules: &Rules, reader: R) -> Result<ScanResult, ScanError> {
+    TimedScanner::new(rules, &ScanOptions::default()).scan_stream(reader, Path::new("<stream>"))
+}
+
+/// Scans one file with the default options, unpacking archives.
+pub fn scan_file<P: AsRef<Path>>(rules: &Rules, path: P) -> Result<ScanResult, ScanError> {
+    TimedScanner::new(rules, &ScanOptions::default()).scan_file(path.as_ref())
+}
+
+/// Scans every file under `root` (or `root` itself if it is a file) on a pool of
+/// worker threads, each with its own `Scanner` over the shared `rules`.
+///
+/// Results come back in directory walk order whatever the thread count,
+/// entries that cannot be walked are reported as `ScanError::Unreadable`.
+/// Symlinks (unless followed) and special files are not scanned. Files skipped
+/// thanks to `options.cache` are reported with `ScanResult::cached` set.
+pub fn scan_path<P: AsRef<Path>>(
+    rules: &Rules,
+    root: P,
+    options: &ScanOptions,
+) -> Result<Vec<FileScan>, ScanError> {
+    let filter = PathFilter::new(options)?;
+    let cache = options.cache.as_deref();
+    // Metadata is taken when walking, it is what the cache entry is checked against.
+    let (job_tx, job_rx) = mpsc::sync_channel::<(usize, PathBuf, Option<Metadata>)>(64);
+    let job_rx = Mutex::new(job_rx);
+    let (done_tx, done_rx) = mpsc::channel::<(usize, FileScan)>();
+
+    thread::scope(|scope| {
+        for _ in 0..options.worker_count() {
+            let job_rx = &job_rx;
+            let done_tx = done_tx.clone();
+            scope.spawn(move || {
+                let mut scanner = TimedScanner::new(rules, options);
+                loop {
+                    // The lock only covers taking the next job, not the scan.
+                    let job = job_rx.lock().unwrap().recv();
+                    let Ok((index, path, metadata)) = job else {
+                        break;
+                    };
+                    let outcome = scanner.scan_file(&path);
+                    if let (Some(cache), Some(metadata)) = (cache, &metadata) {
+                        match &outcome {
+                            Ok(result) if result.is_unremarkable() => cache.mark_clean(metadata),
+                            _ => cache.forget(metadata),
+                        }
+                    }
+                    let _ = done_tx.send((index, FileScan { path, outcome }));
+                }
+            });
+        }
+
+        let walk = WalkDir::new(root)
+            .follow_links(options.follow_symlinks)
+            .same_file_system(options.one_file_system)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|e| filter.walks(e));
+
+        for (index, entry) in walk.enumerate() {
+            let entry = match entry {
+                Ok(entry) if entry.file_type().is_file() && filter.includes(entry.path()) => entry,
+                Ok(_) => continue,
+                Err(e) => {
+                    let path = e.path().map(Path::to_path_buf).unwrap_or_default();
+                    let outcome = Err(ScanError::Unreadable {
+                        path: path.clone(),
+                        source: e.into(),
+                    });
+                    let _ = done_tx.send((index, FileScan { path, outcome }));
+                    continue;
+                }
+            };
+
+            let metadata = entry.metadata().ok();
+            if let Some(limit) = options.max_file_size {
+                let size = metadata.as_ref().map_or(0, |m| m.len());
+                if size > limit {
+                    let path = entry.into_path();
+                    let outcome = Err(ScanError::TooLarge {
+                        path: path.clone(),
+                        size,
+                        limit,
+                    });
+                    let _ = done_tx.send((index, FileScan { path, outcome }));
+                    continue;
+                }
+            }
+            if let (Some(cache), Some(metadata)) = (cache, &metadata)
+                && cache.is_clean(metadata)
+            {
+                let outcome = Ok(ScanResult {
+                    cached: true,
+                    ..ScanResult::default()
+                });
+                let path = entry.into_path();
+                let _ = done_tx.send((index, FileScan { path, outcome }));
+                continue;
+            }
+            let _ = job_tx.send((index, entry.into_path(), metadata));
+        }
+        // Closing the queue lets the workers finish once it is drained.
+        drop(job_tx);
+        drop(done_tx);
+    });
+
+    if let Some(cache) = cache
+        && let Err(e) = cache.save()
+    {
+        log::warn!("Could not write scan cache: {e}");
+    }
+
+    let mut scans: Vec<(usize, FileScan)> = done_rx.into_iter().collect();
+    scans.sort_by_key(|(index, _)| *index);
+    Ok(scans.into_iter().map(|(_, scan)| scan).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeout_grows_with_the_input() {
+        let timeout = Duration::from_secs(60);
+        assert_eq!(budget_for(timeout, 0), timeout);
+        assert_eq!(budget_for(timeout, TIMEOUT_SPAN), timeout);
+        assert_eq!(budget_for(timeout, TIMEOUT_SPAN + 1), timeout * 2);
+        // A 4 GiB image gets 16 times the timeout.
+        assert_eq!(budget_for(timeout, 16 * TIMEOUT_SPAN), timeout * 16);
+    }
+}
